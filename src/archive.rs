@@ -0,0 +1,432 @@
+//! Packing the tileset into a [3TZ](https://github.com/erikdahlstrom/3tz-specification)
+//! archive.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+/// The name of the index entry, which must be the last entry of the archive.
+const INDEX_NAME: &str = "@3dtilesIndex1@";
+/// The directories of the output that are archived, in addition to the tileset files.
+const ARCHIVED_DIRS: [&str; 2] = ["tiles", "subtrees"];
+/// The sizes and offsets that do not fit into the fields of a zip without Zip64.
+const ZIP64_LIMIT: u64 = 0xFFFFFFFF;
+/// The number of entries that does not fit into the fields of a zip without Zip64.
+const ZIP64_LIMIT_ENTRIES: usize = 0xFFFF;
+/// The DOS date of 1980-01-01, because the entries do not store a modification time.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// The archive format of the tileset.
+#[derive(Debug, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum ArchiveFormat {
+    /// A zip file with an index of its entries, see
+    /// <https://github.com/erikdahlstrom/3tz-specification>.
+    #[value(name = "3tz")]
+    ThreeTz,
+}
+
+/// The files of the tileset in `dir` that are archived: the tileset.json files (including
+/// the external tilesets) and the files in the tiles/ and subtrees/ directories.
+/// The paths are relative to `dir`, so that the content URIs resolve inside the archive.
+pub fn tileset_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && name.starts_with("tileset") && name.ends_with(".json") {
+            files.push(PathBuf::from(path.file_name().unwrap()));
+        }
+    }
+    for archived_dir in ARCHIVED_DIRS {
+        for entry in WalkDir::new(dir.join(archived_dir)).into_iter() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                // The directory does not exist, eg. subtrees/ with explicit tiling
+                Err(e) if e.depth() == 0 => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if entry.file_type().is_file() {
+                files.push(entry.path().strip_prefix(dir).unwrap().to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Write the `files` (relative to `dir`) into a 3TZ archive at `path_archive`.
+/// The entries are stored without compression, because the glTF content is usually
+/// compressed already.
+pub fn write_3tz(dir: &Path, files: &[PathBuf], path_archive: &Path) -> io::Result<()> {
+    let mut writer = ZipWriter::new(BufWriter::new(File::create(path_archive)?));
+    let mut index: Vec<([u8; 16], u64)> = Vec::with_capacity(files.len());
+    for file in files {
+        let name = entry_name(file);
+        let data = fs::read(dir.join(file))?;
+        let offset = writer.add_entry(&name, &data)?;
+        index.push((md5(name.as_bytes()), offset));
+    }
+    sort_index(&mut index);
+    let mut index_data: Vec<u8> = Vec::with_capacity(index.len() * 24);
+    for (hash, offset) in index.iter() {
+        index_data.extend_from_slice(hash);
+        index_data.extend_from_slice(&offset.to_le_bytes());
+    }
+    writer.add_entry(INDEX_NAME, &index_data)?;
+    writer.finish()?.flush()
+}
+
+/// Remove the archived `files` (relative to `dir`) and the directories that contained
+/// them.
+pub fn remove_tileset_files(dir: &Path, files: &[PathBuf]) -> io::Result<()> {
+    for file in files {
+        if file.parent() == Some(Path::new("")) {
+            fs::remove_file(dir.join(file))?;
+        }
+    }
+    for archived_dir in ARCHIVED_DIRS {
+        let path = dir.join(archived_dir);
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// The name of the entry of a relative path, with '/' separators.
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The index is sorted by the MD5 hashes, which are compared as two little-endian 64-bit
+/// unsigned integers, the first 8 bytes first.
+fn sort_index(index: &mut [([u8; 16], u64)]) {
+    index.sort_by_key(|(hash, _)| {
+        (
+            u64::from_le_bytes(hash[..8].try_into().unwrap()),
+            u64::from_le_bytes(hash[8..].try_into().unwrap()),
+        )
+    });
+}
+
+/// An entry in the central directory of a zip file.
+struct CentralEntry {
+    name: String,
+    crc32: u32,
+    size: u64,
+    offset: u64,
+}
+
+/// A minimal zip writer that stores the entries without compression, with Zip64 when the
+/// archive is too large for the plain zip fields.
+struct ZipWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Write an entry and return the offset of its local file header.
+    fn add_entry(&mut self, name: &str, data: &[u8]) -> io::Result<u64> {
+        let offset = self.offset;
+        let size = data.len() as u64;
+        let crc32 = crc32(data);
+        let is_zip64 = size >= ZIP64_LIMIT;
+        let mut header: Vec<u8> = Vec::with_capacity(30 + name.len() + 20);
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&version_needed(is_zip64).to_le_bytes());
+        // The names are UTF-8
+        header.extend_from_slice(&0x0800u16.to_le_bytes());
+        // Stored without compression
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&crc32.to_le_bytes());
+        let size_field = size.min(ZIP64_LIMIT) as u32;
+        header.extend_from_slice(&size_field.to_le_bytes());
+        header.extend_from_slice(&size_field.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(if is_zip64 { 20u16 } else { 0u16 }).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        if is_zip64 {
+            header.extend_from_slice(&zip64_extra(&[size, size]));
+        }
+        self.write(&header)?;
+        self.write(data)?;
+        self.entries.push(CentralEntry {
+            name: name.to_string(),
+            crc32,
+            size,
+            offset,
+        });
+        Ok(offset)
+    }
+
+    /// Write the central directory and return the inner writer.
+    fn finish(mut self) -> io::Result<W> {
+        let cd_offset = self.offset;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in entries.iter() {
+            let mut zip64_fields: Vec<u64> = Vec::new();
+            if entry.size >= ZIP64_LIMIT {
+                zip64_fields.extend([entry.size, entry.size]);
+            }
+            if entry.offset >= ZIP64_LIMIT {
+                zip64_fields.push(entry.offset);
+            }
+            let is_zip64 = !zip64_fields.is_empty();
+            let extra = if is_zip64 {
+                zip64_extra(&zip64_fields)
+            } else {
+                Vec::new()
+            };
+            let mut header: Vec<u8> = Vec::with_capacity(46 + entry.name.len() + extra.len());
+            header.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            // Version made by
+            header.extend_from_slice(&45u16.to_le_bytes());
+            header.extend_from_slice(&version_needed(is_zip64).to_le_bytes());
+            header.extend_from_slice(&0x0800u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&DOS_DATE.to_le_bytes());
+            header.extend_from_slice(&entry.crc32.to_le_bytes());
+            let size_field = entry.size.min(ZIP64_LIMIT) as u32;
+            header.extend_from_slice(&size_field.to_le_bytes());
+            header.extend_from_slice(&size_field.to_le_bytes());
+            header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            // File comment length, disk number, internal and external attributes
+            header.extend_from_slice(&[0u8; 10]);
+            header.extend_from_slice(&(entry.offset.min(ZIP64_LIMIT) as u32).to_le_bytes());
+            header.extend_from_slice(entry.name.as_bytes());
+            header.extend_from_slice(&extra);
+            self.write(&header)?;
+        }
+        let cd_size = self.offset - cd_offset;
+        let nr_entries = entries.len();
+        if nr_entries >= ZIP64_LIMIT_ENTRIES || cd_size >= ZIP64_LIMIT || cd_offset >= ZIP64_LIMIT {
+            let eocd64_offset = self.offset;
+            let mut eocd64: Vec<u8> = Vec::with_capacity(56 + 20);
+            eocd64.extend_from_slice(&0x06064b50u32.to_le_bytes());
+            // The size of the rest of the record
+            eocd64.extend_from_slice(&44u64.to_le_bytes());
+            eocd64.extend_from_slice(&45u16.to_le_bytes());
+            eocd64.extend_from_slice(&45u16.to_le_bytes());
+            eocd64.extend_from_slice(&[0u8; 8]);
+            eocd64.extend_from_slice(&(nr_entries as u64).to_le_bytes());
+            eocd64.extend_from_slice(&(nr_entries as u64).to_le_bytes());
+            eocd64.extend_from_slice(&cd_size.to_le_bytes());
+            eocd64.extend_from_slice(&cd_offset.to_le_bytes());
+            // The Zip64 end of central directory locator
+            eocd64.extend_from_slice(&0x07064b50u32.to_le_bytes());
+            eocd64.extend_from_slice(&0u32.to_le_bytes());
+            eocd64.extend_from_slice(&eocd64_offset.to_le_bytes());
+            eocd64.extend_from_slice(&1u32.to_le_bytes());
+            self.write(&eocd64)?;
+        }
+        let nr_entries_field = nr_entries.min(ZIP64_LIMIT_ENTRIES) as u16;
+        let mut eocd: Vec<u8> = Vec::with_capacity(22);
+        eocd.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        eocd.extend_from_slice(&[0u8; 4]);
+        eocd.extend_from_slice(&nr_entries_field.to_le_bytes());
+        eocd.extend_from_slice(&nr_entries_field.to_le_bytes());
+        eocd.extend_from_slice(&(cd_size.min(ZIP64_LIMIT) as u32).to_le_bytes());
+        eocd.extend_from_slice(&(cd_offset.min(ZIP64_LIMIT) as u32).to_le_bytes());
+        eocd.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&eocd)?;
+        Ok(self.writer)
+    }
+}
+
+fn version_needed(is_zip64: bool) -> u16 {
+    if is_zip64 {
+        45
+    } else {
+        20
+    }
+}
+
+/// The Zip64 extended information extra field with the `fields`, in the order of the
+/// specification (uncompressed size, compressed size, offset).
+fn zip64_extra(fields: &[u64]) -> Vec<u8> {
+    let mut extra: Vec<u8> = Vec::with_capacity(4 + fields.len() * 8);
+    extra.extend_from_slice(&0x0001u16.to_le_bytes());
+    extra.extend_from_slice(&((fields.len() * 8) as u16).to_le_bytes());
+    for field in fields {
+        extra.extend_from_slice(&field.to_le_bytes());
+    }
+    extra
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC-32 checksum of the zip entries.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// The MD5 hash of the entry names in the index.
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    const K: [u32; 64] = [
+        0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613,
+        0xfd469501, 0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193,
+        0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d,
+        0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+        0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122,
+        0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa,
+        0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244,
+        0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+        0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+        0xeb86d391,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in message.chunks_exact(64) {
+        let m: Vec<u32> = chunk
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(K[i])
+                .wrapping_add(m[g])
+                .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+    let mut hash = [0u8; 16];
+    for (i, word) in state.iter().enumerate() {
+        hash[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex(&md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+    }
+
+    #[test]
+    fn test_write_3tz() {
+        let dir = std::env::temp_dir().join("tyler_test_write_3tz");
+        fs::create_dir_all(dir.join("tiles").join("0").join("0")).unwrap();
+        fs::write(dir.join("tileset.json"), "{}").unwrap();
+        fs::write(dir.join("tyler_run.json"), "{}").unwrap();
+        fs::write(dir.join("tiles").join("0").join("0").join("0.glb"), "glb").unwrap();
+        let files = tileset_files(&dir).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("tiles/0/0/0.glb"),
+                PathBuf::from("tileset.json")
+            ]
+        );
+        let path_archive = dir.with_extension("3tz");
+        write_3tz(&dir, &files, &path_archive).unwrap();
+        let archive = fs::read(&path_archive).unwrap();
+        // The index is the last entry, right before the central directory
+        let eocd = &archive[archive.len() - 22..];
+        assert_eq!(u16::from_le_bytes([eocd[10], eocd[11]]), 3);
+        let cd_offset = u32::from_le_bytes(eocd[16..20].try_into().unwrap()) as usize;
+        let index = &archive[cd_offset - 48..cd_offset];
+        for entry in index.chunks_exact(24) {
+            let offset = u64::from_le_bytes(entry[16..].try_into().unwrap()) as usize;
+            let name_len = u16::from_le_bytes([archive[offset + 26], archive[offset + 27]]);
+            let name = &archive[offset + 30..offset + 30 + name_len as usize];
+            assert_eq!(entry[..16], md5(name));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&path_archive).unwrap();
+    }
+}
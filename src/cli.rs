@@ -11,38 +11,131 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 
+use crate::parser::CityObjectType;
+
+// The options that are required for a run are not required with a subcommand, because
+// `merge` and `print-schema` do not use them. They are checked by [crate::run] for
+// `validate`.
 #[derive(Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_negates_reqs = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Read the options from a TOML file. The keys are the long option names with
+    /// underscores, eg. 'grid_cellsize = 500' or 'exe_geof = "/usr/bin/geof"'. Flags take a
+    /// boolean value and options that can be specified multiple times take an array.
+    /// The precedence of the values is: command line option > configuration file >
+    /// default value. Unknown keys are an error.
+    #[arg(long, value_parser = existing_canonical_path)]
+    pub config: Option<PathBuf>,
     /// Main CityJSON file (.city.json), containing the coordinate reference system and
     /// transformation properties.
-    #[arg(short, long, value_parser = existing_canonical_path)]
-    pub metadata: PathBuf,
+    /// Required, unless the features are a CityJSONSeq stream and the first line of the
+    /// stream is the CityJSON object.
+    /// It can also be an HTTP(S) URL, then the file is downloaded into the --cache-dir.
+    #[arg(short, long, value_parser = metadata_path)]
+    pub metadata: Option<PathBuf>,
+    /// The CRS of the input, eg. 'EPSG:7415' or 'urn:ogc:def:crs:EPSG::7415'. By default,
+    /// the CRS is the 'referenceSystem' of the --metadata. Set it if the metadata does not
+    /// have a referenceSystem, or if it is wrong.
+    #[arg(long)]
+    pub source_crs: Option<crate::parser::Crs>,
     /// Directory of CityJSONFeatures (.city.jsonl). The directory and all its
     /// subdirectories are searched recursively for feature files.
-    #[arg(short, long, value_parser = existing_canonical_path)]
-    pub features: PathBuf,
+    /// It can also be a text file (.txt) that lists the feature files, one per line. The
+    /// relative paths in the list are resolved against the directory of the list file.
+    /// It can also be a CityJSONSeq file (.jsonl, eg. .city.jsonl) with one feature per
+    /// line, or '-' to read a CityJSONSeq stream from stdin. The features of the stream are
+    /// written to the 'features' directory in the output directory, because the tile
+    /// conversion reads the features from files.
+    /// It can also be an HTTP(S) URL of a feature list (.txt) or a CityJSONSeq file
+    /// (.jsonl), then the files are downloaded into the --cache-dir. The relative URLs in
+    /// a feature list are resolved against the URL of the list.
+    #[arg(short, long, required = true, value_parser = features_path)]
+    pub features: Option<PathBuf>,
+    /// The file extensions of the feature files in the --features directory, as a
+    /// comma-separated list (eg. 'jsonl,cjf'). The gzipped files with these extensions
+    /// (eg. '.cjf.gz') are also used.
+    #[arg(long, value_delimiter = ',', default_value = "jsonl")]
+    pub features_extension: Option<Vec<String>>,
     /// Directory for the output.
-    #[arg(short, long)]
-    pub output: PathBuf,
+    #[arg(short, long, required = true)]
+    pub output: Option<PathBuf>,
+    /// Directory for caching the inputs that are downloaded from HTTP(S) URLs. The cached
+    /// files are reused in the next runs. Defaults to '$XDG_CACHE_HOME/tyler' or
+    /// '$HOME/.cache/tyler'.
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+    /// Path to the curl executable for downloading the inputs from HTTP(S) URLs. Defaults
+    /// to 'curl' in the filesystem PATH.
+    #[arg(long, value_parser = existing_path)]
+    pub exe_curl: Option<PathBuf>,
     // /// Output format.
     // #[arg(long, value_enum)]
     // pub format: crate::Formats,
     /// The CityObject type to use for the 3D Tiles
     /// (https://www.cityjson.org/specs/1.1.3/#the-different-city-objects).
-    /// You can specify it multiple times.
-    #[arg(long, value_enum)]
+    /// You can specify it multiple times or give a comma-separated list, and then the
+    /// features that have any of the types are used. By default, all types are used.
+    #[arg(long, value_enum, value_delimiter = ',')]
     pub object_type: Option<Vec<crate::parser::CityObjectType>>,
+    /// Only use the features that have a CityObject with an attribute that matches the
+    /// filter. Format: <attribute_name><operator><value>, where the operator is one of
+    /// '=', '!=', '>', '<', eg. 'construction_year>2000'. '>' and '<' compare numbers.
+    /// You can specify it multiple times, and then a CityObject must match all filters.
+    /// The excluded features are not counted for the quadtree.
+    #[arg(long)]
+    pub feature_filter: Option<Vec<crate::parser::FeatureFilter>>,
     /// The CityObject attribute name and value type to include as feature attribute when the
     /// output is 3D Tiles. Format: <attribute_name>:<attribute_type> eg: 'name1:string'.
     /// Possible value types are, 'bool', 'int', 'float', 'string'.
     /// You can specify it multiple times.
     #[arg(long)]
     pub object_attribute: Option<Vec<String>>,
+    /// Drop the features that are exact duplicates (same CityObject IDs and geometry) of
+    /// another feature, eg. when the input directory contains the exports of overlapping
+    /// regions. Hashing the geometry of each feature makes the indexing slower.
+    #[arg(long)]
+    pub dedup_features: bool,
+    /// Exit with an error if there are more feature files than this, eg. to protect an
+    /// automated pipeline from a wrong input path. The feature files are only counted, not
+    /// parsed, before the check.
+    #[arg(long, visible_alias = "feature-count-limit")]
+    pub max_features: Option<usize>,
+    /// Only tile a random subset of the features, for a quick preview of a dataset. It is
+    /// a fraction of the features with a decimal point (eg. '0.1' or '1.0'), or a number of
+    /// features (eg. '1000'). The extent of the tileset is still computed from all the
+    /// features.
+    #[arg(long)]
+    pub sample: Option<crate::parser::Sample>,
+    /// The seed of the random selection of the --sample. The same seed selects the same
+    /// features from the same input.
+    #[arg(long, requires = "sample", default_value = "0")]
+    pub seed: Option<u64>,
+    /// Skip the features that have no vertices. Without this option, the run fails if
+    /// there are such features, and they are listed. A metadata 'transform' that is not
+    /// finite always fails the run.
+    #[arg(long)]
+    pub skip_invalid: bool,
+    /// Exit with an error if there are features that are far outside of the
+    /// 'geographicalExtent' of the metadata, which usually means that they are in a
+    /// different CRS than the metadata. The paths of some of these features are reported.
+    #[arg(long)]
+    pub verify_metadata: bool,
+    /// How the features are assigned to the tiles. With 'centroid', each feature is only
+    /// in the tile that contains its centroid, so that the features that span several
+    /// grid cells are not converted several times. With 'all-cells', the features are in
+    /// all the tiles that they intersect, except buildings, which are in one tile.
+    #[arg(long, value_enum, default_value = "all-cells")]
+    pub feature_assignment: Option<crate::parser::FeatureAssignment>,
     /// The CityObject attribute
     /// The metadata class to assign to the property table when the output is
     /// 3D Tiles (https://github.com/CesiumGS/glTF/tree/3d-tiles-next/extensions/2.0/Vendor/EXT_structural_metadata#class).
@@ -52,42 +145,415 @@ pub struct Cli {
     /// By default, explicit tiling is created for the 3D Tiles output.
     #[arg(long = "3dtiles-implicit")]
     pub cesium3dtiles_implicit: bool,
+    /// The tiling scheme of the 3D Tiles output. 'implicit' is the same as
+    /// '--3dtiles-implicit'.
+    #[arg(long, value_enum, default_value = "explicit")]
+    pub tiling: Option<crate::formats::cesium3dtiles::Tiling>,
     /// Generate and write the Tileset only, without exporting the glTF tiles, when the output format is 3D Tiles (https://docs.ogc.org/cs/22-025r4/22-025r4.html#toc31).
     #[arg(long = "3dtiles-tileset-only")]
     pub cesium3dtiles_tileset_only: bool,
+    /// The number of tiles that are converted in parallel. By default, there is one
+    /// conversion per logical CPU, which is also used when the value is 0.
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Do not show the progress bar of the tile conversion. The progress bar is not shown
+    /// either if the standard output is not a terminal or the log level is debug.
+    #[arg(long)]
+    pub no_progress: bool,
+    /// The format of the log records. With 'json', each record is a JSON object on a
+    /// separate line, eg. for a log aggregator. The log level is still set with RUST_LOG.
+    #[arg(long, value_enum, default_value = "text")]
+    pub log_format: Option<crate::logging::LogFormat>,
+    /// Skip the conversion of the tiles whose output file already exists and is not empty,
+    /// eg. to continue a run that was interrupted. The existing tiles are not checked, so
+    /// the other options should be the same as in the interrupted run.
+    #[arg(long)]
+    pub resume: bool,
+    /// Remove the 'tiles' and 'inputs' directories and the 'tileset.json' of a previous run
+    /// from the output directory. Without this option, tyler refuses to convert the tiles
+    /// if the 'tiles' directory is not empty, so that the new tiles are not mixed with
+    /// stale ones.
+    #[arg(long, conflicts_with = "resume")]
+    pub force: bool,
+    /// Only convert the tile with this ID (eg. '10/525/340'), overwriting its output, eg. to
+    /// debug a single tile of a previous run. The 'tileset.json' is left untouched, unless
+    /// --only-tile-tileset is set.
+    #[arg(
+        long,
+        value_name = "TILEID",
+        conflicts_with_all = ["resume", "force", "cesium3dtiles_tileset_only"]
+    )]
+    pub only_tile: Option<String>,
+    /// Also regenerate the 'tileset.json' of all the tiles when converting the --only-tile.
+    #[arg(long, requires = "only_tile")]
+    pub only_tile_tileset: bool,
+    /// Build the quadtree and write the tileset, but do not convert the tiles. The ID,
+    /// number of items and bbox of each tile that would be converted is logged, and the
+    /// 'tiles' and 'inputs' directories are not created.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Index the features, write the tileset and the '.input' files of the tiles, but do
+    /// not convert the tiles. The index is written to 'tyler_index.json' in the 'inputs'
+    /// directory (or --tmp-dir), which is kept, so that the tiles can be converted with
+    /// --convert-only in a separate run, eg. on a different machine.
+    #[arg(
+        long,
+        conflicts_with_all = ["convert_only", "dry_run", "cesium3dtiles_tileset_only", "only_tile"]
+    )]
+    pub index_only: bool,
+    /// Convert the tiles from the index of a previous --index-only run, instead of
+    /// indexing the features. The other options should be the same as in the --index-only
+    /// run. The tileset is written again from the index, without the tiles that failed to
+    /// convert. The 'inputs' directory is kept, so that the run can be continued with
+    /// --resume.
+    #[arg(long)]
+    pub convert_only: bool,
+    /// Directory for the intermediate files of the conversion, eg. the '.input' files with
+    /// the feature paths of the tiles. Defaults to the 'inputs' directory in the output
+    /// directory. The directory is removed after the run, also if the run fails. If it
+    /// existed before the run, only the files that tyler created in it are removed.
+    #[arg(long)]
+    pub tmp_dir: Option<PathBuf>,
+    /// Keep the directory of the intermediate files after the run, for debugging. The
+    /// directory is also kept if the log level is 'debug'.
+    #[arg(long)]
+    pub keep_tmp: bool,
+    /// Give each tile with content its own transform, that places the tile content in
+    /// EPSG:4978. The converter then writes the vertices relative to the center of the
+    /// tile content, which avoids the loss of precision of large coordinates.
+    /// Only supported by '--converter native', because geof does not write the content
+    /// relative to the tile origin, and the run fails before the indexing with geof. Cannot be used with implicit tiling, because implicit tiles do
+    /// not have a transform.
+    #[arg(
+        long = "3dtiles-local-transforms",
+        conflicts_with = "cesium3dtiles_implicit"
+    )]
+    pub cesium3dtiles_local_transforms: bool,
+    /// The type of the tile bounding volumes. A 'region' follows the curvature of the
+    /// earth. A 'box' is an axis-aligned box in EPSG:4978 that contains the region, for
+    /// viewers that do not support regions. Cannot be used with implicit tiling and
+    /// --3dtiles-local-transforms.
+    #[arg(long, value_enum, default_value = "region")]
+    pub bounding_volume: Option<crate::formats::cesium3dtiles::BoundingVolumeType>,
+    /// The refinement of the tiles. With 'replace', the content of the children replaces
+    /// the content of the parent when the children are rendered. With 'add', the content
+    /// of the children is rendered in addition to the content of the parent.
+    #[arg(long, value_enum, default_value = "replace")]
+    pub refine: Option<crate::formats::cesium3dtiles::Refinement>,
+    /// Only write the first N levels of the tile hierarchy into the tileset.json, and
+    /// write the tiles below into external tilesets ('tileset_<level>_<x>_<y>.json') that
+    /// are loaded on demand. The external tilesets are split in the same way.
+    /// Cannot be used with implicit tiling.
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with = "cesium3dtiles_implicit"
+    )]
+    pub inline_levels: Option<u16>,
+    /// Prepend this prefix to the file name of each tile, eg. 'buildings_' gives
+    /// 'tiles/10/4/buildings_7.glb'. Useful when several tilesets are written into the same
+    /// directory.
+    #[arg(long)]
+    pub tile_name_prefix: Option<String>,
+    /// Prepend this prefix to the content URIs of the tiles in the tileset.json, eg.
+    /// 'https://cdn.example.com/city/v3' gives
+    /// 'https://cdn.example.com/city/v3/tiles/10/4/7.glb', when the tiles are served from
+    /// a different location than the tileset.json. The tiles are still written into the
+    /// tiles/ directory of the output.
+    #[arg(long)]
+    pub content_uri_prefix: Option<String>,
+    /// Pack the tileset into an archive after the conversion. With '3tz', the tileset.json,
+    /// the tiles/ and the subtrees/ are written into a '<output>.3tz' file next to the
+    /// output directory.
+    #[arg(long, value_enum)]
+    pub archive: Option<crate::archive::ArchiveFormat>,
+    /// Remove the files that were written into the --archive from the output directory.
+    #[arg(long, requires = "archive")]
+    pub archive_remove_files: bool,
+    /// The version of the tileset, which is written to the 'asset.tilesetVersion' of the
+    /// tileset.json.
+    #[arg(long)]
+    pub tileset_version: Option<String>,
+    /// The application that generated the tileset, which is written to the
+    /// 'asset.generator' of the tileset.json. Defaults to 'tyler <version>'.
+    #[arg(long)]
+    pub tileset_generator: Option<String>,
+    /// Add the property KEY with the string VALUE to the 'extras' of the tileset.json, eg.
+    /// 'project=rotterdam'. Can be given multiple times.
+    #[arg(long, value_parser = key_value)]
+    pub tileset_extra: Option<Vec<(String, String)>>,
+    /// The attribution of the source data, as HTML, which is written to the
+    /// 'asset.extras.cesium.credits' of the tileset.json, eg. '© Kadaster'. Can be given
+    /// multiple times. Defaults to the 'title' and the 'pointOfContact' of the --metadata.
+    #[arg(long)]
+    pub attribution: Option<Vec<String>>,
+    /// Write a JSON file with the bounding box and a suggested camera position for each
+    /// top-level tile, eg. for taking screenshots of the tiles for QA.
+    #[arg(long)]
+    pub qa_camera_manifest: Option<PathBuf>,
+    /// Write the quadtree structure to a JSON file, with the bbox, level, number of items
+    /// and children of each node, and the feature paths of each leaf. The file is
+    /// independent of the 3D Tiles tileset.
+    #[arg(long)]
+    pub export_quadtree: Option<PathBuf>,
+    /// Write the leaves of the quadtree as polygons to 'quadtree.geojson' in the output
+    /// directory, in the CRS of the input.
+    #[arg(long)]
+    pub quadtree_export: bool,
+    /// Write the `crs.json` and `crs.prj` files next to the tileset, that describe the CRS
+    /// of the input data (as WKT), the CityJSON transform and the CRS of the output.
+    #[arg(long)]
+    pub output_crs_info: bool,
+    /// Write a prefetch priority between 0 and 1 into the `extras.priority` of each tile,
+    /// computed from the number of features in the tile and its distance to the center of
+    /// the tileset. Higher values mean that the tile should be requested earlier.
+    #[arg(long)]
+    pub prefetch_hints: bool,
+    /// Check that the content files of the tileset exist in the output directory and that
+    /// they are not empty, and exit with an error if any of them is missing. Cannot be used
+    /// with implicit tiling, because the content URIs of an implicit tileset are templates.
+    #[arg(long, conflicts_with = "cesium3dtiles_implicit")]
+    pub validate_output: bool,
     /// Set the geometric error (see 3D Tiles specification) on the parent nodes of leafs. This controls at what
     /// camera distance leaf nodes become visible. Higher values make content visible earlier when zooming in.
     #[arg(long, short = 'e', default_value = "12")]
     pub geometric_error_above_leaf: Option<f64>,
+    /// Compute the geometric error of the parent tiles from their size, as the diagonal of
+    /// the tile multiplied by this factor, instead of from --geometric-error-above-leaf.
+    /// The geometric error of the tileset is the diagonal of the whole dataset. Must be
+    /// larger than 0.
+    #[arg(long)]
+    pub geometric_error_factor: Option<f64>,
     /// Set the 2D cell size for the grid that is used for constructing the quadtree. In input units (eg. meters).
+    /// Two comma-separated values set a different cell size along the x and y axes, eg.
+    /// '500,100' for a dataset that is much wider than tall. It cannot be used with
+    /// implicit tiling.
+    /// With 'auto', the cell size is computed from the extent and the number of the
+    /// features, so that a cell has --grid-features-per-cell features on average.
     #[arg(long, default_value = "250")]
-    pub grid_cellsize: Option<u16>,
+    pub grid_cellsize: Option<GridCellsize>,
+    /// The average number of features in a grid cell that is targeted by
+    /// '--grid-cellsize auto'.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), default_value = "10")]
+    pub grid_features_per_cell: Option<u32>,
     /// Limit the minimum z coordinate for the bounding box that is computed from the
     /// features. Useful if the features contain errors with extremely small z
     /// coordinates. In input units (eg. meters).
+    /// If not set, the minimum z coordinate of the features is used.
     #[arg(long)]
     pub grid_minz: Option<i32>,
     /// Limit the maximum z coordinate for the bounding box that is computed from the
     /// features. Useful if the features contain errors with extremely large z
     /// coordinates. In input units (eg. meters).
+    /// If not set, the maximum z coordinate of the features is used.
     #[arg(long)]
     pub grid_maxz: Option<i32>,
+    /// Create the grid over this 2D extent ('minx,miny,maxx,maxy'), instead of the
+    /// extent that is computed from the features, eg. to exclude outlier features. The
+    /// features that are completely outside of the extent are dropped. In the CRS of the
+    /// input.
+    #[arg(long, value_parser = extent)]
+    pub extent: Option<[f64; 4]>,
+    /// Convert only the tiles that intersect this 2D box ('minx,miny,maxx,maxy'). Unlike
+    /// --extent, the grid and the quadtree are still built from all the features, so the
+    /// tiles are the same as in a full run. The leaf tiles that are partially outside
+    /// of the box are converted completely. In the CRS of the input.
+    #[arg(long, value_parser = extent)]
+    pub tile_bbox: Option<[f64; 4]>,
+    /// What to do with the leaf tiles without features. With 'skip', the empty tiles are
+    /// kept in the tileset until the tileset is pruned after the conversion. With 'prune',
+    /// the empty tiles, and the parents that have no tiles left, are removed before the
+    /// conversion, so they are not converted and they are also left out with
+    /// --3dtiles-tileset-only.
+    #[arg(long, value_enum, default_value = "skip")]
+    pub empty_tiles: Option<crate::EmptyTiles>,
     /// Export the grid and the feature centroids in to .tsv files in the working
-    /// directory. Used for debugging.
+    /// directory, and the grid in the --grid-export-format into the output directory. Used
+    /// for debugging.
     #[arg(long)]
     pub grid_export: bool,
-    /// The maximum number of vertices in a leaf of the quadtree.
+    /// The format of the grid that is written with --grid-export, as 'grid.geojson' and/or
+    /// 'grid.csv' in the output directory.
+    #[arg(long, value_enum, default_value = "geojson")]
+    pub grid_export_format: Option<crate::spatial_structs::GridExportFormat>,
+    /// The maximum number of items in a leaf of the quadtree. The type of the items is set
+    /// with --qtree-capacity-type.
     #[arg(long, default_value = "42000")]
     pub qtree_capacity: Option<usize>,
+    /// The maximum depth of the quadtree (or octree). The nodes at this depth are not
+    /// subdivided further, even if they have more items than --qtree-capacity, eg. for
+    /// inputs with many duplicate features. By default, the depth is not limited.
+    #[arg(long)]
+    pub qtree_max_depth: Option<u16>,
+    /// Where the quadtree nodes are split. With 'center', the nodes are split into four
+    /// equal squares. With 'median', a node is split at the weighted median of its items
+    /// along the x and y-axis, which gives fewer leaves if the features are clustered, but
+    /// the tiles are rectangles of different sizes. 'median' cannot be used with implicit
+    /// tiling.
+    #[arg(long, value_enum, default_value = "center")]
+    pub qtree_split: Option<crate::spatial_structs::QuadTreeSplit>,
+    /// The items to count for the quadtree leaf capacity. With 'bytes', the size of a leaf is
+    /// estimated from the size of its feature files, which predicts the size of the tile
+    /// content better than the number of vertices.
+    /// With 'objects-and-vertices', a leaf can have at most --qtree-capacity vertices and
+    /// --qtree-capacity-objects features.
+    #[arg(long, value_enum, default_value = "vertices")]
+    pub qtree_capacity_type: Option<crate::spatial_structs::QuadTreeCriteria>,
+    /// The maximum number of features in a leaf of the quadtree, with
+    /// --qtree-capacity-type=objects-and-vertices.
+    #[arg(long)]
+    pub qtree_capacity_objects: Option<usize>,
+    /// Search the --qtree-capacity at which the quadtree has about this number of leaves
+    /// with features, instead of using the --qtree-capacity. The --qtree-capacity-type is
+    /// kept. Each trial is logged, and if the target cannot be reached within the
+    /// --target-tiles-tolerance, the capacity of the closest trial is used.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub target_tiles: Option<u64>,
+    /// The allowed deviation from the --target-tiles, as a fraction of the target (eg.
+    /// '0.05' for 5%).
+    #[arg(long, requires = "target_tiles", default_value = "0.05")]
+    pub target_tiles_tolerance: Option<f64>,
+    /// The spatial index that is used for creating the tiles. The octree also splits the
+    /// tiles along the z-axis, which is useful if many objects are stacked (eg.
+    /// underground and above-ground structures). The octree uses the same capacity as the
+    /// quadtree, and it cannot be used with implicit tiling, --dem, --prefetch-hints,
+    /// --export-quadtree and --quadtree-export.
+    #[arg(long, value_enum, default_value = "quadtree")]
+    pub spatial_index: Option<crate::spatial_structs::SpatialIndex>,
     /// Path to the geoflow executable for clipping and exporting the gltf files.
     #[arg(long, value_parser = existing_path)]
     pub exe_geof: Option<PathBuf>,
+    /// The converter that writes the glTF content of the tiles. 'geof' runs the geof
+    /// flowchart per tile. 'native' writes the glTF directly, without geof, but it does
+    /// not clip the features to the tile, simplify the LoD tiles, compress the content or
+    /// write the feature metadata.
+    #[arg(long, value_enum, default_value = "geof")]
+    pub converter: Option<crate::Converter>,
+    /// The 'resources' directory with the geof flowcharts. If not set, it is the
+    /// TYLER_RESOURCES_DIR environment variable, or the 'resources' directory next to the
+    /// executable or in '../share/tyler' relative to the executable, or the 'resources' of
+    /// the source tree that tyler was built from.
+    #[arg(long, value_parser = existing_path)]
+    pub resources_dir: Option<PathBuf>,
+    /// Raster Digital Elevation Model (eg. GeoTIFF) for setting the bottom of the tile
+    /// bounding volumes to the ground elevation. For each tile, the DEM is sampled at the
+    /// center and corners of the tile and the lowest value is used. Without a DEM, the
+    /// bottom is the lowest point of the tile content (leaves) or the grid (parents).
+    /// The DEM is read with gdallocationinfo (see --exe-gdallocationinfo).
+    #[arg(long, value_parser = existing_canonical_path)]
+    pub dem: Option<PathBuf>,
+    /// Path to the gdallocationinfo executable for sampling the --dem. Defaults to
+    /// 'gdallocationinfo' in the filesystem PATH.
+    #[arg(long, value_parser = existing_path)]
+    pub exe_gdallocationinfo: Option<PathBuf>,
     /// Use mesh simplification to reduce the number of vertices per object by this fraction. Value should be a float between 0.0 (100% reduction) and 1.0 (do not use simplification). Ignored for building object types.
     #[arg(long, default_value = "0.05")]
     pub reduce_vertices: Option<f64>,
-    /// Wait for the tile conversion process to finish, or terminate it if it is not finished after the provided number of seconds.
+    /// The simplification ratio that is passed to geof, as a global ratio (eg. '0.5'),
+    /// ratios per object type (eg. 'Building=0.5,Bridge=1.0'), or both
+    /// (eg. '0.5,Bridge=1.0'). The ratio of the first listed type that is in
+    /// --object-type is used, otherwise the global ratio. Without a ratio, the ratio is
+    /// 1.0 (no simplification) for buildings and the --reduce-vertices for the other
+    /// types.
+    #[arg(long)]
+    pub simplify_ratio: Option<SimplifyRatio>,
+    /// Clip and simplify the buildings like the other object types. By default, the tiles
+    /// that contain a Building or BuildingPart are not clipped to the tile boundary, and
+    /// the --reduce-vertices is not applied to them.
+    #[arg(long, visible_alias = "no-skip-clip")]
+    pub clip_buildings: bool,
+    /// Also add content to the parent tiles, up to this number of levels above their
+    /// deepest leaf, so that the viewer shows coarse content before the leaves are loaded.
+    /// The content of a parent tile is simplified with the --lod-simplify-ratio for each
+    /// level above the leaves. Requires '--refine replace', and it cannot be used with
+    /// implicit tiling and the octree.
+    #[arg(long)]
+    pub lod_levels: Option<u16>,
+    /// The simplification ratio of the content of the parent tiles with --lod-levels, which
+    /// is applied once for each level above the leaves, on top of the --simplify-ratio.
+    #[arg(long, value_parser = ratio, default_value = "0.5")]
+    pub lod_simplify_ratio: Option<f64>,
+    /// Weld (merge) the duplicate vertices of the features before the conversion, reducing
+    /// the size of the tiles. A vertex is merged into the nearest preceding vertex of the
+    /// feature that is within this distance, in the units of the input CRS (usually
+    /// meters). '0' only merges the vertices with the same coordinates. The welded features
+    /// are written to the 'inputs' directory, and the threshold is passed to geof as the
+    /// 'drop_duplicate_vertices_threshold' global. Vertex welding is off by default.
+    #[arg(long, visible_alias = "weld-vertices", value_parser = non_negative)]
+    pub drop_duplicate_vertices_threshold: Option<f64>,
+    /// Compress the meshes of the 3D Tiles content with Draco (KHR_draco_mesh_compression)
+    /// in geof, instead of the default meshopt compression (EXT_meshopt_compression).
+    /// Draco usually gives smaller tiles, but it is slower to decode in the viewer.
     #[arg(long)]
+    pub draco: bool,
+    /// Wait for the tile conversion process to finish, or terminate it if it is not finished after the provided number of seconds.
+    /// The tiles that timed out are failed, and they are marked with 'timed_out' in the
+    /// 'tyler_run.json'.
+    #[arg(long, visible_alias = "subprocess-timeout")]
     pub timeout: Option<u64>,
+    /// Retry a failed tile conversion subprocess at most this many times. The retries
+    /// wait for an exponential backoff, from 0.5 seconds up to 32 seconds.
+    #[arg(long, visible_alias = "retries", default_value = "0")]
+    pub max_retries: Option<u32>,
+    /// Which failures of the tile conversion subprocess are retried (see --max-retries).
+    /// 'always' retries on any failure, 'on-crash' only retries if the subprocess was
+    /// terminated by a signal (eg. segmentation fault), but not if it exited with an error code.
+    #[arg(long, value_enum, default_value = "always")]
+    pub retry_policy: Option<crate::RetryPolicy>,
+    /// Stop the conversion on the first tile that fails (after its retries), and exit with
+    /// an error. The run manifest and the failed tiles are still written to
+    /// 'tyler_run.json' and 'tyler_errors.json'. By default, the remaining tiles are
+    /// converted before exiting with an error.
+    #[arg(long)]
+    pub fail_fast: bool,
+    /// Write the tileset without the tiles that failed to convert, instead of exiting with
+    /// an error after the conversion.
+    #[arg(long, conflicts_with = "fail_fast")]
+    pub keep_going: bool,
+    /// Warn about the converted tiles that are larger than this number of bytes, because
+    /// large tiles stall the streaming in the viewer. The oversize tiles are also listed
+    /// in the 'tyler_run.json'. Lower the --qtree-capacity to get smaller tiles.
+    #[arg(long)]
+    pub max_tile_bytes: Option<u64>,
+    /// Compute the sha256 of each converted tile, and write it to the 'tyler_run.json',
+    /// eg. to verify the integrity of the uploaded tiles.
+    #[arg(long)]
+    pub emit_checksums: bool,
+    /// Also write the features of each tile, merged into a CityJSON file, into the
+    /// 'tiles_cityjson' directory next to the 'tiles', eg. to compare the geometry before
+    /// and after the conversion. With --resume, the existing CityJSON files are kept.
+    #[arg(long)]
+    pub keep_intermediate_cityjson: bool,
+    /// How the list of feature paths of a tile is passed to the conversion subprocess.
+    /// 'file' writes the list to a file in the 'inputs' directory and passes its path with
+    /// '--path_features_input_file', 'stdin' writes the list to the stdin of the subprocess
+    /// and passes '--path_features_input_file=/dev/stdin'.
+    #[arg(long, value_enum, default_value = "file")]
+    pub converter_input: Option<crate::ConverterInput>,
+    /// JSON file with rules that add extra arguments to the conversion subprocess of
+    /// specific tiles. The file contains an array of rules, eg.
+    /// '[{"level": 10, "bbox": [minx, miny, maxx, maxy], "args": ["--simplify_ratio=0.1"]}]'.
+    /// A rule can select tiles by 'tile_id_prefix' (level/x/y), 'level' and 'bbox' (in
+    /// the input CRS). The rules are evaluated in order and only the first matching rule
+    /// is applied.
+    #[arg(long, value_parser = existing_canonical_path)]
+    pub converter_rules: Option<PathBuf>,
+    /// Extra argument for the conversion subprocess, eg. '--geof-arg=--simplify_ratio=0.5'.
+    /// Can be repeated. The arguments are added after the built-in ones, so they override
+    /// them, but before the --converter-rules. geof requires the '--key=value' form.
+    #[arg(long, allow_hyphen_values = true)]
+    pub geof_arg: Option<Vec<String>>,
+    /// Soft limit on the memory use in GB. When the resident memory of tyler and the
+    /// running conversion subprocesses reaches the limit, no new conversions are started
+    /// until the memory use drops. Only has an effect on Linux.
+    #[arg(long)]
+    pub max_memory: Option<f64>,
+    /// Set an environment variable of the conversion subprocess, as 'KEY=VALUE'. Can be
+    /// repeated. The subprocess inherits the environment of tyler, and these variables are
+    /// added to it or override it, eg. '--subprocess-env PROJ_DATA=/opt/proj/share/proj'
+    /// points geof to a custom PROJ installation without exporting PROJ_DATA globally.
+    #[arg(long, value_parser = key_value)]
+    pub subprocess_env: Option<Vec<(String, String)>>,
     /// LoD to use in output for Building features
     #[arg(long)]
     pub lod_building: Option<String>,
@@ -212,9 +678,6 @@ pub struct Cli {
     // Counted from the leaves.
     // #[arg(long, default_value = "0")]
     // pub qtree_export_levels: Option<u16>,
-    // /// The criteria to check for the quadtree leaf capacity.
-    // #[arg(long, value_enum, default_value = "vertices")]
-    // pub qtree_criteria: Option<crate::spatial_structs::QuadTreeCriteria>,
     // /// Path to the python interpreter (>=3.8) to use for generating CityJSON tiles.
     // /// The interpreter must have a recent cjio (https://github.com/cityjson/cjio)
     // /// installed.
@@ -222,6 +685,239 @@ pub struct Cli {
     // pub exe_python: Option<PathBuf>,
 }
 
+impl Cli {
+    /// Parse the command line arguments and the configuration file that is passed with
+    /// `--config`.
+    pub fn parse_with_config() -> Result<Self, Box<dyn Error>> {
+        Self::parse_with_config_from(std::env::args_os())
+    }
+
+    /// Same as [Cli::parse_with_config], but parse the arguments from `args`.
+    ///
+    /// The values of the configuration file are converted to command line arguments
+    /// and they are inserted before the arguments that are passed on the command line.
+    /// Thus, the values of the configuration file are validated in the same way as the
+    /// command line arguments. A value is only taken from the configuration file if the
+    /// argument is not passed on the command line.
+    ///
+    /// The errors of clap, including the ones for `--help` and `--version`, are returned
+    /// as a [clap::Error], so that the caller decides whether to exit.
+    pub fn parse_with_config_from<I, T>(args: I) -> Result<Self, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        // The required arguments might be in the configuration file, so we need to ignore
+        // the errors until the configuration is merged.
+        let matches = match Self::command()
+            .ignore_errors(true)
+            .try_get_matches_from(&args)
+        {
+            Ok(matches) => matches,
+            // eg. --help or --version
+            Err(_) => return Ok(Self::try_parse_from(args)?),
+        };
+        let path_config = match matches.get_one::<PathBuf>("config") {
+            Some(path) => path,
+            None => return Ok(Self::try_parse_from(args)?),
+        };
+        let config: toml::Table = read_to_string(path_config)?
+            .parse()
+            .map_err(|e| format!("could not parse the config file {:?}: {}", path_config, e))?;
+        let command = Self::command();
+        let mut config_args: Vec<OsString> = Vec::new();
+        for (key, value) in config.iter() {
+            let arg = command
+                .get_arguments()
+                .find(|arg| {
+                    arg.get_id() != "config"
+                        && arg.get_long().map(|long| long.replace('-', "_")).as_deref()
+                            == Some(key.as_str())
+                })
+                .ok_or_else(|| {
+                    format!("unknown key '{}' in the config file {:?}", key, path_config)
+                })?;
+            if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let long = format!("--{}", arg.get_long().unwrap());
+            if !arg.get_action().takes_values() {
+                match value {
+                    toml::Value::Boolean(true) => config_args.push(long.into()),
+                    toml::Value::Boolean(false) => {}
+                    _ => {
+                        return Err(format!(
+                            "the key '{}' in the config file {:?} must be a boolean",
+                            key, path_config
+                        )
+                        .into())
+                    }
+                }
+                continue;
+            }
+            let values = match value {
+                toml::Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                let value_str = match value {
+                    toml::Value::String(s) => s.clone(),
+                    toml::Value::Integer(i) => i.to_string(),
+                    toml::Value::Float(f) => f.to_string(),
+                    toml::Value::Boolean(b) => b.to_string(),
+                    _ => {
+                        return Err(format!(
+                            "the key '{}' in the config file {:?} has an unsupported value type",
+                            key, path_config
+                        )
+                        .into())
+                    }
+                };
+                config_args.push(format!("{}={}", long, value_str).into());
+            }
+        }
+        let mut merged_args = args;
+        let tail = merged_args.split_off(1.min(merged_args.len()));
+        merged_args.extend(config_args);
+        merged_args.extend(tail);
+        Ok(Self::try_parse_from(merged_args)?)
+    }
+
+    /// The `resources` directory, see `--resources-dir`. The locations relative to the
+    /// executable are only used if they exist.
+    pub fn resources_dir(&self) -> PathBuf {
+        if let Some(ref dir) = self.resources_dir {
+            return dir.clone();
+        }
+        if let Some(dir) = std::env::var_os("TYLER_RESOURCES_DIR") {
+            return PathBuf::from(dir);
+        }
+        let exe_dir = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf));
+        if let Some(exe_dir) = exe_dir {
+            for dir in [
+                exe_dir.join("resources"),
+                exe_dir
+                    .join("..")
+                    .join("share")
+                    .join("tyler")
+                    .join("resources"),
+            ] {
+                if dir.is_dir() {
+                    return dir;
+                }
+            }
+        }
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources")
+    }
+}
+
+/// The value of --grid-cellsize, which is a number, two comma-separated numbers for the x
+/// and y axes, or 'auto'.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GridCellsize {
+    Auto,
+    Size([u16; 2]),
+}
+
+impl std::str::FromStr for GridCellsize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(Self::Auto);
+        }
+        let sizes: Vec<Result<u16, _>> = s
+            .split(',')
+            .map(|size| size.trim().parse::<u16>())
+            .collect();
+        match sizes.as_slice() {
+            [Ok(size)] if *size > 0 => Ok(Self::Size([*size, *size])),
+            [Ok(size_x), Ok(size_y)] if *size_x > 0 && *size_y > 0 => {
+                Ok(Self::Size([*size_x, *size_y]))
+            }
+            _ => Err(format!(
+                "the cell size must be 'auto', a number or two comma-separated numbers (x,y) between 1 and {}",
+                u16::MAX
+            )),
+        }
+    }
+}
+
+/// The value of --simplify-ratio, a global ratio and the ratios per object type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimplifyRatio {
+    pub global: Option<f64>,
+    pub per_type: Vec<(CityObjectType, f64)>,
+}
+
+impl SimplifyRatio {
+    /// The ratio of the first listed type that is in `cotypes`, otherwise the global
+    /// ratio.
+    pub fn ratio(&self, cotypes: &[CityObjectType]) -> Option<f64> {
+        self.per_type
+            .iter()
+            .find(|(cotype, _)| cotypes.contains(cotype))
+            .map(|(_, ratio)| *ratio)
+            .or(self.global)
+    }
+}
+
+impl std::str::FromStr for SimplifyRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_ratio = |r: &str| ratio(r.trim());
+        let mut simplify_ratio = Self::default();
+        for part in s.split(',') {
+            match part.split_once('=') {
+                Some((cotype, ratio)) => {
+                    let cotype = <CityObjectType as clap::ValueEnum>::from_str(cotype.trim(), true)
+                        .map_err(|_| format!("unknown object type '{}'", cotype))?;
+                    simplify_ratio.per_type.push((cotype, parse_ratio(ratio)?));
+                }
+                None if simplify_ratio.global.is_none() => {
+                    simplify_ratio.global = Some(parse_ratio(part)?)
+                }
+                None => return Err(String::from("only one global ratio can be given")),
+            }
+        }
+        Ok(simplify_ratio)
+    }
+}
+
+#[derive(Subcommand, Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    /// Check the inputs of the run without tiling, and report all the problems that are
+    /// found. The options are the same as for a run and they are given before the
+    /// subcommand, eg. 'tyler -m metadata.city.json -f features -o output validate'.
+    Validate,
+    /// Write a tileset that references existing tilesets as external tilesets, eg. to
+    /// combine the tilesets of several regions, as
+    /// 'tyler merge region1/tileset.json region2/tileset.json -o merged/tileset.json'.
+    /// The options of a run are not used.
+    Merge(MergeArgs),
+    /// Print a sample tileset.json with a single tile, as tyler writes it, and the 3D Tiles
+    /// version that it targets, eg. to check the setup of a viewer before tiling real
+    /// data. No input data is needed and the options of a run are not used.
+    PrintSchema(PrintSchemaArgs),
+}
+
+#[derive(Args, Debug, Clone, Eq, PartialEq)]
+pub struct MergeArgs {
+    /// The tileset.json files to merge. Their root bounding volumes must be regions.
+    #[arg(required = true, value_parser = existing_canonical_path)]
+    pub tilesets: Vec<PathBuf>,
+    /// The merged tileset.json. The URIs of the tilesets are relative to its directory.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Eq, PartialEq)]
+pub struct PrintSchemaArgs {}
+
 fn existing_canonical_path(s: &str) -> Result<PathBuf, String> {
     if let Ok(c) = Path::new(s).canonicalize() {
         if c.exists() {
@@ -234,6 +930,24 @@ fn existing_canonical_path(s: &str) -> Result<PathBuf, String> {
     }
 }
 
+/// Same as [existing_canonical_path], but HTTP(S) URLs are also accepted.
+fn metadata_path(s: &str) -> Result<PathBuf, String> {
+    if crate::remote::is_url(Path::new(s)) {
+        Ok(PathBuf::from(s))
+    } else {
+        existing_canonical_path(s)
+    }
+}
+
+/// Same as [metadata_path], but '-' (stdin) is also accepted.
+fn features_path(s: &str) -> Result<PathBuf, String> {
+    if s == "-" || crate::remote::is_url(Path::new(s)) {
+        Ok(PathBuf::from(s))
+    } else {
+        existing_canonical_path(s)
+    }
+}
+
 /// We don't want to canonicalize paths to executables, especially a python exe from a
 /// virtualenv, because the symlink would get resolved and we would end up with a path
 /// to the python interpreter that was used for creating the virtualenv, and not the
@@ -263,10 +977,51 @@ fn hex_color(s: &str) -> Result<String, String> {
     Ok(String::from(s))
 }
 
+fn ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!(
+            "the simplification ratio must be a number between 0.0 and 1.0, got '{}'",
+            s
+        )),
+    }
+}
+
+fn non_negative(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value >= 0.0 => Ok(value),
+        _ => Err(format!("must be a number that is at least 0, got '{}'", s)),
+    }
+}
+
+fn extent(s: &str) -> Result<[f64; 4], String> {
+    let err = || String::from("Input must be four numbers 'minx,miny,maxx,maxy'");
+    let values: Vec<f64> = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| err())?;
+    let extent: [f64; 4] = values.try_into().map_err(|_| err())?;
+    if extent[0] >= extent[2] || extent[1] >= extent[3] {
+        return Err(String::from(
+            "The minimum coordinates must be less than the maximum coordinates",
+        ));
+    }
+    Ok(extent)
+}
+
+fn key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(String::from("Input must be in the form of KEY=VALUE")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Cli;
     use clap::{CommandFactory, Parser};
+    use std::fs;
 
     fn required_args() -> Vec<&'static str> {
         vec![
@@ -282,6 +1037,22 @@ mod tests {
         ]
     }
 
+    /// The options of a run, with a metadata file that exists.
+    fn run_args() -> Vec<&'static str> {
+        vec![
+            "tyler",
+            "-m",
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/resources/data/3dbag_x00.city.json"
+            ),
+            "-f",
+            env!("CARGO_MANIFEST_DIR"),
+            "-o",
+            env!("CARGO_MANIFEST_DIR"),
+        ]
+    }
+
     #[test]
     fn verify_cli() {
         Cli::command().debug_assert()
@@ -299,4 +1070,167 @@ mod tests {
         assert!(otypes.contains(&crate::parser::CityObjectType::Building));
         assert!(otypes.contains(&crate::parser::CityObjectType::PlantCover));
     }
+
+    /// Can we pass the CityObject types as a comma-separated list?
+    #[test]
+    fn verify_object_types_list() {
+        let mut args = run_args();
+        args.append(&mut vec!["--object-type", "Building,PlantCover"]);
+        let cli = Cli::try_parse_from(args).unwrap();
+        let otypes = &cli.object_type.unwrap();
+        assert!(otypes.contains(&crate::parser::CityObjectType::Building));
+        assert!(otypes.contains(&crate::parser::CityObjectType::PlantCover));
+    }
+
+    /// Can we pass multiple environment variables, and is the value split at the first '='?
+    #[test]
+    fn verify_subprocess_env() {
+        let mut args = run_args();
+        args.append(&mut vec![
+            "--subprocess-env",
+            "PROJ_DATA=/opt/proj",
+            "--subprocess-env",
+            "TOKEN=a=b",
+        ]);
+        let cli = Cli::try_parse_from(args).unwrap();
+        let env = cli.subprocess_env.unwrap();
+        assert_eq!(env[0], ("PROJ_DATA".to_string(), "/opt/proj".to_string()));
+        assert_eq!(env[1], ("TOKEN".to_string(), "a=b".to_string()));
+        let mut args = run_args();
+        args.append(&mut vec!["--subprocess-env", "=value"]);
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn verify_extent() {
+        let mut args = run_args();
+        args.append(&mut vec!["--extent", "84000,445000.5,86000,447000"]);
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(cli.extent, Some([84000.0, 445000.5, 86000.0, 447000.0]));
+        for extent in ["84000,445000,86000", "86000,445000,84000,447000"] {
+            let mut args = run_args();
+            args.append(&mut vec!["--extent", extent]);
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn verify_grid_cellsize() {
+        for (grid_cellsize, expected) in [
+            ("auto", super::GridCellsize::Auto),
+            ("500", super::GridCellsize::Size([500, 500])),
+            ("500,100", super::GridCellsize::Size([500, 100])),
+        ] {
+            let mut args = run_args();
+            args.append(&mut vec!["--grid-cellsize", grid_cellsize]);
+            let cli = Cli::try_parse_from(args).unwrap();
+            assert_eq!(cli.grid_cellsize, Some(expected));
+        }
+        for grid_cellsize in ["0", "500,0", "500,100,10", "70000"] {
+            let mut args = run_args();
+            args.append(&mut vec!["--grid-cellsize", grid_cellsize]);
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn verify_simplify_ratio() {
+        use crate::parser::CityObjectType::{Bridge, Building, PlantCover};
+        let mut args = run_args();
+        args.append(&mut vec!["--simplify-ratio", "0.8,building=0.5,Bridge=1.0"]);
+        let cli = Cli::try_parse_from(args).unwrap();
+        let simplify_ratio = cli.simplify_ratio.unwrap();
+        assert_eq!(simplify_ratio.ratio(&[Bridge, Building]), Some(0.5));
+        assert_eq!(simplify_ratio.ratio(&[Bridge]), Some(1.0));
+        assert_eq!(simplify_ratio.ratio(&[PlantCover]), Some(0.8));
+        for simplify_ratio in ["1.5", "house=0.5", "0.5,0.8"] {
+            let mut args = run_args();
+            args.append(&mut vec!["--simplify-ratio", simplify_ratio]);
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+
+    /// Can we pass subprocess arguments that start with a hyphen?
+    #[test]
+    fn verify_geof_arg() {
+        let mut args = run_args();
+        args.append(&mut vec![
+            "--geof-arg",
+            "--simplify_ratio=0.5",
+            "--geof-arg=--skip_clip=true",
+        ]);
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(
+            cli.geof_arg.unwrap(),
+            vec!["--simplify_ratio=0.5", "--skip_clip=true"]
+        );
+    }
+
+    #[test]
+    fn verify_merge() {
+        let cli = Cli::try_parse_from([
+            "tyler",
+            "merge",
+            env!("CARGO_MANIFEST_DIR"),
+            "-o",
+            "merged/tileset.json",
+        ])
+        .unwrap();
+        let Some(super::Command::Merge(merge)) = cli.command else {
+            panic!("the subcommand should be merge");
+        };
+        assert_eq!(merge.tilesets.len(), 1);
+        assert_eq!(
+            merge.output,
+            std::path::PathBuf::from("merged/tileset.json")
+        );
+        // The options of a run are only required without a subcommand
+        assert!(Cli::try_parse_from(["tyler"]).is_err());
+        assert!(matches!(
+            Cli::try_parse_from(["tyler", "print-schema"])
+                .unwrap()
+                .command,
+            Some(super::Command::PrintSchema(_))
+        ));
+    }
+
+    /// Are the values of the config file used, unless the option is passed on the
+    /// command line?
+    #[test]
+    fn verify_config() {
+        let path_config = std::env::temp_dir().join("tyler_verify_config.toml");
+        fs::write(
+            &path_config,
+            format!(
+                "features = {:?}\noutput = {:?}\ngrid_cellsize = 500\nqtree_capacity = 10\n\
+                 3dtiles_implicit = true\nobject_type = [\"Building\", \"PlantCover\"]\n",
+                env!("CARGO_MANIFEST_DIR"),
+                env!("CARGO_MANIFEST_DIR")
+            ),
+        )
+        .unwrap();
+        let cli = Cli::parse_with_config_from([
+            "tyler",
+            "--config",
+            path_config.to_str().unwrap(),
+            "--qtree-capacity",
+            "20",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.grid_cellsize,
+            Some(super::GridCellsize::Size([500, 500]))
+        );
+        assert_eq!(cli.qtree_capacity, Some(20));
+        assert!(cli.cesium3dtiles_implicit);
+        assert_eq!(cli.object_type.unwrap().len(), 2);
+
+        fs::write(&path_config, "grid_size = 500\n").unwrap();
+        let res = Cli::parse_with_config_from(["tyler", "--config", path_config.to_str().unwrap()]);
+        assert!(res.is_err());
+        fs::remove_file(&path_config).unwrap();
+        // The clap errors are returned instead of exiting
+        let res = Cli::parse_with_config_from(["tyler", "--help"]);
+        assert!(res.err().unwrap().downcast::<clap::Error>().is_ok());
+    }
 }
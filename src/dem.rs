@@ -0,0 +1,131 @@
+//! Ground elevation from a Digital Elevation Model (DEM).
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use log::{debug, warn};
+
+use crate::process::Cmd;
+use crate::spatial_structs::{Bbox, QuadTree, QuadTreeNodeId, SquareGrid};
+
+/// A raster DEM that is sampled with the
+/// [gdallocationinfo](https://gdal.org/programs/gdallocationinfo.html) program, so that
+/// tyler can read any raster format that GDAL supports.
+#[derive(Debug, Clone)]
+pub struct Dem {
+    path: PathBuf,
+    exe: PathBuf,
+    /// EPSG code of the coordinates that are sampled (not of the DEM).
+    epsg: u16,
+}
+
+impl Dem {
+    pub fn new(path: PathBuf, exe: PathBuf, epsg: u16) -> Self {
+        Self { path, exe, epsg }
+    }
+
+    /// The location of the samples that are taken for a bbox, which are the center and the
+    /// corners of the bbox.
+    fn sample_points(bbox: &Bbox) -> [[f64; 2]; 5] {
+        [
+            [(bbox[0] + bbox[3]) * 0.5, (bbox[1] + bbox[4]) * 0.5],
+            [bbox[0], bbox[1]],
+            [bbox[3], bbox[1]],
+            [bbox[3], bbox[4]],
+            [bbox[0], bbox[4]],
+        ]
+    }
+
+    /// Sample the ground elevation for each node of the quadtree, as the minimum of the
+    /// DEM values at the center and the corners of the node.
+    /// The nodes that are outside of the DEM do not have a ground elevation.
+    /// All the nodes are sampled with a single gdallocationinfo call.
+    pub fn ground_z(
+        &self,
+        quadtree: &QuadTree,
+        grid: &SquareGrid,
+    ) -> Result<HashMap<QuadTreeNodeId, f64>, Box<dyn std::error::Error>> {
+        let mut node_ids: Vec<QuadTreeNodeId> = Vec::new();
+        let mut stdin = String::new();
+        let mut q = VecDeque::new();
+        q.push_back(quadtree);
+        while let Some(node) = q.pop_front() {
+            for [x, y] in Self::sample_points(&node.bbox(grid)) {
+                stdin.push_str(&format!("{} {}\n", x, y));
+            }
+            node_ids.push(node.id.clone());
+            for child in &node.children {
+                q.push_back(child);
+            }
+        }
+        let output = Cmd::new(&self.exe)
+            .arg("-valonly")
+            .arg("-l_srs")
+            .arg(format!("EPSG:{}", self.epsg))
+            .arg(&self.path)
+            .output(&stdin)?;
+        if !output.status.success() {
+            return Err(format!(
+                "could not sample the DEM {:?} with {:?}:\n{}",
+                &self.path,
+                &self.exe,
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        let values = Self::parse_values(&String::from_utf8_lossy(&output.stdout));
+        if values.len() != node_ids.len() * 5 {
+            return Err(format!(
+                "expected {} DEM samples, but gdallocationinfo returned {}",
+                node_ids.len() * 5,
+                values.len()
+            )
+            .into());
+        }
+        let ground_z: HashMap<QuadTreeNodeId, f64> = node_ids
+            .into_iter()
+            .zip(values.chunks(5))
+            .filter_map(|(id, samples)| {
+                let z = samples.iter().flatten().copied().reduce(f64::min)?;
+                Some((id, z))
+            })
+            .collect();
+        debug!("Sampled the ground elevation for {} nodes", ground_z.len());
+        if ground_z.is_empty() {
+            warn!("None of the tiles are covered by the DEM {:?}", &self.path);
+        }
+        Ok(ground_z)
+    }
+
+    /// Parse the values that gdallocationinfo writes to stdout, one line per sample.
+    /// Samples outside of the raster are written as empty lines.
+    fn parse_values(stdout: &str) -> Vec<Option<f64>> {
+        stdout
+            .lines()
+            .map(|line| line.trim().parse::<f64>().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        let values = Dem::parse_values("1.5\n\n-2\n");
+        assert_eq!(values, vec![Some(1.5), None, Some(-2.0)]);
+    }
+}
@@ -17,28 +17,53 @@
 pub mod cesium3dtiles {
     //! Cesium [3D Tiles](https://github.com/CesiumGS/3d-tiles).
     //! Supported version: 1.1.
-    //! Not supported: `extras`.
+    //! The only supported `extras` are the `priority` of a tile and the string properties of
+    //! the tileset.
+    use std::collections::BTreeMap;
     use std::collections::HashMap;
     use std::collections::VecDeque;
     use std::fmt::{Display, Formatter};
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io::{Seek, Write};
     use std::path::{Path, PathBuf};
 
     use bitvec::prelude as bv;
     use log::{debug, error, warn};
     use morton_encoding::morton_encode;
-    use serde::{Serialize, Serializer};
+    use serde::{Deserialize, Serialize};
     use serde_repr::Serialize_repr;
 
-    use crate::proj::Proj;
+    use crate::proj::Reprojector;
     use crate::spatial_structs::{
-        Bbox, CellId, QuadTree, QuadTreeCapacity, QuadTreeNodeId, SquareGrid,
+        Bbox, BboxQc, CellId, Octree, OctreeNodeId, QuadTree, QuadTreeCapacity, QuadTreeNodeId,
+        SquareGrid,
     };
 
+    /// The options of [Tileset::from_quadtree] and [Tileset::from_octree].
+    #[derive(Debug, Clone, Copy)]
+    pub struct TilesetOptions<'a> {
+        /// Reprojects the bounding volumes from the CRS of the input to EPSG:4979.
+        pub transformer: &'a Reprojector,
+        /// If provided, it must reproject to EPSG:4978, and the leaf tiles get a `transform`
+        /// that translates their content from the center of the content to EPSG:4978.
+        pub transformer_local: Option<&'a Reprojector>,
+        pub geometric_error_above_leaf: f64,
+        /// Compute the geometric error of the parent tiles from their size, see
+        /// `--geometric-error-factor`.
+        pub geometric_error_factor: Option<f64>,
+        /// The grid cell size along x and y. Only used by [Tileset::from_quadtree].
+        pub arg_cellsize: [u16; 2],
+        pub arg_minz: Option<i32>,
+        pub arg_maxz: Option<i32>,
+        pub tile_name_prefix: &'a str,
+        /// The ground elevation of the quadtree nodes. If a node has one, it is the minimum
+        /// z of the tile bounding volume. Only used by [Tileset::from_quadtree].
+        pub ground_z: Option<&'a HashMap<QuadTreeNodeId, f64>>,
+    }
+
     /// [Tileset](https://github.com/CesiumGS/3d-tiles/tree/main/specification#tileset).
     ///
-    /// Not supported: `extras`.
+    /// The `extras` can only contain string values.
     #[derive(Serialize, Default, Debug, Clone)]
     #[serde(rename_all = "camelCase")]
     pub struct Tileset {
@@ -53,6 +78,8 @@ pub mod cesium3dtiles {
         extensions_required: Option<Vec<ExtensionName>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         extensions: Option<Extensions>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        extras: Option<BTreeMap<String, String>>,
     }
 
     impl Tileset {
@@ -63,18 +90,259 @@ pub mod cesium3dtiles {
             Ok(())
         }
 
+        /// Write the tileset to a `tileset.json` file that only contains the first
+        /// `inline_levels` levels of the tile hierarchy, and the lower levels into external
+        /// tilesets (see [Tileset::split_external]). The external tilesets are written into
+        /// the same directory as `path`.
+        pub fn to_file_split<P: AsRef<Path>>(
+            &self,
+            path: P,
+            inline_levels: u16,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            let (tileset, external_tilesets) = self.split_external(inline_levels);
+            let dir = path.as_ref().parent().unwrap_or(Path::new(""));
+            for (uri, external_tileset) in external_tilesets {
+                external_tileset.to_file(dir.join(uri))?;
+            }
+            tileset.to_file(path)
+        }
+
+        /// Create a tileset that references the existing `tilesets` as external tilesets,
+        /// eg. to combine the tilesets of several regions. The root bounding volume
+        /// encloses the root bounding volumes of the tilesets, which must be regions. The
+        /// URIs of the tilesets are relative to the directory of `output`, which is where
+        /// the merged tileset is written.
+        pub fn merge<P: AsRef<Path>>(
+            tilesets: &[P],
+            output: &Path,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            let output_dir = output.parent().unwrap_or(Path::new("")).canonicalize()?;
+            let mut children: Vec<Tile> = Vec::with_capacity(tilesets.len());
+            let mut root_bounding_volume: Option<BoundingVolume> = None;
+            let mut attributions: Vec<String> = Vec::new();
+            for path in tilesets {
+                let path = path.as_ref().canonicalize()?;
+                let external: ExternalTileset =
+                    serde_json::from_str(&std::fs::read_to_string(&path)?)
+                        .map_err(|e| format!("could not read the tileset {:?}: {}", &path, e))?;
+                let bounding_volume = external.root.bounding_volume;
+                if let Some(extras) = external.asset.and_then(|asset| asset.extras) {
+                    attributions.extend(extras.cesium.credits.into_iter().map(|c| c.html));
+                }
+                if !matches!(bounding_volume, BoundingVolume::Region(_)) {
+                    return Err(format!(
+                        "the root bounding volume of the tileset {:?} is not a region",
+                        &path
+                    )
+                    .into());
+                }
+                match root_bounding_volume {
+                    Some(ref mut root_bv) => root_bv.extend_region(&bounding_volume),
+                    None => root_bounding_volume = Some(bounding_volume),
+                }
+                let uri = relative_path(&output_dir, &path)
+                    .to_str()
+                    .ok_or_else(|| format!("the path {:?} is not valid unicode", &path))?
+                    .to_string();
+                children.push(Tile {
+                    bounding_volume,
+                    geometric_error: external.geometric_error,
+                    content: Some(Content {
+                        bounding_volume: None,
+                        uri,
+                    }),
+                    ..Default::default()
+                });
+            }
+            let Some(root_bounding_volume) = root_bounding_volume else {
+                return Err("there are no tilesets to merge".into());
+            };
+            let root_geometric_error = children
+                .iter()
+                .map(|child| child.geometric_error)
+                .fold(0.0, f64::max);
+            let root = Tile {
+                bounding_volume: root_bounding_volume,
+                geometric_error: root_geometric_error,
+                refine: Some(Refinement::Replace),
+                children: Some(children),
+                ..Default::default()
+            };
+            let mut tileset = Self {
+                geometric_error: root.geometric_error * 1.5,
+                root,
+                ..Default::default()
+            };
+            tileset.set_attributions(attributions);
+            Ok(tileset)
+        }
+
+        /// Split the tileset into a tileset with the first `inline_levels` levels of
+        /// the tile hierarchy, and external tilesets for the tiles below.
+        /// The tiles at the cut level that have children but no content, reference an
+        /// external tileset as their content. The root tile of the external tileset is a
+        /// copy of the referencing tile with the same geometric error, and the external
+        /// tileset is split in the same way.
+        /// Returns the split tileset and the external tilesets with their URI.
+        pub fn split_external(&self, inline_levels: u16) -> (Tileset, Vec<(String, Tileset)>) {
+            let mut external_tilesets: Vec<(String, Tileset)> = Vec::new();
+            let root = self.split_tile(
+                self.root.clone(),
+                inline_levels,
+                inline_levels,
+                &mut external_tilesets,
+            );
+            let tileset = Tileset {
+                root,
+                ..self.clone_without_root()
+            };
+            (tileset, external_tilesets)
+        }
+
+        fn split_tile(
+            &self,
+            mut tile: Tile,
+            levels_left: u16,
+            inline_levels: u16,
+            external_tilesets: &mut Vec<(String, Tileset)>,
+        ) -> Tile {
+            let Some(children) = tile.children.take() else {
+                return tile;
+            };
+            if levels_left == 0 && tile.content.is_none() && inline_levels > 0 {
+                let uri = format!("tileset_{}.json", tile.id.to_string().replace('/', "_"));
+                let external_root = self.split_tile(
+                    Tile {
+                        children: Some(children),
+                        ..tile.clone()
+                    },
+                    inline_levels,
+                    inline_levels,
+                    external_tilesets,
+                );
+                let external_tileset = Tileset {
+                    geometric_error: tile.geometric_error,
+                    root: external_root,
+                    // The extras describe the whole tileset
+                    extras: None,
+                    ..self.clone_without_root()
+                };
+                external_tilesets.push((uri.clone(), external_tileset));
+                tile.content = Some(Content {
+                    bounding_volume: None,
+                    uri,
+                });
+            } else {
+                tile.children = Some(
+                    children
+                        .into_iter()
+                        .map(|child| {
+                            self.split_tile(
+                                child,
+                                levels_left.saturating_sub(1),
+                                inline_levels,
+                                external_tilesets,
+                            )
+                        })
+                        .collect(),
+                );
+            }
+            tile
+        }
+
+        /// A tileset with a single tile, for the `print-schema` subcommand. It is created
+        /// with the same steps as the tileset of a run, from the sample feature in
+        /// `resources/data`, which is embedded in the binary, so that it does not need any
+        /// input data.
+        pub fn sample() -> Result<Self, Box<dyn std::error::Error>> {
+            let dir = std::env::temp_dir().join(format!("tyler_sample_{}", std::process::id()));
+            let dir_features = dir.join("features");
+            fs::create_dir_all(&dir_features)?;
+            let path_metadata = dir.join("metadata.city.json");
+            fs::write(
+                &path_metadata,
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/resources/data/3dbag_x00.city.json"
+                )),
+            )?;
+            fs::write(
+                dir_features.join("feature.city.jsonl"),
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/resources/data/3dbag_feature_x71.city.jsonl"
+                )),
+            )?;
+            let tileset = (|| -> Result<Self, Box<dyn std::error::Error>> {
+                let mut world = crate::parser::World::new(
+                    &path_metadata,
+                    &dir_features,
+                    None,
+                    1,
+                    None,
+                    None,
+                    None,
+                    None,
+                    false,
+                    None,
+                    None,
+                    None,
+                )?;
+                world.index_with_grid()?;
+                let quadtree = QuadTree::from_world(
+                    &world,
+                    QuadTreeCapacity::Objects(usize::MAX),
+                    None,
+                    crate::spatial_structs::QuadTreeSplit::Center,
+                );
+                let transformer =
+                    Reprojector::new(&format!("EPSG:{}", world.crs.to_epsg()?), "EPSG:4979")?;
+                Ok(Self::from_quadtree(
+                    &quadtree,
+                    &world,
+                    &TilesetOptions {
+                        transformer: &transformer,
+                        transformer_local: None,
+                        geometric_error_above_leaf: 12.0,
+                        geometric_error_factor: None,
+                        arg_cellsize: world.grid.cellsize,
+                        arg_minz: None,
+                        arg_maxz: None,
+                        tile_name_prefix: "",
+                        ground_z: None,
+                    },
+                ))
+            })();
+            let _ = fs::remove_dir_all(&dir);
+            tileset
+        }
+
+        /// The 3D Tiles version of the tileset.
+        pub fn version(&self) -> &str {
+            &self.asset.version
+        }
+
+        /// A copy of the tileset properties, with an empty root tile.
+        fn clone_without_root(&self) -> Tileset {
+            Tileset {
+                asset: self.asset.clone(),
+                geometric_error: self.geometric_error,
+                root: Default::default(),
+                properties: self.properties.clone(),
+                extensions_used: self.extensions_used.clone(),
+                extensions_required: self.extensions_required.clone(),
+                extensions: self.extensions.clone(),
+                extras: self.extras.clone(),
+            }
+        }
+
+        /// Create the tileset of the `quadtree`, see [TilesetOptions] for the `options`.
         pub fn from_quadtree(
             quadtree: &QuadTree,
             world: &crate::parser::World,
-            geometric_error_above_leaf: f64,
-            arg_cellsize: u16,
-            arg_minz: Option<i32>,
-            arg_maxz: Option<i32>,
+            options: &TilesetOptions,
         ) -> Self {
-            let crs_from = format!("EPSG:{}", world.crs.to_epsg().unwrap());
-            // Because we have a boundingVolume.box. For a boundingVolume.region we need 4979.
-            let crs_to = "EPSG:4979";
-            let transformer = Proj::new_known_crs(&crs_from, crs_to, None).unwrap();
+            let geometric_error_factor = options.geometric_error_factor;
             // y-up to z-up transform needed because we are using gltf assets, which is y-up
             // https://github.com/CesiumGS/3d-tiles/tree/main/specification#y-up-to-z-up
             // let y_up_to_z_up = Transform([
@@ -83,53 +351,169 @@ pub mod cesium3dtiles {
 
             let root_bbox = quadtree.bbox(&world.grid);
 
-            let root = Self::generate_tiles(
-                quadtree,
-                world,
-                &transformer,
-                geometric_error_above_leaf,
-                arg_cellsize,
-                arg_minz,
-                arg_maxz,
-            );
+            let root = Self::generate_tiles(quadtree, world, options);
             // root.transform = Some(y_up_to_z_up);
 
-            // Using gltf tile content
-            let mut extensions: Extensions = HashMap::new();
-            let e1 = Extension::ContentGtlf {
+            // The glTF content is declared with set_content_extensions after the conversion
+
+            Self {
+                asset: Default::default(),
+                geometric_error: tileset_geometric_error(&root, &root_bbox, geometric_error_factor),
+                root,
+                properties: None,
                 extensions_used: None,
                 extensions_required: None,
-            };
-            extensions.insert(ExtensionName::ContentGltf, e1);
+                extensions: None,
+                extras: None,
+            }
+        }
 
+        /// Same as [Tileset::from_quadtree], but the tiles are created from an [Octree].
+        /// Only explicit tiling is supported.
+        ///
+        /// The children of a tile can overlap in 2D, because they are split along the
+        /// z-axis too. The geometric error of the parents of the leaves is
+        /// `geometric_error_above_leaf`, and it doubles with each level upwards.
+        pub fn from_octree(
+            octree: &Octree,
+            world: &crate::parser::World,
+            options: &TilesetOptions,
+        ) -> Self {
+            let geometric_error_factor = options.geometric_error_factor;
+            let root = Self::generate_tiles_octree(octree, world, options);
             Self {
                 asset: Default::default(),
-                geometric_error: root.geometric_error * 1.5,
+                geometric_error: tileset_geometric_error(
+                    &root,
+                    &octree.bbox(&world.grid),
+                    geometric_error_factor,
+                ),
                 root,
                 properties: None,
                 extensions_used: None,
                 extensions_required: None,
                 extensions: None,
+                extras: None,
+            }
+        }
+
+        fn generate_tiles_octree(
+            octree: &Octree,
+            world: &crate::parser::World,
+            options: &TilesetOptions,
+        ) -> Tile {
+            let TilesetOptions {
+                transformer,
+                transformer_local,
+                geometric_error_above_leaf,
+                geometric_error_factor,
+                arg_minz,
+                arg_maxz,
+                tile_name_prefix,
+                ..
+            } = *options;
+            let tile_id = TileId::from(&octree.id);
+            let mut tile_bbox = octree.bbox(&world.grid);
+            if !octree.children.is_empty() {
+                let tile_children: Vec<Tile> = octree
+                    .children
+                    .iter()
+                    .map(|child| Self::generate_tiles_octree(child, world, options))
+                    .collect();
+                let geometric_error = match geometric_error_factor {
+                    Some(factor) => geometric_error_from_extent(&tile_bbox, factor, &tile_children),
+                    None => tile_children
+                        .iter()
+                        .map(|child| child.geometric_error * 2.0)
+                        .fold(geometric_error_above_leaf, f64::max),
+                };
+                let mut bounding_volume =
+                    BoundingVolume::region_from_bbox(&tile_bbox, transformer).unwrap();
+                // The children are larger than the node if their content is larger
+                for child in tile_children.iter() {
+                    bounding_volume.extend_region(&child.bounding_volume);
+                }
+                Tile {
+                    id: tile_id,
+                    bounding_volume,
+                    geometric_error,
+                    viewer_request_volume: None,
+                    refine: Some(Refinement::Replace),
+                    transform: None,
+                    content: None,
+                    children: Some(tile_children),
+                    implicit_tiling: None,
+                    extras: None,
+                }
+            } else {
+                let tile_content_bbox_rw =
+                    match features_bbox_qc(world, octree.feature_ids().iter()) {
+                        Some(bbox_qc) => bbox_qc.to_bbox(&world.transform, arg_minz, arg_maxz),
+                        None => tile_bbox,
+                    };
+                tile_bbox[2] = tile_content_bbox_rw[2];
+                tile_bbox[5] = tile_content_bbox_rw[5];
+                let content_bounding_volume =
+                    BoundingVolume::region_from_bbox(&tile_content_bbox_rw, transformer).unwrap();
+                let mut bounding_volume =
+                    BoundingVolume::region_from_bbox(&tile_bbox, transformer).unwrap();
+                bounding_volume.extend_region(&content_bounding_volume);
+                let transform = transformer_local.map(|t| {
+                    let origin = t
+                        .reproject(&[
+                            (tile_content_bbox_rw[0] + tile_content_bbox_rw[3]) * 0.5,
+                            (tile_content_bbox_rw[1] + tile_content_bbox_rw[4]) * 0.5,
+                            (tile_content_bbox_rw[2] + tile_content_bbox_rw[5]) * 0.5,
+                        ])
+                        .unwrap();
+                    Transform::from_translation(origin)
+                });
+                Tile {
+                    bounding_volume,
+                    geometric_error: 0.0,
+                    viewer_request_volume: None,
+                    refine: Some(Refinement::Replace),
+                    transform,
+                    content: Some(Content {
+                        bounding_volume: Some(content_bounding_volume),
+                        uri: format!("tiles/{}.glb", tile_id.file_stem(tile_name_prefix)),
+                    }),
+                    id: tile_id,
+                    children: None,
+                    implicit_tiling: None,
+                    extras: None,
+                }
             }
         }
 
+        /// The parent tiles do not have a transform, so the leaf transforms of
+        /// [TilesetOptions::transformer_local] are not compounded.
         fn generate_tiles(
             quadtree: &crate::spatial_structs::QuadTree,
             world: &crate::parser::World,
-            transformer: &Proj,
-            geometric_error_above_leaf: f64,
-            arg_cellsize: u16,
-            arg_minz: Option<i32>,
-            arg_maxz: Option<i32>,
+            options: &TilesetOptions,
         ) -> Tile {
+            let TilesetOptions {
+                transformer,
+                transformer_local,
+                geometric_error_above_leaf,
+                geometric_error_factor,
+                arg_cellsize,
+                arg_minz,
+                arg_maxz,
+                tile_name_prefix,
+                ground_z,
+            } = *options;
+            let tile_ground_z = ground_z.and_then(|g| g.get(&quadtree.id)).copied();
             if !quadtree.children.is_empty() {
+                // The nodes of a quadtree that is split at the median can have less children
                 if quadtree.children.len() != 4 {
-                    warn!("Quadtree does not have 4 children {:?}", &quadtree);
+                    debug!("Quadtree does not have 4 children {:?}", &quadtree);
                 }
                 // Tile bounding volume
                 let mut tile_bbox = quadtree.bbox(&world.grid);
                 // Set the bounding volume height from the content height
-                tile_bbox[2] = world.grid.bbox[2];
+                tile_bbox[2] = tile_ground_z.unwrap_or(world.grid.bbox[2]);
                 tile_bbox[5] = world.grid.bbox[5];
                 let mut bounding_volume =
                     BoundingVolume::region_from_bbox(&tile_bbox, transformer).unwrap();
@@ -148,25 +532,32 @@ pub mod cesium3dtiles {
                     BoundingVolume::Sphere(_) => {}
                 }
 
-                // The geometric error of a tile is computed based on the specified error
-                // for the nodes have leafs as children (assuming all leaf nodes are at the same level)
-                let level_multiplier = (tile_bbox[3] - tile_bbox[0]) / (arg_cellsize as f64) - 2.0;
-                let d = geometric_error_above_leaf * level_multiplier;
-                if d < 0.0 {
-                    debug!("d is negative in parent");
-                }
                 let mut tile_children: Vec<Tile> = Vec::new();
                 for child in quadtree.children.iter() {
-                    tile_children.push(Self::generate_tiles(
-                        child,
-                        world,
-                        transformer,
-                        geometric_error_above_leaf,
-                        arg_cellsize,
-                        arg_minz,
-                        arg_maxz,
-                    ));
+                    tile_children.push(Self::generate_tiles(child, world, options));
                 }
+                let d = match geometric_error_factor {
+                    Some(factor) => geometric_error_from_extent(
+                        &quadtree.bbox(&world.grid),
+                        factor,
+                        &tile_children,
+                    ),
+                    None => {
+                        // The geometric error of a tile is computed based on the specified error
+                        // for the nodes have leafs as children (assuming all leaf nodes are at the same level)
+                        // The number of cells across the tile, along the axis with more
+                        // cells, because the cells are not necessarily square.
+                        let level_multiplier = ((tile_bbox[3] - tile_bbox[0])
+                            / (arg_cellsize[0] as f64))
+                            .max((tile_bbox[4] - tile_bbox[1]) / (arg_cellsize[1] as f64))
+                            - 2.0;
+                        let d = geometric_error_above_leaf * level_multiplier;
+                        if d < 0.0 {
+                            debug!("d is negative in parent");
+                        }
+                        d
+                    }
+                };
                 Tile {
                     id: TileId::from(&quadtree.id),
                     bounding_volume,
@@ -177,35 +568,36 @@ pub mod cesium3dtiles {
                     content: None,
                     children: Some(tile_children),
                     implicit_tiling: None,
+                    extras: None,
                 }
             } else {
-                // Compute the tile content bounding box <-- the bbox of all the cells in a tile
-                let mut tile_content_bbox_qc = crate::spatial_structs::BboxQc([0, 0, 0, 0, 0, 0]);
-                for cellid in quadtree.cells() {
-                    let cell = world.grid.cell(cellid);
-                    if !cell.feature_ids.is_empty() {
-                        tile_content_bbox_qc = world.features[cell.feature_ids[0]].bbox_qc.clone();
-                        break;
-                    }
-                }
-                for cellid in quadtree.cells() {
-                    let cell = world.grid.cell(cellid);
-                    for fi in cell.feature_ids.iter() {
-                        tile_content_bbox_qc.update_with(&world.features[*fi].bbox_qc);
-                    }
-                }
+                // Compute the tile content bounding box <-- the bbox of the features in a tile
+                let tile_content_bbox_qc = features_bbox_qc(
+                    world,
+                    quadtree
+                        .cells()
+                        .iter()
+                        .flat_map(|cellid| world.grid.cell(cellid).feature_ids.iter()),
+                );
+                // Tile bounding volume
+                let mut tile_bbox = quadtree.bbox(&world.grid);
                 // If the limit-minz/maxz arguments are set, also limit the z of the
                 // bounding volume. We could also just use the grid.bbox values to limit the z,
                 // however at this point we don't know if that was computed from the data or set by
                 // the argument. Setting the argument signals intent, so only then do we override
                 // the values.
-                let tile_content_bbox_rw =
-                    tile_content_bbox_qc.to_bbox(&world.transform, arg_minz, arg_maxz);
+                // A leaf without features gets the bbox of its cells as content bounding
+                // volume.
+                let tile_content_bbox_rw = match tile_content_bbox_qc {
+                    Some(bbox_qc) => bbox_qc.to_bbox(&world.transform, arg_minz, arg_maxz),
+                    None => tile_bbox,
+                };
 
-                // Tile bounding volume
-                let mut tile_bbox = quadtree.bbox(&world.grid);
-                // Set the bounding volume height from the content height
-                tile_bbox[2] = tile_content_bbox_rw[2];
+                // Set the bounding volume height from the content height, or start
+                // at the ground if we know the ground elevation.
+                // If the content is below the ground, the bounding volume is extended to
+                // the content further below.
+                tile_bbox[2] = tile_ground_z.unwrap_or(tile_content_bbox_rw[2]);
                 tile_bbox[5] = tile_content_bbox_rw[5];
                 let mut bounding_volume =
                     BoundingVolume::region_from_bbox(&tile_bbox, transformer).unwrap();
@@ -277,19 +669,34 @@ pub mod cesium3dtiles {
                     BoundingVolume::Sphere(_) => {}
                 }
 
+                let transform = transformer_local.map(|t| {
+                    let origin = t
+                        .reproject(&[
+                            (tile_content_bbox_rw[0] + tile_content_bbox_rw[3]) * 0.5,
+                            (tile_content_bbox_rw[1] + tile_content_bbox_rw[4]) * 0.5,
+                            (tile_content_bbox_rw[2] + tile_content_bbox_rw[5]) * 0.5,
+                        ])
+                        .unwrap();
+                    Transform::from_translation(origin)
+                });
+
                 Tile {
                     id: TileId::from(&quadtree.id),
                     bounding_volume,
                     geometric_error: 0.0,
                     viewer_request_volume: None,
                     refine: Some(Refinement::Replace),
-                    transform: None,
+                    transform,
                     content: Some(Content {
                         bounding_volume: Some(content_bounding_voume),
-                        uri: format!("tiles/{}.glb", quadtree.id),
+                        uri: format!(
+                            "tiles/{}.glb",
+                            TileId::from(&quadtree.id).file_stem(tile_name_prefix)
+                        ),
                     }),
                     children: None,
                     implicit_tiling: None,
+                    extras: None,
                 }
             }
         }
@@ -302,11 +709,17 @@ pub mod cesium3dtiles {
         ) -> Self {
             let crs_from = format!(
                 "EPSG:{}",
-                citymodel.metadata.reference_system.to_epsg().unwrap()
+                citymodel
+                    .metadata
+                    .reference_system
+                    .as_ref()
+                    .unwrap()
+                    .to_epsg()
+                    .unwrap()
             );
             // Because we have a boundingVolume.box. For a boundingVolume.region we need 4979.
             let crs_to = "EPSG:4979";
-            let transformer = Proj::new_known_crs(&crs_from, crs_to, None).unwrap();
+            let transformer = Reprojector::new(&crs_from, crs_to).unwrap();
 
             let mut root_children: Vec<Tile> = Vec::with_capacity(grid.length * grid.length);
             for (cellid, cell) in grid {
@@ -354,6 +767,7 @@ pub mod cesium3dtiles {
                     }),
                     children: None,
                     implicit_tiling: None,
+                    extras: None,
                 };
 
                 // LoD 1.3
@@ -370,6 +784,7 @@ pub mod cesium3dtiles {
                     }),
                     children: Some(vec![tile_lod22]),
                     implicit_tiling: None,
+                    extras: None,
                 };
 
                 // LoD 1.2
@@ -387,6 +802,7 @@ pub mod cesium3dtiles {
                     }),
                     children: Some(vec![tile_lod13]),
                     implicit_tiling: None,
+                    extras: None,
                 });
             }
 
@@ -405,15 +821,10 @@ pub mod cesium3dtiles {
                 content: None,
                 children: Some(root_children),
                 implicit_tiling: None,
+                extras: None,
             };
 
-            // Using gltf tile content
-            let mut extensions: Extensions = HashMap::new();
-            let e1 = Extension::ContentGtlf {
-                extensions_used: None,
-                extensions_required: None,
-            };
-            extensions.insert(ExtensionName::ContentGltf, e1);
+            // The glTF content is declared with set_content_extensions after the conversion
 
             Self {
                 asset: Default::default(),
@@ -423,6 +834,7 @@ pub mod cesium3dtiles {
                 extensions_used: None,
                 extensions_required: None,
                 extensions: None,
+                extras: None,
             }
         }
 
@@ -438,6 +850,49 @@ pub mod cesium3dtiles {
             self.root.collect_leaves()
         }
 
+        /// The tiles that have content, which are the leaves and the parent tiles with the
+        /// content of [Tileset::add_lod_content].
+        pub fn collect_content_tiles(&self) -> Vec<&Tile> {
+            let mut tiles: Vec<&Tile> = Vec::new();
+            let mut q = VecDeque::new();
+            q.push_back(&self.root);
+            while let Some(tile) = q.pop_front() {
+                if tile.content.is_some() {
+                    tiles.push(tile);
+                }
+                if let Some(ref children) = tile.children {
+                    q.extend(children.iter());
+                }
+            }
+            tiles
+        }
+
+        /// The tiles whose content file is missing or empty, with the content URI. The URIs
+        /// are resolved against the `dir` of the tileset.
+        pub fn missing_content(&self, dir: &Path) -> Vec<(TileId, String)> {
+            self.collect_content_tiles()
+                .into_iter()
+                .filter_map(|tile| {
+                    let uri = &tile.content.as_ref()?.uri;
+                    match dir.join(uri).metadata() {
+                        Ok(metadata) if metadata.is_file() && metadata.len() > 0 => None,
+                        _ => Some((tile.id.clone(), uri.clone())),
+                    }
+                })
+                .collect()
+        }
+
+        /// Add content to the parent tiles that are at most `levels` levels above their
+        /// deepest leaf, so that the viewer shows coarse content before the leaves are
+        /// loaded. The content of a parent tile must be replaced by the content of its
+        /// children, so the refinement must be `REPLACE`.
+        /// The parent tiles whose geometric error is not larger than the error of their
+        /// children do not get content, because their content would never be refined.
+        /// Returns the number of parent tiles that got content.
+        pub fn add_lod_content(&mut self, levels: u16, tile_name_prefix: &str) -> usize {
+            self.root.add_lod_content(levels, tile_name_prefix)
+        }
+
         pub fn add_content(&mut self, levels_up: Option<u16>) {
             self.root.add_content_from_level(levels_up);
         }
@@ -455,6 +910,7 @@ pub mod cesium3dtiles {
             grid: &SquareGrid,
             qtree: &QuadTree,
             grid_export: bool,
+            tile_name_prefix: &str,
         ) -> (Vec<(Tile, TileId)>, Vec<(TileId, Vec<u8>)>) {
             let mut subtrees_vec: Vec<(TileId, Vec<u8>)> = Vec::new();
             let mut flat_tiles_with_content: Vec<(Tile, TileId)> = Vec::new();
@@ -758,7 +1214,7 @@ pub mod cesium3dtiles {
 
             self.root.content = Some(Content {
                 bounding_volume: None,
-                uri: "tiles/{level}/{x}/{y}.glb".to_string(),
+                uri: format!("tiles/{{level}}/{{x}}/{}{{y}}.glb", tile_name_prefix),
             });
             self.root.children = None;
             (flat_tiles_with_content, subtrees_vec)
@@ -898,19 +1354,234 @@ pub mod cesium3dtiles {
         /// Prune the tileset by removing the tiles in `tiles_to_remove`.
         /// In addition, it also removes that with `nr_items == 0`.
         pub fn prune(&mut self, tiles_to_remove: &Vec<Tile>, qtree: &QuadTree) {
-            self.root.prune(tiles_to_remove, qtree);
+            if self.root.children.is_some() && tiles_to_remove.contains(&self.root) {
+                self.root.content = None;
+            }
+            self.root.prune(tiles_to_remove, &|tileid| {
+                let qtree_nodeid: QuadTreeNodeId = tileid.into();
+                qtree.node(&qtree_nodeid).map(|node| node.nr_items)
+            });
+        }
+
+        /// Same as [Tileset::prune], for a tileset that was created with
+        /// [Tileset::from_octree].
+        pub fn prune_octree(&mut self, tiles_to_remove: &Vec<Tile>, octree: &Octree) {
+            self.root.prune(tiles_to_remove, &|tileid| {
+                octree
+                    .node(&OctreeNodeId::from(tileid))
+                    .map(|node| node.nr_items)
+            });
+        }
+
+        /// Keep only the leaf tiles for which `keep` returns true, and the parents of the
+        /// kept leaves. Returns the number of leaves that are kept.
+        pub fn retain_leaves(&mut self, keep: &dyn Fn(&TileId) -> bool) -> usize {
+            self.root.retain_leaves(keep)
+        }
+
+        /// Replace the `region` bounding volumes of the tiles and their content with `box`
+        /// bounding volumes in EPSG:4978. The box is aligned with the EPSG:4978 axes and
+        /// it contains the region, thus it is larger than the region.
+        /// Must not be used with tile transforms, because a box is transformed with the
+        /// tile, but a region is not.
+        pub fn use_box_bounding_volumes(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            let transformer = Reprojector::new("EPSG:4979", "EPSG:4978")?;
+            self.root.use_box_bounding_volumes(&transformer)
+        }
+
+        /// Set the refinement of all the tiles.
+        pub fn set_refinement(&mut self, refinement: Refinement) {
+            self.root.set_refinement(refinement);
+        }
+
+        /// Set the `tilesetVersion` and the `generator` of the asset. The default
+        /// generator is `tyler <version>`.
+        pub fn set_asset(&mut self, tileset_version: Option<String>, generator: Option<String>) {
+            self.asset.tileset_version = tileset_version;
+            if generator.is_some() {
+                self.asset.generator = generator;
+            }
+        }
+
+        /// Prepend the `prefix` to the content URIs of the tiles, eg. to serve the tiles
+        /// from a different location than the tileset.json. The prefix and the URI are
+        /// joined with exactly one '/'. An empty prefix leaves the URIs unchanged.
+        pub fn set_content_uri_prefix(&mut self, prefix: &str) {
+            let prefix = prefix.trim_end_matches('/');
+            if !prefix.is_empty() {
+                self.root.set_content_uri_prefix(prefix);
+            }
+        }
+
+        /// Set the attributions of the source data, as HTML, in the `asset.extras.cesium.credits`
+        /// of the tileset. The duplicate attributions are removed.
+        pub fn set_attributions(&mut self, attributions: Vec<String>) {
+            let mut credits: Vec<Credit> = Vec::with_capacity(attributions.len());
+            for html in attributions {
+                let credit = Credit { html };
+                if !credits.contains(&credit) {
+                    credits.push(credit);
+                }
+            }
+            self.asset.extras = (!credits.is_empty()).then_some(AssetExtras {
+                cesium: CesiumAssetExtras { credits },
+            });
+        }
+
+        /// The attributions of the source data, see [Tileset::set_attributions].
+        pub fn attributions(&self) -> Vec<String> {
+            self.asset
+                .extras
+                .iter()
+                .flat_map(|extras| extras.cesium.credits.iter())
+                .map(|credit| credit.html.clone())
+                .collect()
+        }
+
+        /// Set the `extras` of the tileset. No `extras` are written if it is empty.
+        pub fn set_extras(&mut self, extras: BTreeMap<String, String>) {
+            self.extras = (!extras.is_empty()).then_some(extras);
+        }
+
+        /// Declare the glTF extensions that are used by the tile content, with the
+        /// `3DTILES_content_gltf` extension.
+        /// Only the extensions that are present in the produced tiles should be passed, so
+        /// that the tileset does not declare extensions that the content does not use (and
+        /// vice versa). If `content_extensions` is empty, no extensions are declared.
+        /// The `.glb` content itself does not need the `3DTILES_content_gltf`, because glTF
+        /// is a core content type of 3D Tiles 1.1, and the content type is given by the
+        /// extension of the content URI. Therefore, the extension is not declared for
+        /// content that is not glTF, which has no glTF extensions.
+        pub fn set_content_extensions(&mut self, content_extensions: &[ExtensionName]) {
+            let mut used: Vec<ExtensionName> = content_extensions.to_vec();
+            used.sort();
+            used.dedup();
+            if used.is_empty() {
+                self.extensions_used = None;
+                self.extensions_required = None;
+                self.extensions = None;
+                return;
+            }
+            let required: Vec<ExtensionName> =
+                used.iter().filter(|e| e.is_required()).copied().collect();
+            self.extensions_required = if required.is_empty() {
+                None
+            } else {
+                Some(vec![ExtensionName::ContentGltf])
+            };
+            self.extensions_used = Some(vec![ExtensionName::ContentGltf]);
+            let mut extensions: Extensions = HashMap::new();
+            extensions.insert(
+                ExtensionName::ContentGltf,
+                Extension::ContentGtlf {
+                    extensions_used: Some(used),
+                    extensions_required: if required.is_empty() {
+                        None
+                    } else {
+                        Some(required)
+                    },
+                },
+            );
+            self.extensions = Some(extensions);
+        }
+
+        /// Compute a prefetch priority for each tile and write it into `extras.priority`.
+        ///
+        /// The priority is the average of the tile's feature count relative to the largest
+        /// feature count of the tiles on the same level, and the closeness of the tile
+        /// center to the center of the root tile. The priorities are normalized to `[0, 1]`
+        /// over the whole tileset, so that the densest, most central tiles get `1.0`.
+        pub fn set_prefetch_priorities(&mut self, qtree: &QuadTree, grid: &SquareGrid) {
+            let root_bbox = qtree.bbox(grid);
+            let root_center = [
+                (root_bbox[0] + root_bbox[3]) * 0.5,
+                (root_bbox[1] + root_bbox[4]) * 0.5,
+            ];
+            let max_distance = ((root_bbox[3] - root_bbox[0]).powi(2)
+                + (root_bbox[4] - root_bbox[1]).powi(2))
+            .sqrt()
+                * 0.5;
+            // The largest feature count per level, so that the tiles with few features
+            // high up in the hierarchy are not favoured over their children.
+            let mut max_items_per_level: HashMap<u16, usize> = HashMap::new();
+            for tile in self.root.flatten(None) {
+                if let Some(node) = qtree.node(&(&tile.id).into()) {
+                    let max_items = max_items_per_level.entry(tile.id.level).or_insert(0);
+                    *max_items = (*max_items).max(node.nr_items);
+                }
+            }
+            let mut priorities: HashMap<TileId, f64> = HashMap::new();
+            for tile in self.root.flatten(None) {
+                let Some(node) = qtree.node(&(&tile.id).into()) else {
+                    continue;
+                };
+                let max_items = max_items_per_level[&tile.id.level];
+                let density = if max_items > 0 {
+                    node.nr_items as f64 / max_items as f64
+                } else {
+                    0.0
+                };
+                let bbox = node.bbox(grid);
+                let distance = (((bbox[0] + bbox[3]) * 0.5 - root_center[0]).powi(2)
+                    + ((bbox[1] + bbox[4]) * 0.5 - root_center[1]).powi(2))
+                .sqrt();
+                let centrality = if max_distance > 0.0 {
+                    1.0 - (distance / max_distance).min(1.0)
+                } else {
+                    1.0
+                };
+                priorities.insert(tile.id.clone(), (density + centrality) * 0.5);
+            }
+            let min = priorities.values().copied().fold(f64::INFINITY, f64::min);
+            let max = priorities
+                .values()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            for priority in priorities.values_mut() {
+                *priority = if max > min {
+                    (*priority - min) / (max - min)
+                } else {
+                    1.0
+                };
+            }
+            self.root.set_priorities(&priorities);
         }
     }
 
     /// [Asset](https://github.com/CesiumGS/3d-tiles/tree/main/specification#asset).
+    /// The `version` is always `1.1`. The `generator` is not part of the specification, but
+    /// additional properties are allowed.
     ///
-    /// Not supported: `extensions, extras`.
-    #[derive(Serialize, Debug, Clone)]
+    /// Not supported: `extensions`.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     #[serde(rename_all = "camelCase")]
     struct Asset {
         version: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         tileset_version: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        generator: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extras: Option<AssetExtras>,
+    }
+
+    /// The `extras` of the [Asset]. CesiumJS shows the `cesium.credits` on screen.
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    struct AssetExtras {
+        #[serde(default)]
+        cesium: CesiumAssetExtras,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    struct CesiumAssetExtras {
+        #[serde(default)]
+        credits: Vec<Credit>,
+    }
+
+    /// An attribution of the source data, as an HTML string.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Credit {
+        html: String,
     }
 
     impl Default for Asset {
@@ -918,6 +1589,8 @@ pub mod cesium3dtiles {
             Self {
                 version: String::from("1.1"),
                 tileset_version: None,
+                generator: Some(format!("tyler {}", clap::crate_version!())),
+                extras: None,
             }
         }
     }
@@ -955,8 +1628,8 @@ pub mod cesium3dtiles {
     }
 
     #[allow(dead_code)]
-    #[derive(Serialize, Default, Debug, Clone, PartialEq, Eq, Hash)]
-    enum ExtensionName {
+    #[derive(Serialize, Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
+    pub enum ExtensionName {
         #[default]
         None,
         #[serde(rename = "3DTILES_content_gltf")]
@@ -967,6 +1640,30 @@ pub mod cesium3dtiles {
         StructuralMetadata,
         #[serde(rename = "3DTILES_implicit_tiling")]
         ImplicitTiling,
+        #[serde(rename = "EXT_meshopt_compression")]
+        MeshoptCompression,
+        #[serde(rename = "KHR_draco_mesh_compression")]
+        DracoMeshCompression,
+        #[serde(rename = "KHR_mesh_quantization")]
+        MeshQuantization,
+        /// KTX2 textures.
+        #[serde(rename = "KHR_texture_basisu")]
+        TextureBasisu,
+    }
+
+    impl ExtensionName {
+        /// Whether a client must support the glTF extension in order to load the content.
+        /// Compressed and quantized data cannot be read without decoding, while the feature
+        /// metadata can be ignored.
+        pub fn is_required(&self) -> bool {
+            matches!(
+                self,
+                ExtensionName::MeshoptCompression
+                    | ExtensionName::DracoMeshCompression
+                    | ExtensionName::MeshQuantization
+                    | ExtensionName::TextureBasisu
+            )
+        }
     }
 
     /// [Tile](https://github.com/CesiumGS/3d-tiles/tree/main/specification#tile).
@@ -989,6 +1686,17 @@ pub mod cesium3dtiles {
         pub children: Option<Vec<Tile>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         implicit_tiling: Option<ImplicitTiling>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        extras: Option<TileExtras>,
+    }
+
+    /// Application-specific data of a [Tile].
+    #[derive(Serialize, Default, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
+    struct TileExtras {
+        /// Prefetch hint for the viewer, in `[0, 1]`, higher is more important.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        priority: Option<f64>,
     }
 
     /// Tile equality is evaluated on the tile ID.
@@ -1096,6 +1804,12 @@ pub mod cesium3dtiles {
             }
         }
 
+        /// The EPSG:4978 coordinates of the origin of the tile content, if the tile has a
+        /// `transform` that localizes its content.
+        pub fn content_origin(&self) -> Option<[f64; 3]> {
+            self.transform.map(|t| t.apply([0.0, 0.0, 0.0]))
+        }
+
         // Adds `Content` to the Tile, by generating a content bounding volume from the
         // tile's bounding volume and a filepath from the tile.id.
         pub fn add_content(&mut self) {
@@ -1105,57 +1819,232 @@ pub mod cesium3dtiles {
             })
         }
 
-        fn prune(&mut self, tiles_to_remove: &Vec<Tile>, qtree: &QuadTree) {
-            if let Some(mut children) = self.children.take() {
-                let mut children_new: Vec<Tile> = Vec::with_capacity(4);
+        fn use_box_bounding_volumes(
+            &mut self,
+            transformer: &Reprojector,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            self.bounding_volume = self.bounding_volume.box_from_region(transformer)?;
+            if let Some(ref mut content) = self.content {
+                if let Some(bounding_volume) = content.bounding_volume {
+                    content.bounding_volume = Some(bounding_volume.box_from_region(transformer)?);
+                }
+            }
+            if let Some(ref mut children) = self.children {
                 for child in children.iter_mut() {
-                    if !tiles_to_remove.contains(&*child) {
-                        let tileid: &TileId = &child.id;
-                        let qtree_nodeid: QuadTreeNodeId = tileid.into();
-                        if let Some(qtree_node) = qtree.node(&qtree_nodeid) {
-                            if qtree_node.nr_items > 0 {
-                                child.prune(tiles_to_remove, qtree);
-                                children_new.push(child.clone());
-                            }
-                        } else {
-                            error!("Did not find matching QuadTree node for TileId {}", tileid);
-                        }
-                    }
+                    child.use_box_bounding_volumes(transformer)?;
                 }
-                self.children = Some(children_new);
             }
+            Ok(())
         }
-    }
 
-    #[derive(Clone, Debug, Default, Eq, PartialEq)]
-    pub struct TileId {
-        pub(crate) x: usize,
-        y: usize,
-        pub(crate) level: u16,
-    }
-
-    impl TileId {
-        pub fn new(x: usize, y: usize, level: u16) -> Self {
-            Self { x, y, level }
+        fn set_refinement(&mut self, refinement: Refinement) {
+            self.refine = Some(refinement);
+            if let Some(ref mut children) = self.children {
+                for child in children.iter_mut() {
+                    child.set_refinement(refinement);
+                }
+            }
+        }
+
+        fn set_content_uri_prefix(&mut self, prefix: &str) {
+            if let Some(ref mut content) = self.content {
+                content.uri = format!("{}/{}", prefix, content.uri.trim_start_matches('/'));
+            }
+            if let Some(ref mut children) = self.children {
+                for child in children.iter_mut() {
+                    child.set_content_uri_prefix(prefix);
+                }
+            }
+        }
+
+        fn set_priorities(&mut self, priorities: &HashMap<TileId, f64>) {
+            if let Some(priority) = priorities.get(&self.id) {
+                self.extras.get_or_insert_with(Default::default).priority = Some(*priority);
+            }
+            if let Some(ref mut children) = self.children {
+                for child in children.iter_mut() {
+                    child.set_priorities(priorities);
+                }
+            }
+        }
+
+        /// The number of levels below the tile down to its deepest leaf, which is 0 for a
+        /// leaf.
+        pub fn height(&self) -> u16 {
+            self.max_level() - self.id.level
+        }
+
+        fn add_lod_content(&mut self, levels: u16, tile_name_prefix: &str) -> usize {
+            let Some(ref mut children) = self.children else {
+                return 0;
+            };
+            let mut nr_tiles: usize = children
+                .iter_mut()
+                .map(|child| child.add_lod_content(levels, tile_name_prefix))
+                .sum();
+            let children_geometric_error = children
+                .iter()
+                .map(|child| child.geometric_error)
+                .fold(0.0, f64::max);
+            // The content of the parent is the simplified content of its children
+            let children_content_extent = children.iter().filter_map(Tile::content_extent).reduce(
+                |mut extent, child_extent| {
+                    extent.extend_region(&child_extent);
+                    extent
+                },
+            );
+            if self.height() <= levels {
+                if self.geometric_error > children_geometric_error {
+                    self.content = Some(Content {
+                        bounding_volume: Some(
+                            children_content_extent.unwrap_or(self.bounding_volume),
+                        ),
+                        uri: format!("tiles/{}.glb", self.id.file_stem(tile_name_prefix)),
+                    });
+                    nr_tiles += 1;
+                } else {
+                    debug!(
+                        "Not adding content to the tile {}, because its geometric error {} is not larger than the error of its children",
+                        &self.id, self.geometric_error
+                    );
+                }
+            }
+            nr_tiles
+        }
+
+        /// The content bounding volume of the tile, or else the extent of the content
+        /// bounding volumes of its descendants. `None` if none of them have a content
+        /// bounding volume.
+        fn content_extent(&self) -> Option<BoundingVolume> {
+            if let Some(bounding_volume) = self
+                .content
+                .as_ref()
+                .and_then(|content| content.bounding_volume)
+            {
+                return Some(bounding_volume);
+            }
+            self.children
+                .iter()
+                .flatten()
+                .filter_map(Tile::content_extent)
+                .reduce(|mut extent, child_extent| {
+                    extent.extend_region(&child_extent);
+                    extent
+                })
+        }
+
+        /// See [Tileset::retain_leaves]. A parent tile is removed if none of its leaves are
+        /// kept.
+        fn retain_leaves(&mut self, keep: &dyn Fn(&TileId) -> bool) -> usize {
+            match self.children {
+                Some(ref mut children) => {
+                    let mut nr_leaves = 0;
+                    children.retain_mut(|child| {
+                        let nr_leaves_child = child.retain_leaves(keep);
+                        nr_leaves += nr_leaves_child;
+                        nr_leaves_child > 0
+                    });
+                    nr_leaves
+                }
+                None => usize::from(keep(&self.id)),
+            }
+        }
+
+        /// Remove the `tiles_to_remove` and the tiles without items. The number of items
+        /// of a tile is looked up with `nr_items`, which returns `None` if the tile does not
+        /// have a matching node in the spatial index.
+        /// A parent tile in `tiles_to_remove` only loses its content, see
+        /// [Tileset::add_lod_content].
+        fn prune(
+            &mut self,
+            tiles_to_remove: &Vec<Tile>,
+            nr_items: &dyn Fn(&TileId) -> Option<usize>,
+        ) {
+            if let Some(mut children) = self.children.take() {
+                let mut children_new: Vec<Tile> = Vec::with_capacity(4);
+                for child in children.iter_mut() {
+                    let remove = tiles_to_remove.contains(&*child);
+                    if remove && child.children.is_some() {
+                        child.content = None;
+                    }
+                    if !remove || child.children.is_some() {
+                        let tileid: &TileId = &child.id;
+                        if let Some(node_nr_items) = nr_items(tileid) {
+                            if node_nr_items > 0 {
+                                child.prune(tiles_to_remove, nr_items);
+                                children_new.push(child.clone());
+                            }
+                        } else {
+                            error!("Did not find matching QuadTree node for TileId {}", tileid);
+                        }
+                    }
+                }
+                self.children = Some(children_new);
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+    pub struct TileId {
+        pub(crate) x: usize,
+        y: usize,
+        /// Only the tiles that are created from an [Octree] have a z.
+        z: Option<usize>,
+        pub(crate) level: u16,
+    }
+
+    impl TileId {
+        pub fn new(x: usize, y: usize, level: u16) -> Self {
+            Self {
+                x,
+                y,
+                z: None,
+                level,
+            }
+        }
+
+        /// The path of the tile content file, relative to the tiles directory and without
+        /// extension. The `prefix` is prepended to the file name.
+        pub fn file_stem(&self, prefix: &str) -> String {
+            match self.z {
+                Some(z) => format!("{}/{}/{}/{}{}", self.level, self.x, self.y, prefix, z),
+                None => format!("{}/{}/{}{}", self.level, self.x, prefix, self.y),
+            }
         }
     }
 
     impl Display for TileId {
         fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}/{}/{}", self.level, self.x, self.y)
+            match self.z {
+                Some(z) => write!(f, "{}/{}/{}/{}", self.level, self.x, self.y, z),
+                None => write!(f, "{}/{}/{}", self.level, self.x, self.y),
+            }
         }
     }
 
     impl From<&QuadTreeNodeId> for TileId {
         fn from(value: &QuadTreeNodeId) -> Self {
+            Self::new(value.x, value.y, value.level)
+        }
+    }
+
+    impl From<&OctreeNodeId> for TileId {
+        fn from(value: &OctreeNodeId) -> Self {
             Self {
                 x: value.x,
                 y: value.y,
+                z: Some(value.z),
                 level: value.level,
             }
         }
     }
 
+    impl From<&TileId> for OctreeNodeId {
+        fn from(value: &TileId) -> Self {
+            OctreeNodeId::new(value.x, value.y, value.z.unwrap_or(0), value.level)
+        }
+    }
+
     impl Into<QuadTreeNodeId> for &TileId {
         fn into(self) -> QuadTreeNodeId {
             QuadTreeNodeId::new(self.x, self.y, self.level)
@@ -1171,9 +2060,94 @@ pub mod cesium3dtiles {
         }
     }
 
+    /// The bbox of the features `feature_ids` of the `world`, or `None` if there are no
+    /// features.
+    fn features_bbox_qc<'a>(
+        world: &crate::parser::World,
+        feature_ids: impl IntoIterator<Item = &'a usize>,
+    ) -> Option<BboxQc> {
+        feature_ids.into_iter().fold(None, |bbox_qc, fid| {
+            let feature_bbox_qc = &world.features[*fid].bbox_qc;
+            Some(match bbox_qc {
+                Some(mut bbox_qc) => {
+                    bbox_qc.update_with(feature_bbox_qc);
+                    bbox_qc
+                }
+                None => feature_bbox_qc.clone(),
+            })
+        })
+    }
+
+    /// The geometric error of a parent tile, which is the diagonal of the tile `bbox`
+    /// multiplied by `factor`.
+    /// The error is always larger than the error of the `children`, so that the refinement
+    /// is monotonic.
+    fn geometric_error_from_extent(bbox: &Bbox, factor: f64, children: &[Tile]) -> f64 {
+        let diagonal = ((bbox[3] - bbox[0]).powi(2)
+            + (bbox[4] - bbox[1]).powi(2)
+            + (bbox[5] - bbox[2]).powi(2))
+        .sqrt();
+        let geometric_error = diagonal * factor;
+        let max_child = children
+            .iter()
+            .map(|child| child.geometric_error)
+            .fold(0.0, f64::max);
+        if geometric_error > max_child {
+            geometric_error
+        } else {
+            max_child * 2.0
+        }
+    }
+
+    /// The geometric error of the tileset. With a `factor`, it is the error of the
+    /// `root_bbox` extent (the whole dataset), otherwise it is 1.5 times the error of the
+    /// root tile.
+    fn tileset_geometric_error(root: &Tile, root_bbox: &Bbox, factor: Option<f64>) -> f64 {
+        match factor {
+            Some(_) => geometric_error_from_extent(root_bbox, 1.0, std::slice::from_ref(root)),
+            None => root.geometric_error * 1.5,
+        }
+    }
+
+    /// The properties of an existing tileset that are needed for referencing it as an
+    /// external tileset and the attributions of its source data, see [Tileset::merge].
+    #[derive(Deserialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct ExternalTileset {
+        #[serde(default)]
+        asset: Option<Asset>,
+        geometric_error: GeometricError,
+        root: ExternalTilesetRoot,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct ExternalTilesetRoot {
+        bounding_volume: BoundingVolume,
+    }
+
+    /// The path of `path` relative to the directory `dir`. Both paths must be absolute.
+    fn relative_path(dir: &Path, path: &Path) -> PathBuf {
+        let dir_components: Vec<_> = dir.components().collect();
+        let path_components: Vec<_> = path.components().collect();
+        let nr_common = dir_components
+            .iter()
+            .zip(path_components.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut relative = PathBuf::new();
+        for _ in nr_common..dir_components.len() {
+            relative.push("..");
+        }
+        for component in &path_components[nr_common..] {
+            relative.push(component);
+        }
+        relative
+    }
+
     /// [boundingVolume](https://github.com/CesiumGS/3d-tiles/tree/main/specification#bounding-volume).
     #[allow(dead_code)]
-    #[derive(Serialize, Debug, Copy, Clone)]
+    #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
     #[serde(rename_all = "lowercase")]
     enum BoundingVolume {
         Box([f64; 12]),
@@ -1181,6 +2155,27 @@ pub mod cesium3dtiles {
         Sphere([f64; 4]),
     }
 
+    /// The type of the bounding volumes in the tileset.
+    #[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+    pub enum BoundingVolumeType {
+        /// Longitude, latitude and ellipsoidal height bounds in EPSG:4979.
+        #[default]
+        Region,
+        /// Axis-aligned box in EPSG:4978.
+        Box,
+    }
+
+    /// The tiling scheme of the tileset.
+    #[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+    pub enum Tiling {
+        /// The tileset.json contains the tile tree.
+        #[default]
+        Explicit,
+        /// 3D Tiles 1.1 implicit tiling, with the tile availability in subtree files and
+        /// the content URIs templated on the tile coordinates.
+        Implicit,
+    }
+
     impl Default for BoundingVolume {
         fn default() -> Self {
             Self::Region([0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
@@ -1262,30 +2257,79 @@ pub mod cesium3dtiles {
         #[allow(dead_code)]
         fn box_from_bbox(
             bbox: &Bbox,
-            transformer: &Proj,
+            transformer: &Reprojector,
         ) -> Result<Self, Box<dyn std::error::Error>> {
-            let min_coord = transformer.convert((bbox[0], bbox[1], bbox[2]))?;
-            let max_coord = transformer.convert((bbox[3], bbox[4], bbox[5]))?;
+            let min_coord = transformer.reproject(&[bbox[0], bbox[1], bbox[2]])?;
+            let max_coord = transformer.reproject(&[bbox[3], bbox[4], bbox[5]])?;
             debug!(
                 "bounding volume reprojected box dz {}",
-                max_coord.2 - min_coord.2
+                max_coord[2] - min_coord[2]
             );
             Ok(BoundingVolume::from(&[
-                max_coord.0,
-                min_coord.1,
-                min_coord.2,
-                min_coord.0,
-                max_coord.1,
-                max_coord.2,
+                max_coord[0],
+                min_coord[1],
+                min_coord[2],
+                min_coord[0],
+                max_coord[1],
+                max_coord[2],
             ]))
         }
 
+        /// Extend the region so that it contains the `other` region. Other types of
+        /// bounding volumes are not changed.
+        fn extend_region(&mut self, other: &BoundingVolume) {
+            if let (BoundingVolume::Region(region), BoundingVolume::Region(other_region)) =
+                (self, other)
+            {
+                for i in 0..2 {
+                    region[i] = region[i].min(other_region[i]);
+                    region[i + 2] = region[i + 2].max(other_region[i + 2]);
+                }
+                region[4] = region[4].min(other_region[4]);
+                region[5] = region[5].max(other_region[5]);
+            }
+        }
+
+        /// Compute the boundingVolume.box in EPSG:4978 that contains the region. Other
+        /// types of bounding volumes are not changed.
+        ///
+        /// The `transformer` must be from `EPSG:4979` to `EPSG:4978`. Because the region is
+        /// curved in EPSG:4978, its extent is computed from a grid of points on its top and
+        /// bottom, and not only from its corners.
+        fn box_from_region(
+            &self,
+            transformer: &Reprojector,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            let [west, south, east, north, minh, maxh] = match self {
+                BoundingVolume::Region(region) => *region,
+                _ => return Ok(*self),
+            };
+            let mut bbox: Bbox = [f64::MAX, f64::MAX, f64::MAX, f64::MIN, f64::MIN, f64::MIN];
+            for i in 0..3 {
+                let lon = west + (east - west) * i as f64 * 0.5;
+                for j in 0..3 {
+                    let lat = south + (north - south) * j as f64 * 0.5;
+                    for h in [minh, maxh] {
+                        let [x, y, z] =
+                            transformer.reproject(&[lon.to_degrees(), lat.to_degrees(), h])?;
+                        bbox[0] = bbox[0].min(x);
+                        bbox[1] = bbox[1].min(y);
+                        bbox[2] = bbox[2].min(z);
+                        bbox[3] = bbox[3].max(x);
+                        bbox[4] = bbox[4].max(y);
+                        bbox[5] = bbox[5].max(z);
+                    }
+                }
+            }
+            Ok(BoundingVolume::from(&bbox))
+        }
+
         fn region_from_bbox(
             bbox: &Bbox,
-            transformer: &Proj,
+            transformer: &Reprojector,
         ) -> Result<Self, Box<dyn std::error::Error>> {
-            let (west, south, minh) = transformer.convert((bbox[0], bbox[1], bbox[2]))?;
-            let (east, north, maxh) = transformer.convert((bbox[3], bbox[4], bbox[5]))?;
+            let [west, south, minh] = transformer.reproject(&[bbox[0], bbox[1], bbox[2]])?;
+            let [east, north, maxh] = transformer.reproject(&[bbox[3], bbox[4], bbox[5]])?;
             Ok(BoundingVolume::Region([
                 west.to_radians(),
                 south.to_radians(),
@@ -1298,18 +2342,44 @@ pub mod cesium3dtiles {
     }
 
     /// [Tile.refine](https://github.com/CesiumGS/3d-tiles/tree/main/specification#tilerefine).
-    #[allow(dead_code)]
-    #[derive(Serialize, Debug, Clone)]
+    #[derive(Serialize, Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
     #[serde(rename_all = "UPPERCASE")]
-    enum Refinement {
+    pub enum Refinement {
+        /// The content of the children is rendered in addition to the content of the parent.
         Add,
+        /// The content of the children replaces the content of the parent.
+        #[default]
         Replace,
     }
 
     /// [Tile.transform](https://github.com/CesiumGS/3d-tiles/tree/main/specification#tiletransform)
+    ///
+    /// A 4x4 affine transformation matrix, stored in column-major order.
     #[derive(Serialize, Debug, Copy, Clone)]
     struct Transform([f64; 16]);
 
+    impl Transform {
+        #[rustfmt::skip]
+        fn from_translation(translation: [f64; 3]) -> Self {
+            Self([
+                1.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0, 0.0,
+                translation[0], translation[1], translation[2], 1.0,
+            ])
+        }
+
+        /// Transform a point.
+        fn apply(&self, point: [f64; 3]) -> [f64; 3] {
+            let m = &self.0;
+            let mut transformed = [0.0; 3];
+            for (i, coord) in transformed.iter_mut().enumerate() {
+                *coord = m[i] * point[0] + m[4 + i] * point[1] + m[8 + i] * point[2] + m[12 + i];
+            }
+            transformed
+        }
+    }
+
     impl Default for Transform {
         #[rustfmt::skip]
         fn default() -> Self {
@@ -1454,23 +2524,53 @@ pub mod cesium3dtiles {
                 test_data_dir()
                     .join("features_3dbag_5909")
                     .join("3dbag_v21031_7425c21b_5909_subset"),
-                200,
+                Some([200, 200]),
+                10,
                 Some(vec![
                     crate::parser::CityObjectType::Building,
                     crate::parser::CityObjectType::BuildingPart,
                 ]),
                 None,
                 None,
+                None,
+                false,
+                None,
+                None,
+                None,
             )
             .unwrap();
-            world.index_with_grid();
+            world.index_with_grid().unwrap();
 
             world.export_grid();
 
-            let quadtree = QuadTree::from_world(&world, QuadTreeCapacity::Vertices(15000));
+            let quadtree = QuadTree::from_world(
+                &world,
+                QuadTreeCapacity::Vertices(15000),
+                None,
+                crate::spatial_structs::QuadTreeSplit::Center,
+            );
             quadtree.export(&world.grid).unwrap();
 
-            let mut tileset = Tileset::from_quadtree(&quadtree, &world, 16_f64, 200, None, None);
+            let transformer = Reprojector::new(
+                &format!("EPSG:{}", world.crs.to_epsg().unwrap()),
+                "EPSG:4979",
+            )
+            .unwrap();
+            let tileset = Tileset::from_quadtree(
+                &quadtree,
+                &world,
+                &TilesetOptions {
+                    transformer: &transformer,
+                    transformer_local: None,
+                    geometric_error_above_leaf: 16_f64,
+                    geometric_error_factor: None,
+                    arg_cellsize: [200, 200],
+                    arg_minz: None,
+                    arg_maxz: None,
+                    tile_name_prefix: "",
+                    ground_z: None,
+                },
+            );
 
             // tileset.make_implicit(&world.grid, &quadtree, );
 
@@ -1484,11 +2584,513 @@ pub mod cesium3dtiles {
             assert_eq!("1", serde_json::to_string(&a).unwrap());
         }
 
+        #[test]
+        fn test_transform_local_to_global() {
+            let origin = [3_904_856.272, 313_300.581, 5_019_824.654];
+            let tile = Tile {
+                transform: Some(Transform::from_translation(origin)),
+                ..Default::default()
+            };
+            assert_eq!(tile.content_origin(), Some(origin));
+            let global = [3_904_912.5, 313_251.25, 5_019_790.75];
+            let local = [
+                global[0] - origin[0],
+                global[1] - origin[1],
+                global[2] - origin[2],
+            ];
+            let transformed = tile.transform.unwrap().apply(local);
+            for i in 0..3 {
+                assert!((transformed[i] - global[i]).abs() < 1e-6);
+            }
+        }
+
+        #[test]
+        fn test_local_transform_content() -> Result<(), Box<dyn std::error::Error>> {
+            let dir = std::env::temp_dir().join("tyler_test_local_transform_content");
+            let dir_features = dir.join("features");
+            fs::create_dir_all(&dir_features)?;
+            let path_feature = dir_features.join("feature.city.jsonl");
+            fs::copy(
+                test_data_dir().join("3dbag_feature_x71.city.jsonl"),
+                &path_feature,
+            )?;
+            let mut world = crate::parser::World::new(
+                test_data_dir().join("3dbag_x00.city.json"),
+                dir_features.clone(),
+                None,
+                1,
+                None,
+                None,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+            )?;
+            world.index_with_grid()?;
+            let quadtree = QuadTree::from_world(
+                &world,
+                QuadTreeCapacity::Objects(usize::MAX),
+                None,
+                crate::spatial_structs::QuadTreeSplit::Center,
+            );
+            let crs_from = format!("EPSG:{}", world.crs.to_epsg()?);
+            let transformer = Reprojector::new(&crs_from, "EPSG:4979")?;
+            let transformer_local = Reprojector::new(&crs_from, "EPSG:4978")?;
+            let tileset = Tileset::from_quadtree(
+                &quadtree,
+                &world,
+                &TilesetOptions {
+                    transformer: &transformer,
+                    transformer_local: Some(&transformer_local),
+                    geometric_error_above_leaf: 12.0,
+                    geometric_error_factor: None,
+                    arg_cellsize: world.grid.cellsize,
+                    arg_minz: None,
+                    arg_maxz: None,
+                    tile_name_prefix: "",
+                    ground_z: None,
+                },
+            );
+            let mut tiles = vec![&tileset.root];
+            let mut tile_with_content = None;
+            while let Some(tile) = tiles.pop() {
+                if tile.content.is_some() && tile.transform.is_some() {
+                    tile_with_content = Some(tile);
+                }
+                tiles.extend(tile.children.iter().flatten());
+            }
+            let tile = tile_with_content.expect("a leaf with content should have a transform");
+
+            // The content is written with the converter, relative to the tile origin
+            let output_file = dir.join("content.glb");
+            let writer =
+                crate::formats::gltf::GlbWriter::new(&crs_from, world.transform.clone(), None)?;
+            writer.write(
+                &[path_feature.as_path()],
+                tile.content_origin(),
+                &output_file,
+            )?;
+            let glb = fs::read(&output_file)?;
+            let json_length = u32::from_le_bytes(glb[12..16].try_into()?) as usize;
+            let gltf: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_length])?;
+            assert!(gltf["nodes"][0].get("translation").is_none());
+            let bin = &glb[28 + json_length..];
+            let nr_positions = gltf["accessors"][0]["count"].as_u64().unwrap() as usize;
+            assert!(nr_positions > 0);
+
+            let feature: serde_json::Value =
+                serde_json::from_str(&crate::parser::read_feature(&path_feature)?)?;
+            let vertices_global: Vec<[f64; 3]> = feature["vertices"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|vertex| {
+                    let v: Vec<f64> = (0..3)
+                        .map(|i| {
+                            vertex[i].as_f64().unwrap() * world.transform.scale[i]
+                                + world.transform.translate[i]
+                        })
+                        .collect();
+                    transformer_local.reproject(&[v[0], v[1], v[2]]).unwrap()
+                })
+                .collect();
+            for i in 0..nr_positions {
+                let coordinate = |j: usize| -> f64 {
+                    let offset = (i * 3 + j) * 4;
+                    f32::from_le_bytes(bin[offset..offset + 4].try_into().unwrap()) as f64
+                };
+                // The glTF is y-up, the viewer rotates it to z-up before the tile transform
+                let local = [coordinate(0), -coordinate(2), coordinate(1)];
+                let global = tile.transform.unwrap().apply(local);
+                assert!(
+                    vertices_global
+                        .iter()
+                        .any(|vertex| (0..3).all(|j| (vertex[j] - global[j]).abs() < 0.01)),
+                    "{:?} is not a vertex of the feature",
+                    global
+                );
+            }
+            fs::remove_dir_all(&dir)?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_split_external() {
+            // root (level 0) -> 2 tiles (level 1) -> 2 tiles each (level 2) -> leaves (level 3)
+            fn tile(level: u16, x: usize, depth: u16) -> Tile {
+                Tile {
+                    id: TileId::new(x, 0, level),
+                    geometric_error: (depth * 10) as f64,
+                    children: if depth == 0 {
+                        None
+                    } else {
+                        Some(
+                            (0..2)
+                                .map(|i| tile(level + 1, x * 2 + i, depth - 1))
+                                .collect(),
+                        )
+                    },
+                    ..Default::default()
+                }
+            }
+            let tileset = Tileset {
+                root: tile(0, 0, 3),
+                ..Default::default()
+            };
+            let (split, externals) = tileset.split_external(1);
+            // The level 1 tiles are cut
+            let inline = split.root.children.as_ref().unwrap();
+            assert!(inline.iter().all(|t| t.children.is_none()));
+            assert_eq!(
+                inline[0].content.as_ref().unwrap().uri,
+                "tileset_1_0_0.json"
+            );
+            // 2 external tilesets for the level 1 tiles, 4 for the level 2 tiles.
+            assert_eq!(externals.len(), 6);
+            let (uri, external) = &externals[0];
+            assert_eq!(uri, "tileset_2_0_0.json");
+            assert_eq!(external.geometric_error, 10.0);
+            assert_eq!(external.root.geometric_error, 10.0);
+        }
+
+        #[test]
+        fn test_sample() {
+            let tileset = Tileset::sample().unwrap();
+            assert_eq!(tileset.version(), "1.1");
+            let j = serde_json::to_value(&tileset).unwrap();
+            assert_eq!(j["asset"]["version"], "1.1");
+            assert!(j["root"]["content"]["uri"].is_string());
+            assert!(j["root"].get("children").is_none());
+        }
+
+        #[test]
+        fn test_merge() {
+            let dir = std::env::temp_dir().join("tyler_test_merge");
+            for (region, west, geometric_error) in [("a", 0.1, 100.0), ("b", 0.2, 200.0)] {
+                std::fs::create_dir_all(dir.join(region)).unwrap();
+                let mut tileset = Tileset {
+                    geometric_error,
+                    root: Tile {
+                        bounding_volume: BoundingVolume::Region([west, 0.5, 0.3, 0.6, 1.0, 50.0]),
+                        geometric_error,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                tileset.set_attributions(vec![
+                    "© Kadaster".to_string(),
+                    format!("© Municipality {}", region),
+                ]);
+                tileset
+                    .to_file(dir.join(region).join("tileset.json"))
+                    .unwrap();
+            }
+            std::fs::create_dir_all(dir.join("merged")).unwrap();
+            let merged = Tileset::merge(
+                &[
+                    dir.join("a").join("tileset.json"),
+                    dir.join("b").join("tileset.json"),
+                ],
+                &dir.join("merged").join("tileset.json"),
+            )
+            .unwrap();
+            let children = merged.root.children.as_ref().unwrap();
+            assert_eq!(
+                children[1].content.as_ref().unwrap().uri,
+                "../b/tileset.json"
+            );
+            assert_eq!(children[1].geometric_error, 200.0);
+            assert_eq!(merged.root.geometric_error, 200.0);
+            let BoundingVolume::Region(region) = merged.root.bounding_volume else {
+                panic!("the merged bounding volume must be a region");
+            };
+            assert_eq!(region, [0.1, 0.5, 0.3, 0.6, 1.0, 50.0]);
+            assert_eq!(
+                merged.attributions(),
+                ["© Kadaster", "© Municipality a", "© Municipality b"]
+            );
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn test_geometric_error_from_extent() {
+            let bbox: Bbox = [0.0, 0.0, 0.0, 30.0, 40.0, 0.0];
+            assert_eq!(geometric_error_from_extent(&bbox, 0.5, &[]), 25.0);
+            let child = Tile {
+                geometric_error: 40.0,
+                ..Default::default()
+            };
+            assert_eq!(geometric_error_from_extent(&bbox, 0.5, &[child]), 80.0);
+        }
+
         #[test]
         fn test_refinement() {
             let r = Refinement::Replace;
             let j = serde_json::to_string(&r).unwrap();
             assert_eq!(j, r#""REPLACE""#.to_string());
+            let j = serde_json::to_string(&Refinement::Add).unwrap();
+            assert_eq!(j, r#""ADD""#.to_string());
+        }
+
+        #[test]
+        fn test_asset_extras() {
+            let mut tileset = Tileset::default();
+            let j = serde_json::to_value(&tileset).unwrap();
+            assert_eq!(j["asset"]["version"], "1.1");
+            assert!(j["asset"]["generator"]
+                .as_str()
+                .unwrap()
+                .starts_with("tyler "));
+            assert!(j.get("extras").is_none());
+            tileset.set_asset(Some("2024.1".to_string()), Some("pipeline".to_string()));
+            tileset.set_extras(BTreeMap::from([(
+                "project".to_string(),
+                "rotterdam".to_string(),
+            )]));
+            let j = serde_json::to_value(&tileset).unwrap();
+            assert_eq!(j["asset"]["tilesetVersion"], "2024.1");
+            assert_eq!(j["asset"]["generator"], "pipeline");
+            assert_eq!(j["extras"]["project"], "rotterdam");
+            assert!(j["asset"].get("extras").is_none());
+            tileset.set_attributions(vec![
+                "© A".to_string(),
+                "© B".to_string(),
+                "© A".to_string(),
+            ]);
+            let j = serde_json::to_value(&tileset).unwrap();
+            assert_eq!(
+                j["asset"]["extras"]["cesium"]["credits"],
+                serde_json::json!([{"html": "© A"}, {"html": "© B"}])
+            );
+        }
+
+        #[test]
+        fn test_add_lod_content() {
+            let leaf = |x: usize, y: usize, level: u16| Tile {
+                id: TileId::new(x, y, level),
+                content: Some(Content {
+                    bounding_volume: Some(BoundingVolume::Region([
+                        x as f64,
+                        y as f64,
+                        x as f64 + 0.5,
+                        y as f64 + 0.5,
+                        0.0,
+                        level as f64,
+                    ])),
+                    uri: format!("tiles/{}/{}/{}.glb", level, x, y),
+                }),
+                ..Default::default()
+            };
+            let parent = |x: usize, y: usize, level: u16, children: Vec<Tile>| Tile {
+                id: TileId::new(x, y, level),
+                geometric_error: 10.0 / (level + 1) as f64,
+                children: Some(children),
+                ..Default::default()
+            };
+            let mut tileset = Tileset {
+                root: parent(
+                    0,
+                    0,
+                    0,
+                    vec![
+                        parent(0, 0, 1, vec![leaf(0, 0, 2), leaf(1, 0, 2)]),
+                        leaf(2, 0, 1),
+                    ],
+                ),
+                ..Default::default()
+            };
+            assert_eq!(tileset.add_lod_content(1, ""), 1);
+            let content_tiles: Vec<String> = tileset
+                .collect_content_tiles()
+                .iter()
+                .map(|tile| tile.id.to_string())
+                .collect();
+            assert_eq!(content_tiles, ["1/0/0", "1/2/0", "2/0/0", "2/1/0"]);
+            assert_eq!(tileset.root.height(), 2);
+            // The content bounding volume of the parent is the extent of its children's
+            // content, not the tile bounding volume
+            let parent_content = tileset.root.children.as_ref().unwrap()[0]
+                .content
+                .as_ref()
+                .unwrap();
+            assert!(matches!(
+                parent_content.bounding_volume,
+                Some(BoundingVolume::Region([0.0, 0.0, 1.5, 0.5, 0.0, 2.0]))
+            ));
+            // A parent that failed to convert keeps its children, but not its content
+            tileset
+                .root
+                .prune(&vec![parent(0, 0, 1, vec![])], &|_| Some(1));
+            let content_tiles: Vec<String> = tileset
+                .collect_content_tiles()
+                .iter()
+                .map(|tile| tile.id.to_string())
+                .collect();
+            assert_eq!(content_tiles, ["1/2/0", "2/0/0", "2/1/0"]);
+        }
+
+        #[test]
+        fn test_prefetch_priorities() -> Result<(), Box<dyn std::error::Error>> {
+            let dir_features = std::env::temp_dir().join("tyler_test_prefetch_priorities");
+            fs::create_dir_all(&dir_features)?;
+            fs::copy(
+                test_data_dir().join("3dbag_feature_x71.city.jsonl"),
+                dir_features.join("feature.city.jsonl"),
+            )?;
+            let mut world = crate::parser::World::new(
+                test_data_dir().join("3dbag_x00.city.json"),
+                dir_features.clone(),
+                Some([50, 50]),
+                1,
+                None,
+                None,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+            )?;
+            world.index_with_grid()?;
+            fs::remove_dir_all(&dir_features)?;
+            // The node with the feature is split down to a single cell, so that there are
+            // tiles of different densities and distances from the center
+            let quadtree = QuadTree::from_world(
+                &world,
+                QuadTreeCapacity::Objects(0),
+                None,
+                crate::spatial_structs::QuadTreeSplit::Center,
+            );
+            let crs_from = format!("EPSG:{}", world.crs.to_epsg()?);
+            let transformer = Reprojector::new(&crs_from, "EPSG:4979")?;
+            let mut tileset = Tileset::from_quadtree(
+                &quadtree,
+                &world,
+                &TilesetOptions {
+                    transformer: &transformer,
+                    transformer_local: None,
+                    geometric_error_above_leaf: 12.0,
+                    geometric_error_factor: None,
+                    arg_cellsize: world.grid.cellsize,
+                    arg_minz: None,
+                    arg_maxz: None,
+                    tile_name_prefix: "",
+                    ground_z: None,
+                },
+            );
+            tileset.set_prefetch_priorities(&quadtree, &world.grid);
+            let priorities: Vec<f64> = tileset
+                .flatten(None)
+                .iter()
+                .filter_map(|tile| tile.extras.as_ref().and_then(|extras| extras.priority))
+                .collect();
+            assert!(priorities.len() > 2, "{:?}", priorities);
+            assert!(priorities.iter().all(|p| (0.0..=1.0).contains(p)));
+            let min = priorities.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = priorities.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            assert_eq!(min, 0.0);
+            assert_eq!(max, 1.0);
+            Ok(())
+        }
+
+        #[test]
+        fn test_missing_content() {
+            let dir = std::env::temp_dir().join("tyler_test_missing_content");
+            std::fs::create_dir_all(dir.join("tiles")).unwrap();
+            std::fs::write(dir.join("tiles").join("1.glb"), "glb").unwrap();
+            std::fs::write(dir.join("tiles").join("2.glb"), "").unwrap();
+            let leaf = |x: usize| Tile {
+                id: TileId::new(x, 0, 1),
+                content: Some(Content {
+                    bounding_volume: None,
+                    uri: format!("tiles/{}.glb", x),
+                }),
+                ..Default::default()
+            };
+            let tileset = Tileset {
+                root: Tile {
+                    children: Some(vec![leaf(1), leaf(2), leaf(3)]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let missing: Vec<String> = tileset
+                .missing_content(&dir)
+                .into_iter()
+                .map(|(_, uri)| uri)
+                .collect();
+            assert_eq!(missing, ["tiles/2.glb", "tiles/3.glb"]);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn test_retain_leaves() {
+            let leaf = |x: usize, y: usize, level: u16| Tile {
+                id: TileId::new(x, y, level),
+                ..Default::default()
+            };
+            let parent = |x: usize, y: usize, level: u16, children: Vec<Tile>| Tile {
+                id: TileId::new(x, y, level),
+                children: Some(children),
+                ..Default::default()
+            };
+            let mut tileset = Tileset {
+                root: parent(
+                    0,
+                    0,
+                    0,
+                    vec![
+                        parent(0, 0, 1, vec![leaf(0, 0, 2), leaf(1, 0, 2)]),
+                        parent(1, 0, 1, vec![leaf(2, 0, 2), leaf(3, 0, 2)]),
+                        leaf(0, 1, 1),
+                    ],
+                ),
+                ..Default::default()
+            };
+            assert_eq!(tileset.retain_leaves(&|tileid| tileid.x == 1), 1);
+            let children = tileset.root.children.as_ref().unwrap();
+            assert_eq!(children.len(), 1);
+            assert_eq!(children[0].id, TileId::new(0, 0, 1));
+            assert_eq!(
+                children[0].children.as_ref().unwrap()[0].id,
+                TileId::new(1, 0, 2)
+            );
+            assert_eq!(tileset.retain_leaves(&|_| false), 0);
+            assert!(tileset.root.children.as_ref().unwrap().is_empty());
+        }
+
+        #[test]
+        fn test_content_uri_prefix() {
+            let tile = || Tile {
+                content: Some(Content {
+                    bounding_volume: None,
+                    uri: String::from("tiles/0/0/0.glb"),
+                }),
+                ..Default::default()
+            };
+            let uri = |tileset: &Tileset| tileset.root.content.as_ref().unwrap().uri.clone();
+            let mut tileset = Tileset {
+                root: tile(),
+                ..Default::default()
+            };
+            tileset.set_content_uri_prefix("");
+            assert_eq!(uri(&tileset), "tiles/0/0/0.glb");
+            for prefix in [
+                "https://cdn.example.com/city/v3",
+                "https://cdn.example.com/city/v3/",
+            ] {
+                let mut tileset = Tileset {
+                    root: tile(),
+                    ..Default::default()
+                };
+                tileset.set_content_uri_prefix(prefix);
+                assert_eq!(
+                    uri(&tileset),
+                    "https://cdn.example.com/city/v3/tiles/0/0/0.glb"
+                );
+            }
         }
 
         #[test]
@@ -1522,6 +3124,7 @@ pub mod cesium3dtiles {
                 extensions_used: Some(vec![ExtensionName::ContentGltf]),
                 extensions_required: Some(vec![ExtensionName::ContentGltf]),
                 extensions: Some(extensions),
+                extras: None,
                 root: Default::default(),
             };
             println!("{}", to_string_pretty(&t).unwrap());
@@ -1547,9 +3150,1032 @@ pub mod cesium3dtiles {
                 extensions_used: Some(vec![ExtensionName::ContentGltf]),
                 extensions_required: Some(vec![ExtensionName::ContentGltf]),
                 extensions: Some(extensions),
+                extras: None,
                 root: Default::default(),
             };
             println!("{}", to_string_pretty(&t).unwrap());
         }
+
+        /// Meshopt compression and quantization must be declared as required, and nothing
+        /// else that the content does not use.
+        #[test]
+        fn test_content_extensions_meshopt() {
+            let mut t = Tileset::default();
+            t.set_content_extensions(&[
+                ExtensionName::MeshFeatures,
+                ExtensionName::MeshoptCompression,
+                ExtensionName::MeshQuantization,
+                ExtensionName::MeshoptCompression,
+            ]);
+            let j = serde_json::to_value(&t).unwrap();
+            assert_eq!(
+                j["extensionsUsed"],
+                serde_json::json!(["3DTILES_content_gltf"])
+            );
+            assert_eq!(
+                j["extensionsRequired"],
+                serde_json::json!(["3DTILES_content_gltf"])
+            );
+            let content_gltf = &j["extensions"]["3DTILES_content_gltf"];
+            assert_eq!(
+                content_gltf["extensionsUsed"],
+                serde_json::json!([
+                    "EXT_mesh_features",
+                    "EXT_meshopt_compression",
+                    "KHR_mesh_quantization"
+                ])
+            );
+            assert_eq!(
+                content_gltf["extensionsRequired"],
+                serde_json::json!(["EXT_meshopt_compression", "KHR_mesh_quantization"])
+            );
+        }
+
+        /// Draco compression replaces meshopt, and it is required too.
+        #[test]
+        fn test_content_extensions_draco() {
+            let mut t = Tileset::default();
+            t.set_content_extensions(&[
+                ExtensionName::MeshFeatures,
+                ExtensionName::DracoMeshCompression,
+                ExtensionName::MeshQuantization,
+            ]);
+            let j = serde_json::to_value(&t).unwrap();
+            let content_gltf = &j["extensions"]["3DTILES_content_gltf"];
+            assert_eq!(
+                content_gltf["extensionsRequired"],
+                serde_json::json!(["KHR_draco_mesh_compression", "KHR_mesh_quantization"])
+            );
+        }
+
+        /// KTX2 textures without meshopt compression must not declare meshopt.
+        #[test]
+        fn test_content_extensions_ktx2() {
+            let mut t = Tileset::default();
+            t.set_content_extensions(&[ExtensionName::TextureBasisu]);
+            let j = serde_json::to_value(&t).unwrap();
+            let content_gltf = &j["extensions"]["3DTILES_content_gltf"];
+            assert_eq!(
+                content_gltf["extensionsUsed"],
+                serde_json::json!(["KHR_texture_basisu"])
+            );
+            assert_eq!(
+                content_gltf["extensionsRequired"],
+                serde_json::json!(["KHR_texture_basisu"])
+            );
+
+            // Nothing is declared when the content does not use any extension
+            t.set_content_extensions(&[]);
+            let j = serde_json::to_value(&t).unwrap();
+            assert!(j.get("extensionsUsed").is_none());
+            assert!(j.get("extensionsRequired").is_none());
+            assert!(j.get("extensions").is_none());
+        }
+    }
+}
+
+pub mod qa {
+    //! Derived data for the quality assurance of the tiles, for instance for taking
+    //! screenshots of each tile with an external tool.
+    use std::fs::File;
+    use std::path::Path;
+
+    use serde::Serialize;
+
+    use crate::proj::Reprojector;
+    use crate::spatial_structs::{Bbox, QuadTree};
+
+    /// A suggested camera for viewing a tile from above.
+    ///
+    /// The coordinates are in the CRS of the input data, and the `_geographic` coordinates
+    /// are in EPSG:4979 (longitude, latitude in degrees, ellipsoidal height in meters).
+    #[derive(Serialize, Debug, Clone)]
+    pub struct TileCamera {
+        pub tile_id: String,
+        pub bbox: Bbox,
+        pub look_at: [f64; 3],
+        pub position: [f64; 3],
+        pub look_at_geographic: [f64; 3],
+        pub position_geographic: [f64; 3],
+    }
+
+    /// Compute the point to look at (the bbox center) and the camera position from a bbox.
+    /// The camera is placed above the bbox center, at a height above the top of the bbox
+    /// that is equal to the longest horizontal side of the bbox, so that the whole tile is
+    /// in view.
+    pub fn camera_from_bbox(bbox: &Bbox) -> ([f64; 3], [f64; 3]) {
+        let dx = bbox[3] - bbox[0];
+        let dy = bbox[4] - bbox[1];
+        let look_at = [
+            bbox[0] + dx * 0.5,
+            bbox[1] + dy * 0.5,
+            bbox[2] + (bbox[5] - bbox[2]) * 0.5,
+        ];
+        let position = [look_at[0], look_at[1], bbox[5] + dx.max(dy)];
+        (look_at, position)
+    }
+
+    /// Compute a camera for each top-level tile, which are the children of the quadtree
+    /// root (or the root itself if it does not have children). Empty tiles are skipped.
+    pub fn camera_manifest(
+        quadtree: &QuadTree,
+        world: &crate::parser::World,
+    ) -> Result<Vec<TileCamera>, Box<dyn std::error::Error>> {
+        let crs_from = format!("EPSG:{}", world.crs.to_epsg()?);
+        let transformer = Reprojector::new(&crs_from, "EPSG:4979")?;
+        let top_level: Vec<&QuadTree> = if quadtree.children.is_empty() {
+            vec![quadtree]
+        } else {
+            quadtree.children.iter().collect()
+        };
+        let mut cameras: Vec<TileCamera> = Vec::with_capacity(top_level.len());
+        for node in top_level.into_iter().filter(|n| n.nr_items > 0) {
+            let bbox = node.bbox(&world.grid);
+            let (look_at, position) = camera_from_bbox(&bbox);
+            let look_at_geographic = transformer.reproject(&look_at)?;
+            let position_geographic = transformer.reproject(&position)?;
+            cameras.push(TileCamera {
+                tile_id: node.id.to_string(),
+                bbox,
+                look_at,
+                position,
+                look_at_geographic,
+                position_geographic,
+            });
+        }
+        Ok(cameras)
+    }
+
+    /// Write the cameras of the top-level tiles to a JSON file.
+    pub fn write_camera_manifest<P: AsRef<Path>>(
+        path: P,
+        quadtree: &QuadTree,
+        world: &crate::parser::World,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let cameras = camera_manifest(quadtree, world)?;
+        let file_out = File::create(path.as_ref())?;
+        serde_json::to_writer(&file_out, &cameras)?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_camera_from_bbox() {
+            let bbox: Bbox = [0.0, 0.0, -5.0, 200.0, 100.0, 45.0];
+            let (look_at, position) = camera_from_bbox(&bbox);
+            assert_eq!(look_at, [100.0, 50.0, 20.0]);
+            assert_eq!(position, [100.0, 50.0, 245.0]);
+        }
+    }
+}
+
+pub mod crs_info {
+    //! Sidecar files that document the georeferencing of the output.
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+
+    use proj_sys::{PJ_WKT_TYPE_PJ_WKT1_ESRI, PJ_WKT_TYPE_PJ_WKT2_2019};
+    use serde::Serialize;
+
+    use crate::parser::Transform;
+    use crate::proj::crs_to_wkt;
+
+    /// The CRS of the input data and how the output is placed.
+    #[derive(Serialize, Debug)]
+    struct CrsInfo<'a> {
+        source_crs: String,
+        source_crs_wkt: String,
+        /// The CityJSON transform of the quantized input coordinates.
+        source_transform: &'a Transform,
+        /// The CRS of the tile bounding volumes (region).
+        bounding_volume_crs: &'static str,
+        /// The CRS of the glTF tile content.
+        content_crs: &'static str,
+        /// Whether the content of each tile is placed by its own tile transform.
+        local_transforms: bool,
+    }
+
+    /// Write the `crs.json` and `crs.prj` files into the `output` directory.
+    /// The `crs.prj` contains the WKT of the input CRS.
+    pub fn write_crs_info<P: AsRef<Path>>(
+        output: P,
+        world: &crate::parser::World,
+        local_transforms: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let source_crs = format!("EPSG:{}", world.crs.to_epsg()?);
+        let crs_info = CrsInfo {
+            source_crs_wkt: crs_to_wkt(&source_crs, PJ_WKT_TYPE_PJ_WKT2_2019)?,
+            source_crs,
+            source_transform: &world.transform,
+            bounding_volume_crs: "EPSG:4979",
+            content_crs: "EPSG:4978",
+            local_transforms,
+        };
+        let file_json = File::create(output.as_ref().join("crs.json"))?;
+        serde_json::to_writer_pretty(&file_json, &crs_info)?;
+        let mut file_prj = File::create(output.as_ref().join("crs.prj"))?;
+        file_prj
+            .write_all(crs_to_wkt(&crs_info.source_crs, PJ_WKT_TYPE_PJ_WKT1_ESRI)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub mod gltf {
+    //! Binary [glTF 2.0](https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html) (GLB)
+    //! tile content, which is written directly from the CityJSONFeatures, without the geof
+    //! subprocess.
+    //!
+    //! The surfaces of the CityObjects are triangulated and written in EPSG:4978, with one
+    //! material per color. The content is not clipped to the tile, simplified or
+    //! compressed, and it does not have the feature IDs and the metadata
+    //! (`EXT_mesh_features`, `EXT_structural_metadata`) of the geof content.
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    use log::debug;
+    use serde::de::IntoDeserializer;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use crate::parser::{read_feature, CityObjectType, Transform};
+    use crate::proj::Reprojector;
+
+    /// The color of the CityObject types that do not have a color set.
+    const DEFAULT_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+    /// The geometry types with surfaces. The other types (points, lines and geometry
+    /// instances) are not written.
+    const SURFACE_GEOMETRY_TYPES: [&str; 5] = [
+        "MultiSurface",
+        "CompositeSurface",
+        "Solid",
+        "MultiSolid",
+        "CompositeSolid",
+    ];
+
+    /// Writes the CityObjects of the features of a tile into a GLB file.
+    #[derive(Debug, Clone)]
+    pub struct GlbWriter {
+        /// From the CRS of the input to EPSG:4978.
+        reprojector: Reprojector,
+        transform: Transform,
+        /// Only these CityObject types are written. All types are written if `None`.
+        cityobject_types: Option<Vec<CityObjectType>>,
+        /// The LoD of the geometry that is written per CityObject type. The highest LoD is
+        /// written for the other types.
+        lods: BTreeMap<CityObjectType, String>,
+        colors: BTreeMap<CityObjectType, [f32; 4]>,
+    }
+
+    impl GlbWriter {
+        /// The `crs_from` is the CRS of the input (eg. "EPSG:7415") and the `transform` is
+        /// the transform of the metadata.
+        pub fn new(
+            crs_from: &str,
+            transform: Transform,
+            cityobject_types: Option<Vec<CityObjectType>>,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            Ok(Self {
+                reprojector: Reprojector::new(crs_from, "EPSG:4978")?,
+                transform,
+                cityobject_types,
+                lods: BTreeMap::new(),
+                colors: BTreeMap::new(),
+            })
+        }
+
+        /// Write the geometry with the `lod` (eg. "2.2") for the CityObjects of the type.
+        pub fn set_lod(&mut self, cotype: CityObjectType, lod: &str) {
+            self.lods.insert(cotype, lod.to_string());
+        }
+
+        /// Set the color of the CityObjects of the type from a hex color (eg. "#FF0000").
+        pub fn set_color(&mut self, cotype: CityObjectType, hex_color: &str) -> Result<(), String> {
+            self.colors.insert(cotype, parse_hex_color(hex_color)?);
+            Ok(())
+        }
+
+        /// Triangulate the features and write them into the `output_file`. The vertices
+        /// are relative to the `origin` (EPSG:4978) if it is set, which is the translation
+        /// of the tile transform. Otherwise, they are relative to the center of the
+        /// content, which is stored as the translation of the glTF node. Returns the
+        /// number of triangles.
+        pub fn write(
+            &self,
+            feature_paths: &[&Path],
+            origin: Option<[f64; 3]>,
+            output_file: &Path,
+        ) -> Result<usize, Box<dyn std::error::Error>> {
+            let mut mesh = Mesh::default();
+            for path in feature_paths {
+                let feature: Feature = serde_json::from_str(&read_feature(path)?)
+                    .map_err(|e| format!("could not parse {:?}: {}", path, e))?;
+                self.add_feature(&feature, &mut mesh)
+                    .map_err(|e| format!("could not reproject {:?}: {}", path, e))?;
+            }
+            if let Some(dir) = output_file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(output_file, mesh.to_glb(origin))?;
+            Ok(mesh.nr_triangles())
+        }
+
+        fn add_feature(
+            &self,
+            feature: &Feature,
+            mesh: &mut Mesh,
+        ) -> Result<(), crate::proj::ProjError> {
+            let mut vertices: Vec<Option<[f64; 3]>> = vec![None; feature.vertices.len()];
+            for cityobject in feature.cityobjects.values() {
+                let Some(cotype) = parse_cityobject_type(&cityobject.cotype) else {
+                    debug!("Skipping the CityObject type {}", &cityobject.cotype);
+                    continue;
+                };
+                if self
+                    .cityobject_types
+                    .as_ref()
+                    .is_some_and(|cotypes| !cotypes.contains(&cotype))
+                {
+                    continue;
+                }
+                let color = self.colors.get(&cotype).copied().unwrap_or(DEFAULT_COLOR);
+                let lod = self.lods.get(&cotype).map(String::as_str);
+                for geometry in select_geometries(&cityobject.geometry, lod) {
+                    let mut surfaces: Vec<&Value> = Vec::new();
+                    collect_surfaces(&geometry.boundaries, &mut surfaces);
+                    for surface in surfaces {
+                        let mut rings: Vec<Vec<[f64; 3]>> = Vec::new();
+                        for ring in surface.as_array().into_iter().flatten() {
+                            let mut points: Vec<[f64; 3]> = Vec::new();
+                            for index in ring.as_array().into_iter().flatten() {
+                                let Some(i) = index.as_u64().map(|i| i as usize) else {
+                                    continue;
+                                };
+                                let Some(vertex) = feature.vertices.get(i) else {
+                                    continue;
+                                };
+                                let point = match vertices[i] {
+                                    Some(point) => point,
+                                    None => {
+                                        let point = self.reprojector.reproject(&[
+                                            vertex[0] as f64 * self.transform.scale[0]
+                                                + self.transform.translate[0],
+                                            vertex[1] as f64 * self.transform.scale[1]
+                                                + self.transform.translate[1],
+                                            vertex[2] as f64 * self.transform.scale[2]
+                                                + self.transform.translate[2],
+                                        ])?;
+                                        vertices[i] = Some(point);
+                                        point
+                                    }
+                                };
+                                points.push(point);
+                            }
+                            rings.push(points);
+                        }
+                        mesh.add_surface(&rings, color);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Feature {
+        /// Sorted by the ID, so that the content is the same in each run.
+        #[serde(rename = "CityObjects")]
+        cityobjects: BTreeMap<String, CityObject>,
+        vertices: Vec<[i64; 3]>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct CityObject {
+        /// A string, because the extension types (eg. '+Foo') are not a [CityObjectType].
+        #[serde(rename = "type")]
+        cotype: String,
+        #[serde(default)]
+        geometry: Vec<Geometry>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Geometry {
+        #[serde(rename = "type")]
+        geometry_type: String,
+        /// A string in CityJSON 1.1, but a number in older files.
+        #[serde(default)]
+        lod: Option<Value>,
+        #[serde(default)]
+        boundaries: Value,
+    }
+
+    impl Geometry {
+        fn lod(&self) -> Option<String> {
+            match self.lod {
+                Some(Value::String(ref lod)) => Some(lod.clone()),
+                Some(Value::Number(ref lod)) => Some(lod.to_string()),
+                _ => None,
+            }
+        }
+    }
+
+    fn parse_cityobject_type(cotype: &str) -> Option<CityObjectType> {
+        CityObjectType::deserialize(
+            IntoDeserializer::<serde::de::value::Error>::into_deserializer(cotype),
+        )
+        .ok()
+    }
+
+    fn parse_hex_color(hex_color: &str) -> Result<[f32; 4], String> {
+        let hex_digits = hex_color.trim_start_matches('#');
+        let channel = |i: usize| -> Result<f32, String> {
+            hex_digits
+                .get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .map(|value| value as f32 / 255.0)
+                .ok_or_else(|| format!("{} is not a hex color, eg. #FF0000", hex_color))
+        };
+        if hex_digits.len() != 6 {
+            return Err(format!("{} is not a hex color, eg. #FF0000", hex_color));
+        }
+        Ok([channel(0)?, channel(2)?, channel(4)?, 1.0])
+    }
+
+    /// The surface geometries with the `lod`, or with the highest LoD if `lod` is not set.
+    fn select_geometries<'a>(geometries: &'a [Geometry], lod: Option<&str>) -> Vec<&'a Geometry> {
+        let surface_geometries = geometries
+            .iter()
+            .filter(|geometry| SURFACE_GEOMETRY_TYPES.contains(&geometry.geometry_type.as_str()));
+        match lod {
+            Some(lod) => surface_geometries
+                .filter(|geometry| geometry.lod().as_deref() == Some(lod))
+                .collect(),
+            None => {
+                let lod_value = |geometry: &Geometry| -> f64 {
+                    geometry
+                        .lod()
+                        .and_then(|lod| lod.parse().ok())
+                        .unwrap_or(0.0)
+                };
+                let surface_geometries: Vec<&Geometry> = surface_geometries.collect();
+                let max_lod = surface_geometries
+                    .iter()
+                    .map(|geometry| lod_value(geometry))
+                    .fold(f64::NEG_INFINITY, f64::max);
+                surface_geometries
+                    .into_iter()
+                    .filter(|geometry| lod_value(geometry) == max_lod)
+                    .collect()
+            }
+        }
+    }
+
+    /// Collect the surfaces (an array of rings of vertex indices) from the nested arrays of
+    /// the `boundaries`, for any depth of solids and shells.
+    fn collect_surfaces<'a>(boundaries: &'a Value, surfaces: &mut Vec<&'a Value>) {
+        let Some(array) = boundaries.as_array() else {
+            return;
+        };
+        let is_surface = array
+            .first()
+            .and_then(Value::as_array)
+            .and_then(|ring| ring.first())
+            .is_some_and(Value::is_number);
+        if is_surface {
+            surfaces.push(boundaries);
+        } else {
+            for child in array {
+                collect_surfaces(child, surfaces);
+            }
+        }
+    }
+
+    /// The triangles of the tile content, in EPSG:4978.
+    #[derive(Debug, Default)]
+    struct Mesh {
+        positions: Vec<[f64; 3]>,
+        normals: Vec<[f64; 3]>,
+        /// The vertex indices of the triangles per color.
+        triangles: Vec<([f32; 4], Vec<u32>)>,
+    }
+
+    impl Mesh {
+        /// Triangulate a surface and add its triangles with a flat normal. The first ring
+        /// is the exterior, the others are the holes.
+        fn add_surface(&mut self, rings: &[Vec<[f64; 3]>], color: [f32; 4]) {
+            let triangles = triangulate(rings);
+            if triangles.is_empty() {
+                return;
+            }
+            let normal = normalize(newell_normal(&rings[0]));
+            let offset = self.positions.len() as u32;
+            for point in rings.iter().flatten() {
+                self.positions.push(*point);
+                self.normals.push(normal);
+            }
+            let indices = match self.triangles.iter_mut().find(|(c, _)| *c == color) {
+                Some((_, indices)) => indices,
+                None => {
+                    self.triangles.push((color, Vec::new()));
+                    &mut self.triangles.last_mut().unwrap().1
+                }
+            };
+            for triangle in triangles {
+                indices.extend(triangle.iter().map(|i| offset + *i as u32));
+            }
+        }
+
+        fn nr_triangles(&self) -> usize {
+            self.triangles
+                .iter()
+                .map(|(_, indices)| indices.len() / 3)
+                .sum()
+        }
+
+        /// The GLB of the mesh. The glTF is y-up, so the EPSG:4978 (z-up) coordinates are
+        /// rotated, and the viewer rotates them back.
+        fn to_glb(&self, origin: Option<[f64; 3]>) -> Vec<u8> {
+            let y_up = |p: [f64; 3]| -> [f64; 3] { [p[0], p[2], -p[1]] };
+            let center = origin.unwrap_or_else(|| {
+                let mut min = [f64::MAX; 3];
+                let mut max = [f64::MIN; 3];
+                for point in self.positions.iter() {
+                    for i in 0..3 {
+                        min[i] = min[i].min(point[i]);
+                        max[i] = max[i].max(point[i]);
+                    }
+                }
+                if self.positions.is_empty() {
+                    [0.0; 3]
+                } else {
+                    [
+                        (min[0] + max[0]) * 0.5,
+                        (min[1] + max[1]) * 0.5,
+                        (min[2] + max[2]) * 0.5,
+                    ]
+                }
+            });
+            let mut bin: Vec<u8> = Vec::new();
+            let mut position_min = [f32::MAX; 3];
+            let mut position_max = [f32::MIN; 3];
+            for point in self.positions.iter() {
+                let local = y_up([
+                    point[0] - center[0],
+                    point[1] - center[1],
+                    point[2] - center[2],
+                ]);
+                for i in 0..3 {
+                    let coordinate = local[i] as f32;
+                    position_min[i] = position_min[i].min(coordinate);
+                    position_max[i] = position_max[i].max(coordinate);
+                    bin.extend_from_slice(&coordinate.to_le_bytes());
+                }
+            }
+            let nr_bytes_positions = bin.len();
+            for normal in self.normals.iter() {
+                for coordinate in y_up(*normal) {
+                    bin.extend_from_slice(&(coordinate as f32).to_le_bytes());
+                }
+            }
+            let mut buffer_views = vec![
+                json!({"buffer": 0, "byteOffset": 0, "byteLength": nr_bytes_positions, "target": 34962}),
+                json!({"buffer": 0, "byteOffset": nr_bytes_positions, "byteLength": nr_bytes_positions, "target": 34962}),
+            ];
+            let nr_vertices = self.positions.len();
+            let mut accessors = vec![
+                json!({"bufferView": 0, "componentType": 5126, "count": nr_vertices, "type": "VEC3",
+                    "min": position_min, "max": position_max}),
+                json!({"bufferView": 1, "componentType": 5126, "count": nr_vertices, "type": "VEC3"}),
+            ];
+            let mut materials = Vec::new();
+            let mut primitives = Vec::new();
+            for (i, (color, indices)) in self.triangles.iter().enumerate() {
+                buffer_views.push(json!({"buffer": 0, "byteOffset": bin.len(),
+                    "byteLength": indices.len() * 4, "target": 34963}));
+                for index in indices {
+                    bin.extend_from_slice(&index.to_le_bytes());
+                }
+                accessors.push(json!({"bufferView": buffer_views.len() - 1,
+                    "componentType": 5125, "count": indices.len(), "type": "SCALAR"}));
+                materials.push(json!({
+                    "pbrMetallicRoughness": {"baseColorFactor": color, "metallicFactor": 0.0, "roughnessFactor": 1.0},
+                    // The orientation of the surfaces is not reliable
+                    "doubleSided": true
+                }));
+                primitives.push(json!({"attributes": {"POSITION": 0, "NORMAL": 1},
+                    "indices": accessors.len() - 1, "material": i, "mode": 4}));
+            }
+            let mut gltf = json!({
+                "asset": {"version": "2.0", "generator": concat!("tyler ", env!("CARGO_PKG_VERSION"))},
+                "scene": 0,
+                "scenes": [{}],
+            });
+            if !primitives.is_empty() {
+                let mut node = json!({"mesh": 0});
+                if origin.is_none() {
+                    node["translation"] = json!(y_up(center));
+                }
+                gltf["scenes"] = json!([{"nodes": [0]}]);
+                gltf["nodes"] = json!([node]);
+                gltf["meshes"] = json!([{"primitives": primitives}]);
+                gltf["materials"] = json!(materials);
+                gltf["accessors"] = json!(accessors);
+                gltf["bufferViews"] = json!(buffer_views);
+                gltf["buffers"] = json!([{"byteLength": bin.len()}]);
+            }
+            let mut json_chunk = gltf.to_string().into_bytes();
+            json_chunk.resize(json_chunk.len().div_ceil(4) * 4, b' ');
+            let mut glb_length = 12 + 8 + json_chunk.len();
+            if !primitives.is_empty() {
+                glb_length += 8 + bin.len();
+            }
+            let mut glb: Vec<u8> = Vec::with_capacity(glb_length);
+            glb.extend_from_slice(b"glTF");
+            glb.extend_from_slice(&2u32.to_le_bytes());
+            glb.extend_from_slice(&(glb_length as u32).to_le_bytes());
+            glb.extend_from_slice(&(json_chunk.len() as u32).to_le_bytes());
+            glb.extend_from_slice(b"JSON");
+            glb.extend_from_slice(&json_chunk);
+            if !primitives.is_empty() {
+                glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+                glb.extend_from_slice(b"BIN\0");
+                glb.extend_from_slice(&bin);
+            }
+            glb
+        }
+    }
+
+    /// The normal of a ring with [Newell's method](https://www.khronos.org/opengl/wiki/Calculating_a_Surface_Normal),
+    /// which is not normalized. Its length is twice the area of the ring.
+    fn newell_normal(ring: &[[f64; 3]]) -> [f64; 3] {
+        let mut normal = [0.0; 3];
+        for (i, current) in ring.iter().enumerate() {
+            let next = ring[(i + 1) % ring.len()];
+            normal[0] += (current[1] - next[1]) * (current[2] + next[2]);
+            normal[1] += (current[2] - next[2]) * (current[0] + next[0]);
+            normal[2] += (current[0] - next[0]) * (current[1] + next[1]);
+        }
+        normal
+    }
+
+    fn normalize(v: [f64; 3]) -> [f64; 3] {
+        let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        if length == 0.0 {
+            return v;
+        }
+        [v[0] / length, v[1] / length, v[2] / length]
+    }
+
+    /// The twice signed area of the 2D triangle, positive if it is counter-clockwise.
+    fn cross(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    }
+
+    /// Do the segments `a`-`b` and `c`-`d` cross each other, apart from their end points?
+    fn segments_cross(a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> bool {
+        if a == c || a == d || b == c || b == d {
+            return false;
+        }
+        let d1 = cross(a, b, c);
+        let d2 = cross(a, b, d);
+        let d3 = cross(c, d, a);
+        let d4 = cross(c, d, b);
+        (d1 > 0.0) != (d2 > 0.0) && (d3 > 0.0) != (d4 > 0.0)
+    }
+
+    /// Triangulate a planar polygon with holes by ear clipping. The first ring is the
+    /// exterior, the others are the holes. The holes are connected to the exterior with a
+    /// bridge, so that the polygon is a single ring. The triangles are the indices of the
+    /// points of the rings, in the order of the rings, and they are oriented like the
+    /// exterior.
+    fn triangulate(rings: &[Vec<[f64; 3]>]) -> Vec<[usize; 3]> {
+        let Some(exterior) = rings.first() else {
+            return Vec::new();
+        };
+        if exterior.len() < 3 {
+            return Vec::new();
+        }
+        if rings.len() == 1 && exterior.len() == 3 {
+            return vec![[0, 1, 2]];
+        }
+        // Project the points on the axis-aligned plane that is the closest to the plane of
+        // the polygon, keeping the orientation of the exterior
+        let normal = newell_normal(exterior);
+        let axis = (0..3)
+            .max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs()))
+            .unwrap();
+        if normal[axis] == 0.0 {
+            return Vec::new();
+        }
+        let (u, v) = match (axis, normal[axis] > 0.0) {
+            (0, true) => (1, 2),
+            (0, false) => (2, 1),
+            (1, true) => (2, 0),
+            (1, false) => (0, 2),
+            (_, true) => (0, 1),
+            (_, false) => (1, 0),
+        };
+        let points: Vec<[f64; 2]> = rings.iter().flatten().map(|p| [p[u], p[v]]).collect();
+        let mut ring_indices: Vec<Vec<usize>> = Vec::with_capacity(rings.len());
+        let mut offset = 0;
+        for ring in rings {
+            ring_indices.push((offset..offset + ring.len()).collect());
+            offset += ring.len();
+        }
+        let signed_area = |ring: &[usize]| -> f64 {
+            (0..ring.len())
+                .map(|i| {
+                    let a = points[ring[i]];
+                    let b = points[ring[(i + 1) % ring.len()]];
+                    a[0] * b[1] - b[0] * a[1]
+                })
+                .sum::<f64>()
+        };
+        let mut polygon = ring_indices.remove(0);
+        if signed_area(&polygon) < 0.0 {
+            polygon.reverse();
+        }
+        let mut holes: Vec<Vec<usize>> = ring_indices
+            .into_iter()
+            .filter(|hole| hole.len() >= 3)
+            .map(|mut hole| {
+                if signed_area(&hole) > 0.0 {
+                    hole.reverse();
+                }
+                hole
+            })
+            .collect();
+        // The holes are bridged from right to left
+        let max_x = |hole: &Vec<usize>| -> f64 {
+            hole.iter()
+                .map(|i| points[*i][0])
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        holes.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+        for h in 0..holes.len() {
+            let hole = &holes[h];
+            let start = (0..hole.len())
+                .max_by(|a, b| points[hole[*a]][0].total_cmp(&points[hole[*b]][0]))
+                .unwrap();
+            let m = points[hole[start]];
+            // The closest vertex of the polygon that can be connected to the hole without
+            // crossing an edge
+            let edges_of = |ring: &[usize]| -> Vec<([f64; 2], [f64; 2])> {
+                (0..ring.len())
+                    .map(|i| (points[ring[i]], points[ring[(i + 1) % ring.len()]]))
+                    .collect()
+            };
+            let mut edges = edges_of(&polygon);
+            for other in holes[h..].iter() {
+                edges.extend(edges_of(other));
+            }
+            let distance = |j: usize| -> f64 {
+                let p = points[polygon[j]];
+                (p[0] - m[0]).powi(2) + (p[1] - m[1]).powi(2)
+            };
+            let visible = |j: usize| -> bool {
+                let p = points[polygon[j]];
+                !edges.iter().any(|(a, b)| segments_cross(p, m, *a, *b))
+            };
+            let bridge = (0..polygon.len())
+                .filter(|j| visible(*j))
+                .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+                .or_else(|| (0..polygon.len()).min_by(|a, b| distance(*a).total_cmp(&distance(*b))))
+                .unwrap();
+            let mut merged: Vec<usize> = polygon[..=bridge].to_vec();
+            merged.extend(hole[start..].iter());
+            merged.extend(hole[..=start].iter());
+            merged.extend(polygon[bridge..].iter());
+            polygon = merged;
+        }
+        // Ear clipping
+        let mut triangles: Vec<[usize; 3]> = Vec::with_capacity(polygon.len());
+        let mut i = 0;
+        let mut nr_failed = 0;
+        while polygon.len() > 3 {
+            let n = polygon.len();
+            let (a, b, c) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            let area = cross(pa, pb, pc);
+            let is_ear = area > 0.0
+                && !polygon.iter().any(|p| {
+                    let pp = points[*p];
+                    pp != pa
+                        && pp != pb
+                        && pp != pc
+                        && cross(pa, pb, pp) >= 0.0
+                        && cross(pb, pc, pp) >= 0.0
+                        && cross(pc, pa, pp) >= 0.0
+                });
+            // A degenerate polygon does not have an ear, then a vertex is clipped anyway
+            if is_ear || area == 0.0 || nr_failed > n {
+                if area != 0.0 {
+                    triangles.push([a, b, c]);
+                }
+                polygon.remove(i);
+                if i >= polygon.len() {
+                    i = 0;
+                }
+                nr_failed = 0;
+            } else {
+                i = (i + 1) % n;
+                nr_failed += 1;
+            }
+        }
+        let (a, b, c) = (polygon[0], polygon[1], polygon[2]);
+        if cross(points[a], points[b], points[c]) != 0.0 {
+            triangles.push([a, b, c]);
+        }
+        triangles
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn area(rings: &[Vec<[f64; 3]>], triangles: &[[usize; 3]]) -> f64 {
+            let points: Vec<[f64; 3]> = rings.iter().flatten().copied().collect();
+            triangles
+                .iter()
+                .map(|[a, b, c]| newell_normal(&[points[*a], points[*b], points[*c]])[2] * 0.5)
+                .sum()
+        }
+
+        #[test]
+        fn test_triangulate() {
+            let square = vec![
+                [0.0, 0.0, 0.0],
+                [4.0, 0.0, 0.0],
+                [4.0, 4.0, 0.0],
+                [0.0, 4.0, 0.0],
+            ];
+            let triangles = triangulate(std::slice::from_ref(&square));
+            assert_eq!(triangles.len(), 2);
+            assert_eq!(area(std::slice::from_ref(&square), &triangles), 16.0);
+            // A concave L-shape
+            let l_shape = vec![
+                [0.0, 0.0, 0.0],
+                [4.0, 0.0, 0.0],
+                [4.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [1.0, 4.0, 0.0],
+                [0.0, 4.0, 0.0],
+            ];
+            let triangles = triangulate(std::slice::from_ref(&l_shape));
+            assert_eq!(triangles.len(), 4);
+            assert_eq!(area(&[l_shape], &triangles), 7.0);
+            // A square with a hole
+            let hole = vec![
+                [1.0, 1.0, 0.0],
+                [1.0, 3.0, 0.0],
+                [3.0, 3.0, 0.0],
+                [3.0, 1.0, 0.0],
+            ];
+            let rings = vec![square, hole];
+            let triangles = triangulate(&rings);
+            assert_eq!(triangles.len(), 8);
+            assert_eq!(area(&rings, &triangles), 12.0);
+            // A clockwise wall keeps its orientation
+            let wall = vec![
+                [0.0, 0.0, 0.0],
+                [0.0, 0.0, 3.0],
+                [0.0, 2.0, 3.0],
+                [0.0, 2.0, 0.0],
+            ];
+            let triangles = triangulate(std::slice::from_ref(&wall));
+            assert_eq!(triangles.len(), 2);
+            let points: Vec<[f64; 3]> = triangles[0].iter().map(|i| wall[*i]).collect();
+            assert!(newell_normal(&points)[0] < 0.0);
+            assert!(newell_normal(&wall)[0] < 0.0);
+            // Degenerate
+            assert!(triangulate(&[vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 1.0, 1.0],
+                [2.0, 2.0, 2.0],
+                [3.0, 3.0, 3.0]
+            ]])
+            .is_empty());
+        }
+
+        #[test]
+        fn test_select_geometries() {
+            let geometries: Vec<Geometry> = serde_json::from_str(
+                r#"[{"type": "Solid", "lod": "1.2", "boundaries": []},
+                    {"type": "Solid", "lod": "2.2", "boundaries": []},
+                    {"type": "MultiPoint", "lod": "3", "boundaries": []},
+                    {"type": "MultiSurface", "lod": 1, "boundaries": []}]"#,
+            )
+            .unwrap();
+            let lods = |lod: Option<&str>| -> Vec<String> {
+                select_geometries(&geometries, lod)
+                    .iter()
+                    .map(|geometry| geometry.lod().unwrap())
+                    .collect()
+            };
+            assert_eq!(lods(None), ["2.2"]);
+            assert_eq!(lods(Some("1.2")), ["1.2"]);
+            assert_eq!(lods(Some("1")), ["1"]);
+            assert!(lods(Some("3")).is_empty());
+        }
+
+        #[test]
+        fn test_collect_surfaces() {
+            let solid = json!([[[[0, 1, 2]], [[0, 2, 3], [4, 5, 6]]], [[[7, 8, 9]]]]);
+            let mut surfaces = Vec::new();
+            collect_surfaces(&solid, &mut surfaces);
+            assert_eq!(surfaces.len(), 3);
+            assert_eq!(surfaces[1], &json!([[0, 2, 3], [4, 5, 6]]));
+            assert_eq!(
+                parse_cityobject_type("Building"),
+                Some(CityObjectType::Building)
+            );
+            assert_eq!(
+                parse_cityobject_type("+GenericCityObject"),
+                Some(CityObjectType::GenericCityObject)
+            );
+            assert_eq!(parse_cityobject_type("+Foo"), None);
+            assert_eq!(parse_hex_color("#FF0000"), Ok([1.0, 0.0, 0.0, 1.0]));
+            assert!(parse_hex_color("red").is_err());
+        }
+
+        #[test]
+        fn test_glb() {
+            let mut mesh = Mesh::default();
+            let square = vec![
+                [10.0, 0.0, 0.0],
+                [14.0, 0.0, 0.0],
+                [14.0, 4.0, 0.0],
+                [10.0, 4.0, 0.0],
+            ];
+            mesh.add_surface(std::slice::from_ref(&square), DEFAULT_COLOR);
+            mesh.add_surface(&[square], [1.0, 0.0, 0.0, 1.0]);
+            assert_eq!(mesh.nr_triangles(), 4);
+            let glb = mesh.to_glb(None);
+            assert_eq!(&glb[0..4], b"glTF");
+            assert_eq!(
+                u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+                glb.len()
+            );
+            let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+            assert_eq!(&glb[16..20], b"JSON");
+            let gltf: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+            assert_eq!(gltf["materials"].as_array().unwrap().len(), 2);
+            assert_eq!(gltf["accessors"][0]["count"], 8);
+            assert_eq!(gltf["accessors"][2]["count"], 6);
+            assert_eq!(gltf["nodes"][0]["translation"], json!([12.0, 0.0, -2.0]));
+            assert_eq!(gltf["accessors"][0]["max"], json!([2.0, 0.0, 2.0]));
+            let bin_length =
+                u32::from_le_bytes(glb[20 + json_length..24 + json_length].try_into().unwrap());
+            assert_eq!(gltf["buffers"][0]["byteLength"], bin_length);
+            assert_eq!(&glb[24 + json_length..28 + json_length], b"BIN\0");
+            // With the origin of a tile transform, the node is not translated
+            let gltf_json_origin = mesh.to_glb(Some([10.0, 0.0, 0.0]));
+            assert!(!String::from_utf8_lossy(&gltf_json_origin).contains("translation"));
+            // An empty tile is a valid glTF without a buffer
+            let glb = Mesh::default().to_glb(None);
+            assert_eq!(glb.len() % 4, 0);
+            assert!(!String::from_utf8_lossy(&glb).contains("buffers"));
+        }
+
+        #[test]
+        fn test_write() -> Result<(), Box<dyn std::error::Error>> {
+            let transform = Transform {
+                scale: [0.001, 0.001, 0.001],
+                translate: [84995.2799868164, 446316.81397216796, -5.333460330963135],
+            };
+            let path_feature = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("data")
+                .join("3dbag_feature_x71.city.jsonl");
+            let output_file = std::env::temp_dir()
+                .join("tyler_test_gltf_write")
+                .join("content.glb");
+            let mut writer = GlbWriter::new("EPSG:7415", transform.clone(), None)?;
+            let nr_triangles = writer.write(&[path_feature.as_path()], None, &output_file)?;
+            assert!(nr_triangles > 0);
+            assert_eq!(&fs::read(&output_file)?[0..4], b"glTF");
+            // The building only has LoD 1.2, 1.3 and 2.2 geometries
+            writer.set_lod(CityObjectType::Building, "3");
+            assert_eq!(
+                writer.write(&[path_feature.as_path()], None, &output_file)?,
+                0
+            );
+            let writer = GlbWriter::new(
+                "EPSG:7415",
+                transform,
+                Some(vec![CityObjectType::PlantCover]),
+            )?;
+            assert_eq!(
+                writer.write(&[path_feature.as_path()], None, &output_file)?,
+                0
+            );
+            fs::remove_dir_all(output_file.parent().unwrap())?;
+            Ok(())
+        }
     }
 }
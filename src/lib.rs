@@ -0,0 +1,2417 @@
+//! Create tiles from 3D city objects encoded as CityJSONFeatures.
+//!
+//! The whole pipeline is run with [run], which is what the `tyler` binary calls. The
+//! steps can also be composed from the [parser::World], the
+//! [spatial_structs::QuadTree] and the [formats::cesium3dtiles::Tileset].
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+pub mod archive;
+pub mod cli;
+mod dem;
+pub mod formats;
+pub mod logging;
+mod memory;
+pub mod parser;
+mod process;
+mod proj;
+mod remote;
+mod rules;
+pub mod spatial_structs;
+mod validate;
+
+use core::time::Duration;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, IsTerminal, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::formats::cesium3dtiles::{ExtensionName, Tile, TileId};
+use crate::process::Cmd;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, log_enabled, warn, Level};
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The wait before the first retry of a failed conversion subprocess. It is doubled for
+/// each further retry of the same tile, at most [RETRY_BACKOFF_MAX_DOUBLINGS] times.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_BACKOFF_MAX_DOUBLINGS: u32 = 6;
+
+#[derive(Debug, Default, Clone)]
+struct SubprocessConfig {
+    output_extension: String,
+    exe: PathBuf,
+    script: PathBuf,
+    timeout: Option<Duration>,
+    /// The glTF extensions that are present in a tile that is produced by the subprocess.
+    content_extensions: Vec<ExtensionName>,
+}
+
+/// The maximum number of quadtrees that are built for finding the `--target-tiles`.
+const TARGET_TILES_MAX_ITERATIONS: usize = 20;
+
+/// A tile that is ready for the conversion subprocess.
+struct TileJob {
+    tile: Tile,
+    tileid: TileId,
+    output_file: PathBuf,
+    bbox: spatial_structs::Bbox,
+    nr_items: usize,
+    start: Instant,
+    cmd: Cmd,
+    cmd_string: String,
+    stdin_data: Option<String>,
+    feature_ids: Vec<usize>,
+}
+
+/// The glTF extensions that are present in the content of a converted tile, or the tile
+/// itself if the conversion failed. The tile is boxed, because it is much larger than
+/// the extensions.
+type TileConversion = Result<Vec<ExtensionName>, Box<Tile>>;
+
+/// The tiles with content that are converted, with the ID that names their content file.
+type ContentTiles = Vec<(Tile, TileId)>;
+
+/// The configuration of a [run]. It has the same fields as the command line arguments,
+/// so it can be created with [clap::Parser::try_parse_from] from a list of arguments, or
+/// with [cli::Cli::parse_with_config_from] to also read a `--config` file.
+pub type TilerConfig = cli::Cli;
+
+/// The result of the conversion of a tile, for the run manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct TileRecord {
+    pub tile_id: String,
+    pub output_file: PathBuf,
+    pub bbox: spatial_structs::Bbox,
+    pub nr_items: usize,
+    pub success: bool,
+    /// The size of the output file, if the conversion succeeded.
+    pub nr_bytes: Option<u64>,
+    /// Wall-clock duration of the conversion, including the retries.
+    pub duration_secs: f64,
+    /// The conversion subprocess was killed, because it did not finish within the
+    /// `--timeout`.
+    pub timed_out: bool,
+    /// The hex-encoded sha256 of the output file, with `--emit-checksums`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// A tile that failed to convert, for the `tyler_errors.json` in the output directory.
+/// The tile can be converted again with the `command`, or with `tyler --only-tile`.
+#[derive(Debug, Clone, Serialize)]
+struct TileError {
+    tile_id: String,
+    /// The command line of the conversion subprocess.
+    command: String,
+    /// The stdout and stderr of the last attempt of the conversion, or the reason why the
+    /// subprocess could not be run.
+    output: String,
+    timed_out: bool,
+}
+
+/// The hex-encoded sha256 of the contents of the file.
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The `--lod-*` and `--color-*` per CityObject type, for the `--converter=native`.
+fn cotype_options(cli: &cli::Cli) -> Vec<(parser::CityObjectType, Option<String>, Option<String>)> {
+    use parser::CityObjectType;
+    vec![
+        (
+            CityObjectType::Building,
+            cli.lod_building.clone(),
+            cli.color_building.clone(),
+        ),
+        (
+            CityObjectType::BuildingPart,
+            cli.lod_building_part.clone(),
+            cli.color_building_part.clone(),
+        ),
+        (
+            CityObjectType::BuildingInstallation,
+            cli.lod_building_installation.clone(),
+            cli.color_building_installation.clone(),
+        ),
+        (
+            CityObjectType::TINRelief,
+            cli.lod_tin_relief.clone(),
+            cli.color_tin_relief.clone(),
+        ),
+        (
+            CityObjectType::Road,
+            cli.lod_road.clone(),
+            cli.color_road.clone(),
+        ),
+        (
+            CityObjectType::Railway,
+            cli.lod_railway.clone(),
+            cli.color_railway.clone(),
+        ),
+        (
+            CityObjectType::TransportSquare,
+            cli.lod_transport_square.clone(),
+            cli.color_transport_square.clone(),
+        ),
+        (
+            CityObjectType::WaterBody,
+            cli.lod_water_body.clone(),
+            cli.color_water_body.clone(),
+        ),
+        (
+            CityObjectType::PlantCover,
+            cli.lod_plant_cover.clone(),
+            cli.color_plant_cover.clone(),
+        ),
+        (
+            CityObjectType::SolitaryVegetationObject,
+            cli.lod_solitary_vegetation_object.clone(),
+            cli.color_solitary_vegetation_object.clone(),
+        ),
+        (
+            CityObjectType::LandUse,
+            cli.lod_land_use.clone(),
+            cli.color_land_use.clone(),
+        ),
+        (
+            CityObjectType::CityFurniture,
+            cli.lod_city_furniture.clone(),
+            cli.color_city_furniture.clone(),
+        ),
+        (
+            CityObjectType::Bridge,
+            cli.lod_bridge.clone(),
+            cli.color_bridge.clone(),
+        ),
+        (
+            CityObjectType::BridgePart,
+            cli.lod_bridge_part.clone(),
+            cli.color_bridge_part.clone(),
+        ),
+        (
+            CityObjectType::BridgeInstallation,
+            cli.lod_bridge_installation.clone(),
+            cli.color_bridge_installation.clone(),
+        ),
+        (
+            CityObjectType::BridgeConstructiveElement,
+            cli.lod_bridge_construction_element.clone(),
+            cli.color_bridge_construction_element.clone(),
+        ),
+        (
+            CityObjectType::Tunnel,
+            cli.lod_tunnel.clone(),
+            cli.color_tunnel.clone(),
+        ),
+        (
+            CityObjectType::TunnelPart,
+            cli.lod_tunnel_part.clone(),
+            cli.color_tunnel_part.clone(),
+        ),
+        (
+            CityObjectType::TunnelInstallation,
+            cli.lod_tunnel_installation.clone(),
+            cli.color_tunnel_installation.clone(),
+        ),
+        (
+            CityObjectType::GenericCityObject,
+            cli.lod_generic_city_object.clone(),
+            cli.color_generic_city_object.clone(),
+        ),
+    ]
+}
+
+/// The writer of the `--converter=native`, with the [cotype_options].
+fn glb_writer(
+    crs_from: &str,
+    world: &parser::World,
+    cotype_options: &[(parser::CityObjectType, Option<String>, Option<String>)],
+) -> Result<formats::gltf::GlbWriter, Box<dyn std::error::Error>> {
+    let mut writer = formats::gltf::GlbWriter::new(
+        crs_from,
+        world.transform.clone(),
+        world.cityobject_types.clone(),
+    )?;
+    for (cotype, lod, color) in cotype_options {
+        if let Some(lod) = lod {
+            writer.set_lod(*cotype, lod);
+        }
+        if let Some(color) = color {
+            writer
+                .set_color(*cotype, color)
+                .map_err(|e| format!("invalid color for {}: {}", cotype, e))?;
+        }
+    }
+    Ok(writer)
+}
+
+/// The summary of a [run], that is also written to `tyler_run.json` in the output
+/// directory. The `tiles` are empty if the run did not convert any tiles, eg. with
+/// `--3dtiles-tileset-only` or `validate`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunReport {
+    pub nr_tiles: usize,
+    pub nr_tiles_empty: usize,
+    pub nr_tiles_failed: usize,
+    /// The number of failed tiles whose conversion timed out.
+    pub nr_tiles_timed_out: usize,
+    /// The number of features that were skipped, because they are invalid.
+    pub nr_features_invalid: usize,
+    /// The IDs of the tiles that are larger than the `--max-tile-bytes`.
+    pub tiles_oversize: Vec<String>,
+    pub duration_secs: f64,
+    pub tiles: Vec<TileRecord>,
+}
+
+/// The directory of the intermediate files, that is removed when the guard is dropped, so
+/// that the files do not leak when the run fails or panics.
+/// If the directory already exists and is not `owned`, eg. a `--tmp-dir` that may contain
+/// other files, only the files and directories that were created through the guard are
+/// removed.
+struct TmpDir {
+    path: PathBuf,
+    owned: bool,
+    keep: bool,
+    created: Mutex<Vec<PathBuf>>,
+}
+
+impl TmpDir {
+    /// Create the directory. If `owned` is false and the directory already exists, only
+    /// the files that are created with [TmpDir::create_dir_all] and [TmpDir::write], or
+    /// remembered with [TmpDir::track_new], are removed on drop.
+    fn create(path: PathBuf, owned: bool, keep: bool) -> std::io::Result<Self> {
+        let owned = owned || !path.exists();
+        fs::create_dir_all(&path)?;
+        Ok(Self {
+            path,
+            owned,
+            keep,
+            created: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Create the directory `dir` and its missing parents, and remember the outermost
+    /// directory that did not exist yet.
+    fn create_dir_all(&self, dir: &Path) -> std::io::Result<()> {
+        let outermost_missing = dir.ancestors().take_while(|d| !d.exists()).last();
+        if let Some(outermost_missing) = outermost_missing {
+            fs::create_dir_all(dir)?;
+            self.track(outermost_missing.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Remember `path` if it does not exist yet, so that it is removed on drop if it is
+    /// created later, eg. by [parser::World::decompress_features].
+    fn track_new(&self, path: &Path) {
+        if !path.exists() {
+            self.track(path.to_path_buf());
+        }
+    }
+
+    /// Write the file `path` and remember it, including the parent directories that
+    /// are created for it.
+    fn write<C: AsRef<[u8]>>(&self, path: &Path, contents: C) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        self.track(path.to_path_buf());
+        Ok(())
+    }
+
+    fn track(&self, path: PathBuf) {
+        if !self.owned {
+            self.created
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(path);
+        }
+    }
+}
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        if self.keep {
+            debug!("Keeping the intermediate files in {:?}", &self.path);
+        } else if self.owned {
+            if let Err(e) = fs::remove_dir_all(&self.path) {
+                warn!("Could not remove the directory {:?}: {}", &self.path, e);
+            }
+        } else {
+            let created = self.created.get_mut().unwrap_or_else(|e| e.into_inner());
+            for path in created.drain(..) {
+                let result = if path.is_dir() {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_file(&path)
+                };
+                // The file may have been removed with its directory already, or by the
+                // converter
+                match result {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        warn!("Could not remove {:?}: {}", &path, e)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::ValueEnum, Eq, PartialEq)]
+#[clap(rename_all = "lower")]
+pub enum Formats {
+    _3DTiles,
+    CityJSON,
+}
+
+impl ToString for Formats {
+    fn to_string(&self) -> String {
+        match self {
+            Formats::_3DTiles => "3DTiles".to_string(),
+            Formats::CityJSON => "CityJSON".to_string(),
+        }
+    }
+}
+
+/// Which failures of the conversion subprocess are retried.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum RetryPolicy {
+    /// Retry on any failure.
+    #[default]
+    Always,
+    /// Only retry if the subprocess was terminated by a signal (eg. segmentation fault),
+    /// but not if it exited with an error code.
+    OnCrash,
+}
+
+impl RetryPolicy {
+    /// A subprocess that we killed because of the timeout did not crash, so it is only
+    /// retried with `RetryPolicy::Always`.
+    fn should_retry(&self, exit_status: &std::process::ExitStatus, timed_out: bool) -> bool {
+        match self {
+            RetryPolicy::Always => !exit_status.success(),
+            RetryPolicy::OnCrash => !timed_out && exit_status.signal().is_some(),
+        }
+    }
+}
+
+/// How the list of feature paths of a tile is passed to the conversion subprocess.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum ConverterInput {
+    /// Write the list to an `.input` file and pass its path with
+    /// `--path_features_input_file`.
+    #[default]
+    File,
+    /// Write the list to the stdin of the subprocess, and pass `/dev/stdin` as the
+    /// `--path_features_input_file`.
+    Stdin,
+}
+
+/// The converter that writes the glTF content of the tiles.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum Converter {
+    /// Run the geof flowchart in a subprocess per tile.
+    #[default]
+    Geof,
+    /// Write the glTF with the [formats::gltf::GlbWriter], without a subprocess.
+    Native,
+}
+
+/// What to do with the leaf tiles that do not have any items.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum EmptyTiles {
+    /// Keep the empty tiles in the tileset.
+    #[default]
+    Skip,
+    /// Remove the empty tiles from the tileset, and the parent tiles that have no leaves
+    /// left. The empty tiles are not converted.
+    Prune,
+}
+
+/// The settings of a [run] that are resolved from the command line arguments, before the
+/// features are indexed.
+struct RunSettings {
+    /// The `--grid-cellsize`, `None` for `auto`.
+    grid_cellsize: Option<[u16; 2]>,
+    geometric_error_above_leaf: f64,
+    max_retries: u32,
+    retry_policy: RetryPolicy,
+    converter_input: ConverterInput,
+    converter: Converter,
+    native_cotype_options: Vec<(parser::CityObjectType, Option<String>, Option<String>)>,
+    converter_rules: Option<rules::ConverterRules>,
+    memory_throttle: Option<memory::MemoryThrottle>,
+    tile_name_prefix: String,
+    /// The pool of the tile conversion, `None` for the global rayon pool.
+    thread_pool: Option<rayon::ThreadPool>,
+    bounding_volume: formats::cesium3dtiles::BoundingVolumeType,
+    spatial_index: spatial_structs::SpatialIndex,
+    lod_levels: Option<u16>,
+    format: Formats,
+    subprocess_config: SubprocessConfig,
+    geof_args: Vec<String>,
+    quadtree_capacity: spatial_structs::QuadTreeCapacity,
+    metadata_class: String,
+    subprocess_env: Vec<(String, String)>,
+    proj_data: Option<String>,
+    /// The `--output` directory.
+    output: PathBuf,
+    path_output_tiles: PathBuf,
+    path_output_tiles_cityjson: PathBuf,
+    /// The directory of the intermediate files, see [TmpDir].
+    path_features_input_dir: PathBuf,
+}
+
+impl RunSettings {
+    /// Resolve the settings from the `cli`, and check the combinations of the options
+    /// that clap cannot check.
+    fn new(cli: &mut cli::Cli, output: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path_output_tiles = output.join("tiles");
+        let path_output_tiles_cityjson = output.join("tiles_cityjson");
+        let path_features_input_dir = cli.tmp_dir.clone().unwrap_or_else(|| output.join("inputs"));
+        // Since we have a default value, we can safely unwrap.
+        let grid_cellsize = match cli.grid_cellsize.unwrap() {
+            crate::cli::GridCellsize::Auto => None,
+            crate::cli::GridCellsize::Size(size) => Some(size),
+        };
+        let geometric_error_above_leaf = cli.geometric_error_above_leaf.unwrap();
+        if let Some(factor) = cli.geometric_error_factor {
+            if factor <= 0.0 {
+                return Err("--geometric-error-factor must be larger than 0".into());
+            }
+        }
+        let max_retries = cli.max_retries.unwrap();
+        let retry_policy = cli.retry_policy.unwrap();
+        let converter_input = cli.converter_input.unwrap();
+        let converter = cli.converter.unwrap();
+        let native_cotype_options = cotype_options(cli);
+        let converter_rules = match cli.converter_rules {
+            Some(ref path) => Some(rules::ConverterRules::from_file(path)?),
+            None => None,
+        };
+        let memory_throttle = cli
+            .max_memory
+            .map(|gb| memory::MemoryThrottle::new((gb * 1024.0 * 1024.0 * 1024.0) as u64));
+        let tile_name_prefix = cli.tile_name_prefix.clone().unwrap_or_default();
+        // The tiles are converted in the global rayon pool, unless the number of threads is
+        // limited.
+        let thread_pool = match cli.threads {
+            Some(num_threads) => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()?,
+            ),
+            None => None,
+        };
+        let bounding_volume = cli.bounding_volume.unwrap();
+        if bounding_volume == formats::cesium3dtiles::BoundingVolumeType::Box
+            && (cli.cesium3dtiles_implicit || cli.cesium3dtiles_local_transforms)
+        {
+            return Err(
+                "--bounding-volume=box cannot be used with implicit tiling and --3dtiles-local-transforms"
+                    .into(),
+            );
+        }
+        let spatial_index = cli.spatial_index.unwrap();
+        let lod_levels = cli.lod_levels.filter(|levels| *levels > 0);
+        if lod_levels.is_some()
+            && (cli.cesium3dtiles_implicit
+                || spatial_index == spatial_structs::SpatialIndex::Octree
+                || cli.refine == Some(formats::cesium3dtiles::Refinement::Add))
+        {
+            return Err(
+                "--lod-levels cannot be used with implicit tiling, --spatial-index=octree and --refine=add"
+                    .into(),
+            );
+        }
+        if let Some(crate::cli::GridCellsize::Size([size_x, size_y])) = cli.grid_cellsize {
+            if size_x != size_y && cli.cesium3dtiles_implicit {
+                return Err(
+                    "--grid-cellsize with different x and y sizes cannot be used with implicit tiling"
+                        .into(),
+                );
+            }
+        }
+        if cli.cesium3dtiles_implicit
+            && cli.qtree_split == Some(spatial_structs::QuadTreeSplit::Median)
+        {
+            return Err("--qtree-split=median cannot be used with implicit tiling".into());
+        }
+        if spatial_index == spatial_structs::SpatialIndex::Octree {
+            if cli.cesium3dtiles_implicit {
+                return Err("--spatial-index=octree cannot be used with implicit tiling".into());
+            }
+            if cli.dem.is_some()
+                || cli.prefetch_hints
+                || cli.export_quadtree.is_some()
+                || cli.quadtree_export
+            {
+                return Err(
+                    "--spatial-index=octree cannot be used with --dem, --prefetch-hints, --export-quadtree and --quadtree-export"
+                        .into(),
+                );
+            }
+        }
+        if tile_name_prefix.contains(std::path::is_separator) {
+            return Err("--tile-name-prefix must not contain a path separator".into());
+        }
+        let format = Formats::_3DTiles; // override --format
+        let subprocess_config = match format {
+            Formats::_3DTiles if converter == Converter::Native => {
+                warn!("--converter=native does not clip the features to the tiles, simplify the LoD tiles, compress the content or write the feature metadata");
+                SubprocessConfig {
+                    output_extension: "glb".to_string(),
+                    exe: PathBuf::new(),
+                    script: PathBuf::new(),
+                    timeout: None,
+                    content_extensions: vec![],
+                }
+            }
+            Formats::_3DTiles => {
+                let exe = match cli.exe_geof.clone() {
+                    Some(exe_g) => {
+                        if !exe_g.is_file() {
+                            return Err(format!(
+                                "the geoflow executable must be an existing file for generating 3D Tiles, exe_geof: {:?}",
+                                &exe_g
+                            )
+                            .into());
+                        }
+                        exe_g
+                    }
+                    None => {
+                        debug!(
+                            "exe_geof is not set for generating 3D Tiles, defaulting to 'geof' in the filesystem PATH"
+                        );
+                        PathBuf::from("geof")
+                    }
+                };
+                let capture_data = Cmd::new(&exe)
+                    .arg("--version")
+                    .arg("--verbose")
+                    .output_merged()
+                    .map_err(|e| format!("could not execute geof ({:?}): {}", &exe, e))?;
+                let plugins_stdout_str = Cmd::new(&exe)
+                    .arg("--list-plugins")
+                    .arg("--verbose")
+                    .output_merged()
+                    .map_err(|e| format!("could not list the plugins of geof ({:?}): {}", &exe, e))?
+                    .stdout;
+                info!(
+                    "geof version:\n{}{}",
+                    capture_data.stdout, plugins_stdout_str
+                );
+                let geof_flowchart_path = cli.resources_dir().join("geof").join("createGLB.json");
+                if !geof_flowchart_path.is_file() {
+                    return Err(format!(
+                        "the geof flowchart {:?} does not exist, set the resources directory with --resources-dir or TYLER_RESOURCES_DIR",
+                        &geof_flowchart_path
+                    )
+                    .into());
+                }
+                let timeout = cli.timeout.map(Duration::from_secs);
+                SubprocessConfig {
+                    output_extension: "glb".to_string(),
+                    exe,
+                    script: geof_flowchart_path,
+                    timeout,
+                    // The createGLB flowchart writes the feature metadata, and it always
+                    // compresses (meshopt, or Draco with --draco) and quantizes the vertices.
+                    content_extensions: vec![
+                        ExtensionName::MeshFeatures,
+                        ExtensionName::StructuralMetadata,
+                        match cli.draco {
+                            true => ExtensionName::DracoMeshCompression,
+                            false => ExtensionName::MeshoptCompression,
+                        },
+                        ExtensionName::MeshQuantization,
+                    ],
+                }
+            }
+            Formats::CityJSON => {
+                // TODO: refactor parallel loop
+                return Err("cityjson output is not supported".into());
+                // if let Some(exe) = cli.exe_python {
+                //     SubprocessConfig {
+                //         output_extension: "city.json".to_string(),
+                //         exe,
+                //         script: cli.resources_dir()
+                //             .join("python")
+                //             .join("convert_cityjsonfeatures.py"),
+                //     }
+                // } else {
+                //     panic!("exe_python must be set for generating CityJSON tiles")
+                // }
+            }
+        };
+        debug!("{:?}", &subprocess_config);
+        let geof_args = cli.geof_arg.clone().unwrap_or_default();
+        for arg in geof_args.iter().filter(|arg| !arg.contains('=')) {
+            warn!(
+                "--geof-arg {} is not in the '--key=value' form that geof requires",
+                arg
+            );
+        }
+        // Since we have a default value, it is safe to unwrap
+        // let qtree_capacity = 0; // override cli.qtree_capacity
+        let qtree_criteria = cli.qtree_capacity_type.clone().unwrap();
+        let quadtree_capacity = match qtree_criteria {
+            spatial_structs::QuadTreeCriteria::Objects => {
+                spatial_structs::QuadTreeCapacity::Objects(cli.qtree_capacity.unwrap())
+            }
+            spatial_structs::QuadTreeCriteria::Vertices => {
+                spatial_structs::QuadTreeCapacity::Vertices(cli.qtree_capacity.unwrap())
+            }
+            spatial_structs::QuadTreeCriteria::Bytes => {
+                spatial_structs::QuadTreeCapacity::Bytes(cli.qtree_capacity.unwrap())
+            }
+            spatial_structs::QuadTreeCriteria::ObjectsAndVertices => {
+                spatial_structs::QuadTreeCapacity::ObjectsAndVertices {
+                    objects: cli.qtree_capacity_objects.ok_or(
+                        "--qtree-capacity-type=objects-and-vertices requires --qtree-capacity-objects",
+                    )?,
+                    vertices: cli.qtree_capacity.unwrap(),
+                }
+            }
+        };
+        let metadata_class: String = match format {
+            Formats::_3DTiles => cli
+                .cesium3dtiles_metadata_class
+                .take()
+                .ok_or("--3dtiles-metadata-class must be set for writing 3D Tiles")?,
+            Formats::CityJSON => "".to_string(),
+        };
+        let subprocess_env = cli.subprocess_env.clone().unwrap_or_default();
+        let proj_data = match env::var("PROJ_DATA") {
+            Ok(val) => {
+                debug!("PROJ_DATA: {}", &val);
+                Some(val)
+            }
+            Err(val) => {
+                warn!("PROJ_DATA environment variable is not set");
+                None
+            }
+        };
+        Ok(Self {
+            grid_cellsize,
+            geometric_error_above_leaf,
+            max_retries,
+            retry_policy,
+            converter_input,
+            converter,
+            native_cotype_options,
+            converter_rules,
+            memory_throttle,
+            tile_name_prefix,
+            thread_pool,
+            bounding_volume,
+            spatial_index,
+            lod_levels,
+            format,
+            subprocess_config,
+            geof_args,
+            quadtree_capacity,
+            metadata_class,
+            subprocess_env,
+            proj_data,
+            output: output.to_path_buf(),
+            path_output_tiles,
+            path_output_tiles_cityjson,
+            path_features_input_dir,
+        })
+    }
+}
+
+/// The features of a [run] and their spatial index.
+struct FeatureIndex {
+    world: parser::World,
+    quadtree: spatial_structs::QuadTree,
+    /// The octree of `--spatial-index=octree`, the tileset is built from it instead of
+    /// the quadtree.
+    octree: Option<spatial_structs::Octree>,
+    grid_minz: i32,
+    grid_maxz: i32,
+    /// The ground elevation of the tiles from the `--dem`.
+    ground_z: Option<HashMap<spatial_structs::QuadTreeNodeId, f64>>,
+    /// The directory of the intermediate files, if the tiles are converted.
+    tmp_dir: Option<TmpDir>,
+    /// The features that were spooled from a CityJSONSeq stream.
+    spool_dir: Option<TmpDir>,
+}
+
+impl FeatureIndex {
+    /// The features, the bbox and the number of items of the spatial index node of a tile.
+    fn tile_node(&self, tileid: &TileId) -> (Vec<usize>, spatial_structs::Bbox, usize) {
+        match self.octree {
+            Some(ref octree) => {
+                let node = octree
+                    .node(&tileid.into())
+                    .unwrap_or_else(|| panic!("did not find tile {} in octree", tileid));
+                (
+                    node.feature_ids().to_vec(),
+                    node.bbox(&self.world.grid),
+                    node.nr_items,
+                )
+            }
+            None => {
+                let qtree_nodeid: spatial_structs::QuadTreeNodeId = tileid.into();
+                let node = self
+                    .quadtree
+                    .node(&qtree_nodeid)
+                    .unwrap_or_else(|| panic!("did not find tile {} in quadtree", tileid));
+                let feature_ids = node
+                    .cells()
+                    .into_iter()
+                    .flat_map(|cellid| self.world.grid.cell(cellid).feature_ids.iter().copied())
+                    .collect();
+                (feature_ids, node.bbox(&self.world.grid), node.nr_items)
+            }
+        }
+    }
+
+    /// The bbox and the number of items of the spatial index node of a tile, if there is
+    /// a node for it.
+    fn node_bbox_items(&self, tileid: &TileId) -> Option<(spatial_structs::Bbox, usize)> {
+        match self.octree {
+            Some(ref octree) => octree
+                .node(&tileid.into())
+                .map(|node| (node.bbox(&self.world.grid), node.nr_items)),
+            None => self
+                .quadtree
+                .node(&tileid.into())
+                .map(|node| (node.bbox(&self.world.grid), node.nr_items)),
+        }
+    }
+}
+
+/// Run the tiling pipeline with the `config`: index the features, build the quadtree,
+/// convert the tiles and write the tileset. If the `config` has a subcommand (eg.
+/// `validate`), the subcommand is run instead.
+pub fn run(config: TilerConfig) -> Result<RunReport, Box<dyn std::error::Error>> {
+    let run_start = Instant::now();
+    let mut cli = config;
+    if let Some(cli::Command::Merge(ref merge_args)) = cli.command {
+        info!(
+            "Merging {} tilesets into {:?}",
+            merge_args.tilesets.len(),
+            &merge_args.output
+        );
+        if let Some(dir) = merge_args.output.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tileset =
+            formats::cesium3dtiles::Tileset::merge(&merge_args.tilesets, &merge_args.output)?;
+        tileset.to_file(&merge_args.output)?;
+        return Ok(RunReport::default());
+    }
+    if let Some(cli::Command::PrintSchema(_)) = cli.command {
+        let tileset = formats::cesium3dtiles::Tileset::sample()?;
+        eprintln!("Sample tileset.json for 3D Tiles {}", tileset.version());
+        println!("{}", serde_json::to_string_pretty(&tileset)?);
+        return Ok(RunReport::default());
+    }
+    // clap only requires the options of a run without a subcommand
+    let (Some(mut features), Some(output)) = (cli.features.take(), cli.output.take()) else {
+        return Err("the options --features and --output are required".into());
+    };
+    if cli.tiling == Some(formats::cesium3dtiles::Tiling::Implicit) {
+        // The conflicts of --3dtiles-implicit are not checked by clap for --tiling.
+        if cli.cesium3dtiles_local_transforms || cli.inline_levels.is_some() {
+            return Err(
+                "--tiling=implicit cannot be used with --3dtiles-local-transforms and --inline-levels"
+                    .into(),
+            );
+        }
+        cli.cesium3dtiles_implicit = true;
+    }
+    if cli.cesium3dtiles_local_transforms && cli.converter != Some(Converter::Native) {
+        // The GLTFWriter of the geof flowchart writes the content relative to its own
+        // center, so the tile transform would translate it twice
+        return Err(
+            "--3dtiles-local-transforms is only supported by --converter=native, because the geof flowchart does not write the content relative to the tile transform"
+                .into(),
+        );
+    }
+    info!("tyler version: {}", clap::crate_version!());
+    // The remote inputs are downloaded first, so that the rest of the run only sees local
+    // files
+    let metadata_is_url = cli.metadata.as_ref().is_some_and(|m| remote::is_url(m));
+    if metadata_is_url || remote::is_url(&features) {
+        let downloader = remote::Downloader::new(
+            cli.exe_curl
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("curl")),
+            cli.cache_dir
+                .clone()
+                .unwrap_or_else(remote::Downloader::default_cache_dir),
+        );
+        info!(
+            "Downloading the remote inputs into the cache {:?}",
+            downloader.cache_dir()
+        );
+        if metadata_is_url {
+            let url = cli
+                .metadata
+                .as_ref()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            cli.metadata = Some(downloader.fetch(&url)?);
+        }
+        if remote::is_url(&features) {
+            let url = features.to_string_lossy().into_owned();
+            let url_path = url.split('?').next().unwrap_or_default();
+            features = if url_path.ends_with(".txt") {
+                downloader.fetch_feature_list(&url)?
+            } else if url_path.ends_with(".jsonl") {
+                downloader.fetch(&url)?
+            } else {
+                return Err(format!(
+                    "the --features URL {} must be a feature list (.txt) or a CityJSONSeq file (.jsonl)",
+                    &url
+                )
+                .into());
+            };
+        }
+    }
+    if cli.command == Some(crate::cli::Command::Validate) {
+        let problems = validate::validate(&cli, &features);
+        if problems.is_empty() {
+            info!("No problems found");
+            return Ok(RunReport::default());
+        }
+        for problem in problems.iter() {
+            error!("{}", problem);
+        }
+        return Err(format!("found {} problems in the inputs", problems.len()).into());
+    }
+    if !output.is_dir() {
+        fs::create_dir_all(&output)?;
+        info!("Created output directory {:#?}", &output);
+    }
+    let settings = RunSettings::new(&mut cli, &output)?;
+    // Check the output of a previous run before the expensive indexing.
+    let path_output_tiles = &settings.path_output_tiles;
+    let path_output_tiles_cityjson = &settings.path_output_tiles_cityjson;
+    if !cli.dry_run
+        && !cli.resume
+        && cli.only_tile.is_none()
+        && fs::read_dir(path_output_tiles).is_ok_and(|mut entries| entries.next().is_some())
+    {
+        if !cli.force {
+            return Err(format!(
+                "the output directory {:?} already contains tiles, use --force to remove them or --resume to continue the previous run",
+                &path_output_tiles
+            )
+            .into());
+        }
+        for path in [
+            path_output_tiles,
+            path_output_tiles_cityjson,
+            &output.join("inputs"),
+        ] {
+            // The inputs of --convert-only are the index of a previous run
+            if cli.convert_only && path.ends_with("inputs") {
+                continue;
+            }
+            if path.is_dir() {
+                fs::remove_dir_all(path)?;
+                info!("Removed {:?}", path);
+            }
+        }
+        let path_old_tileset = output.join("tileset.json");
+        if path_old_tileset.is_file() {
+            fs::remove_file(&path_old_tileset)?;
+            info!("Removed {:?}", &path_old_tileset);
+        }
+    }
+    let mut index = index_features(&cli, &settings, &features)?;
+    let (mut tileset, tiles) = build_tileset(&cli, &settings, &index)?;
+    if cli.dry_run {
+        info!("Dry run, skipping the conversion of {} tiles", tiles.len());
+        for (tile, tileid) in tiles.iter() {
+            let (_, bbox, nr_items) = index.tile_node(&tile.id);
+            info!("{} nr_items: {} bbox: {:?}", tileid, nr_items, bbox);
+        }
+    }
+    // TODO: need to refactor this parallel loop somehow that it does not only read the
+    //  3d tiles tiles, but also works with cityjson output
+    if cli.index_only {
+        info!(
+            "Writing the feature paths of {} tiles into {:?}, without converting them",
+            tiles.len(),
+            &settings.path_features_input_dir
+        );
+        for (tile, tileid) in tiles.iter() {
+            let (feature_ids, _, _) = index.tile_node(&tile.id);
+            let path_features_input_file = settings
+                .path_features_input_dir
+                .join(tileid.file_stem(&settings.tile_name_prefix))
+                .with_extension("input");
+            fs::create_dir_all(path_features_input_file.parent().unwrap())?;
+            fs::write(
+                &path_features_input_file,
+                index.world.feature_input(&feature_ids),
+            )?;
+        }
+    }
+    let mut report = RunReport::default();
+    if !cli.cesium3dtiles_tileset_only && !cli.dry_run && !cli.index_only {
+        let (conversion_report, tiles_failed) =
+            convert_tiles(&cli, &settings, &index, &mut tileset, tiles, run_start)?;
+        report = conversion_report;
+        if let Some(ref only_tile) = cli.only_tile {
+            if !cli.only_tile_tileset {
+                info!("Converted the tile {}, not writing the tileset", only_tile);
+                return Ok(report);
+            }
+        }
+        info!("Pruning tileset of empty tiles");
+        for (i, failed) in tiles_failed.iter().enumerate() {
+            debug!("{}, removing failed from the tileset: {}", i, failed.id);
+        }
+        // Remove tiles that failed the gltf conversion
+        match index.octree {
+            Some(ref octree) => tileset.prune_octree(&tiles_failed, octree),
+            None => tileset.prune(&tiles_failed, &index.quadtree),
+        }
+        if cli.cesium3dtiles_implicit {
+            // FIXME: here we re-create the implicit tileset from the pruned tileset,
+            //  because it is simpler than flipping the bits of the unavailable tiles,
+            //  because of the mixed up explicit/implicit tile IDs. But ideally, we
+            //  flip the bits, so we won't need to duplicate the tileset here.
+            let (_, subtrees) = tileset.make_implicit(
+                &index.world.grid,
+                &index.quadtree,
+                cli.grid_export,
+                &settings.tile_name_prefix,
+            );
+            info!("Writing subtrees for implicit tiling");
+            let subtrees_path = output.join("subtrees");
+            fs::create_dir_all(&subtrees_path)?;
+            for (subtree_id, subtree_bytes) in subtrees {
+                fs::create_dir_all(
+                    subtrees_path.join(format!("{}/{}", subtree_id.level, subtree_id.x)),
+                )?;
+                let out_path = subtrees_path
+                    .join(&subtree_id.to_string())
+                    .with_extension("subtree");
+                let mut subtree_file = File::create(&out_path)
+                    .map_err(|e| format!("could not create {:?} for writing: {}", &out_path, e))?;
+                if let Err(e) = subtree_file.write_all(&subtree_bytes) {
+                    warn!("Failed to write subtree {} content", subtree_id);
+                }
+            }
+        }
+    } else {
+        // The tiles are not converted in this run, so we declare what the conversion
+        // would produce.
+        tileset.set_content_extensions(&settings.subprocess_config.content_extensions);
+    }
+
+    // The intermediate files are removed before the tileset is written, so that they are
+    // not in the --archive
+    drop(index.tmp_dir.take());
+    drop(index.spool_dir.take());
+
+    write_tileset(&cli, &settings, &index, tileset)?;
+    Ok(report)
+}
+
+/// The indexing phase of a [run]: read the features into the [parser::World], prepare
+/// the intermediate files and build the spatial index.
+fn index_features(
+    cli: &cli::Cli,
+    settings: &RunSettings,
+    features: &Path,
+) -> Result<FeatureIndex, Box<dyn std::error::Error>> {
+    let RunSettings {
+        grid_cellsize,
+        spatial_index,
+        quadtree_capacity,
+        ref output,
+        ref path_features_input_dir,
+        ..
+    } = *settings;
+    // Reading the features from a CityJSONSeq stream (stdin or file)
+    let features_from_stdin = features.as_os_str() == "-";
+    let features_from_stream = features_from_stdin || parser::World::is_cityjsonseq(&features);
+    // The spooled features are removed with the guard, also if the run fails
+    let (path_metadata, path_features, spool_dir) = if features_from_stream {
+        let path_features = output.join("features");
+        let spool_dir = TmpDir::create(path_features.clone(), true, log_enabled!(Level::Debug))?;
+        let path_metadata_stream = if features_from_stdin {
+            info!(
+                "Reading the CityJSONSeq stream from stdin into {:?}",
+                &path_features
+            );
+            parser::spool_cityjsonseq(std::io::stdin().lock(), &path_features)?
+        } else {
+            info!(
+                "Reading the CityJSONSeq file {:?} into {:?}",
+                &features, &path_features
+            );
+            parser::spool_cityjsonseq(BufReader::new(File::open(&features)?), &path_features)?
+        };
+        let path_metadata = cli.metadata.clone().or(path_metadata_stream).ok_or(
+            "--metadata must be set if the first line of the stream is not a CityJSON object",
+        )?;
+        (path_metadata, path_features, Some(spool_dir))
+    } else {
+        let path_metadata = cli
+            .metadata
+            .clone()
+            .ok_or("--metadata must be set if the features are not a CityJSONSeq stream")?;
+        (path_metadata, features.to_path_buf(), None)
+    };
+
+    // Populate the World with features
+    // Primitive types that implement Copy are efficiently copied into the function and
+    // and it is cleaner to avoid the indirection. However, heap-allocated container
+    // types are best passed by reference, because it is "expensive" to Clone them
+    // (they don't implement Copy). When we move a value, we explicitly transfer
+    // ownership of the value (eg cli.object_type).
+    // The index of --index-only, which is read by --convert-only
+    let path_index = path_features_input_dir.join("tyler_index.json");
+    let mut world = if cli.convert_only {
+        info!("Reading the index of the features from {:?}", &path_index);
+        parser::World::from_index(
+            &path_index,
+            &path_metadata,
+            &path_features,
+            cli.object_type.clone(),
+        )?
+    } else {
+        let mut world = parser::World::new(
+            &path_metadata,
+            &path_features,
+            grid_cellsize,
+            cli.grid_features_per_cell.unwrap() as usize,
+            cli.object_type.clone(),
+            cli.grid_minz,
+            cli.grid_maxz,
+            cli.extent,
+            cli.dedup_features,
+            cli.max_features,
+            cli.source_crs.clone(),
+            cli.features_extension.clone(),
+        )?;
+        world.feature_filters = cli.feature_filter.clone().unwrap_or_default();
+        world.feature_assignment = cli.feature_assignment.unwrap_or_default();
+        world.skip_invalid = cli.skip_invalid;
+        world.verify_metadata = cli.verify_metadata;
+        world.sample = cli.sample;
+        world.sample_seed = cli.seed.unwrap();
+        world.index_with_grid()?;
+        world
+    };
+    // The directory of the intermediate files is only needed if the tiles are converted
+    let tmp_dir = match !cli.cesium3dtiles_tileset_only && !cli.dry_run {
+        true => {
+            let tmp_dir = TmpDir::create(
+                path_features_input_dir.clone(),
+                cli.tmp_dir.is_none(),
+                cli.keep_tmp || log_enabled!(Level::Debug) || cli.index_only || cli.convert_only,
+            )?;
+            info!("Created directory {:#?}", &path_features_input_dir);
+            // The features of --convert-only were decompressed by --index-only
+            if !cli.convert_only {
+                // The features directory is removed with the guard if tyler created it
+                let path_features_copies = path_features_input_dir.join("features");
+                tmp_dir.track_new(&path_features_copies);
+                let nr_decompressed = world.decompress_features(&path_features_copies)?;
+                if nr_decompressed > 0 {
+                    info!("Decompressed {} gzipped features", nr_decompressed);
+                }
+                if let Some(threshold) = cli.drop_duplicate_vertices_threshold {
+                    let nr_merged = world.weld_features(&path_features_copies, threshold)?;
+                    info!("Merged {} duplicate vertices of the features", nr_merged);
+                }
+            }
+            Some(tmp_dir)
+        }
+        false => None,
+    };
+    if cli.index_only {
+        info!("Writing the index of the features to {:?}", &path_index);
+        world.write_index(&path_index)?;
+    }
+    let (grid_minz, grid_maxz) = world.z_bounds();
+    info!(
+        "Using the z bounds {} - {} for the tileset",
+        grid_minz, grid_maxz
+    );
+
+    // Debug
+    if cli.grid_export {
+        debug!("Exporting the grid to the working directory");
+        world.export_grid()?;
+        info!("Writing the grid to {:?}", &output);
+        cli.grid_export_format
+            .unwrap()
+            .export(&world.grid, &output)?;
+    }
+
+    // Build quadtree
+    info!("Building quadtree");
+    let quadtree_capacity = match cli.target_tiles {
+        Some(target_tiles) => {
+            info!("Searching the quadtree capacity for {} tiles", target_tiles);
+            let capacity = spatial_structs::QuadTree::tune_capacity(
+                &world.grid,
+                quadtree_capacity,
+                cli.qtree_max_depth,
+                cli.qtree_split.unwrap(),
+                target_tiles as usize,
+                cli.target_tiles_tolerance.unwrap(),
+                TARGET_TILES_MAX_ITERATIONS,
+            );
+            info!("Using the quadtree capacity {:?}", capacity);
+            capacity
+        }
+        None => quadtree_capacity,
+    };
+    let quadtree = spatial_structs::QuadTree::from_world(
+        &world,
+        quadtree_capacity,
+        cli.qtree_max_depth,
+        cli.qtree_split.unwrap(),
+    );
+    info!("Quadtree: {}", quadtree.stats(quadtree_capacity));
+    let octree = match spatial_index {
+        spatial_structs::SpatialIndex::Quadtree => None,
+        spatial_structs::SpatialIndex::Octree => {
+            info!("Building octree");
+            let octree =
+                spatial_structs::Octree::from_world(&world, quadtree_capacity, cli.qtree_max_depth);
+            debug!("Octree has {} leaves", octree.collect_leaves().len());
+            Some(octree)
+        }
+    };
+
+    // Debug
+    if cli.grid_export {
+        debug!("Exporting the quadtree to the working directory");
+        quadtree.export(&world.grid)?;
+    }
+
+    if let Some(ref export_quadtree_path) = cli.export_quadtree {
+        info!("Writing the quadtree to {:?}", export_quadtree_path);
+        quadtree.export_json(export_quadtree_path, &world)?;
+    }
+
+    if cli.quadtree_export {
+        let path_geojson = output.join("quadtree.geojson");
+        info!("Writing the quadtree leaves to {:?}", &path_geojson);
+        quadtree.export_geojson(&path_geojson, &world.grid)?;
+    }
+
+    // let tiles: Vec<&formats::cesium3dtiles::Tile> = Vec::new();
+    // if cli.format == Formats::_3DTiles {
+    //     // 3D Tiles
+    //     info!("Generating 3D Tiles tileset");
+    //     let tileset_path = output.join("tileset.json");
+    //     let tileset = formats::cesium3dtiles::Tileset::from_quadtree(
+    //         &quadtree,
+    //         &world,
+    //         cli.grid_minz,
+    //         cli.grid_maxz,
+    //     );
+    //     tileset.to_file(tileset_path)?;
+    //     tiles = tileset.flatten(Some(4));
+    // }
+    let ground_z = match cli.dem {
+        Some(ref path_dem) => {
+            info!(
+                "Sampling the ground elevation of the tiles from {:?}",
+                path_dem
+            );
+            let exe = cli
+                .exe_gdallocationinfo
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("gdallocationinfo"));
+            let dem = dem::Dem::new(path_dem.clone(), exe, world.grid.epsg);
+            Some(dem.ground_z(&quadtree, &world.grid)?)
+        }
+        None => None,
+    };
+
+    Ok(FeatureIndex {
+        world,
+        quadtree,
+        octree,
+        grid_minz,
+        grid_maxz,
+        ground_z,
+        tmp_dir,
+        spool_dir,
+    })
+}
+
+/// The tileset phase of a [run]: build the tileset from the spatial index, and select the
+/// tiles with content that are converted.
+fn build_tileset(
+    cli: &cli::Cli,
+    settings: &RunSettings,
+    index: &FeatureIndex,
+) -> Result<(formats::cesium3dtiles::Tileset, ContentTiles), Box<dyn std::error::Error>> {
+    let RunSettings {
+        geometric_error_above_leaf,
+        lod_levels,
+        ref tile_name_prefix,
+        ..
+    } = *settings;
+    let FeatureIndex {
+        ref world,
+        ref quadtree,
+        ref octree,
+        grid_minz,
+        grid_maxz,
+        ref ground_z,
+        ..
+    } = *index;
+    info!("Generating 3D Tiles tileset");
+    // The bounding volumes are regions in EPSG:4979, and the tile transforms place the glTF
+    // content, which is in EPSG:4978
+    let crs_from = format!("EPSG:{}", world.crs.to_epsg()?);
+    let transformer = proj::Reprojector::new(&crs_from, "EPSG:4979")?;
+    let transformer_local = match cli.cesium3dtiles_local_transforms {
+        true => Some(proj::Reprojector::new(&crs_from, "EPSG:4978")?),
+        false => None,
+    };
+    let tileset_options = formats::cesium3dtiles::TilesetOptions {
+        transformer: &transformer,
+        transformer_local: transformer_local.as_ref(),
+        geometric_error_above_leaf,
+        geometric_error_factor: cli.geometric_error_factor,
+        arg_cellsize: world.grid.cellsize,
+        arg_minz: Some(grid_minz),
+        arg_maxz: Some(grid_maxz),
+        tile_name_prefix: &tile_name_prefix,
+        ground_z: ground_z.as_ref(),
+    };
+    let mut tileset = match octree {
+        Some(ref octree) => {
+            formats::cesium3dtiles::Tileset::from_octree(octree, &world, &tileset_options)
+        }
+        None => formats::cesium3dtiles::Tileset::from_quadtree(&quadtree, &world, &tileset_options),
+    };
+
+    if cli.empty_tiles.unwrap() == EmptyTiles::Prune {
+        let nr_leaves = tileset
+            .retain_leaves(&|tileid| index.node_bbox_items(tileid).is_some_and(|(_, n)| n > 0));
+        info!("Removed the empty tiles, kept {} leaf tiles", nr_leaves);
+    }
+    if let Some(tile_bbox) = cli.tile_bbox {
+        let intersects = |bbox: spatial_structs::Bbox| {
+            bbox[0] <= tile_bbox[2]
+                && bbox[3] >= tile_bbox[0]
+                && bbox[1] <= tile_bbox[3]
+                && bbox[4] >= tile_bbox[1]
+        };
+        let nr_leaves = tileset.retain_leaves(&|tileid| {
+            index
+                .node_bbox_items(tileid)
+                .is_some_and(|(bbox, _)| intersects(bbox))
+        });
+        if nr_leaves == 0 {
+            return Err(format!(
+                "none of the tiles intersect the --tile-bbox {:?}",
+                tile_bbox
+            )
+            .into());
+        }
+        info!(
+            "Converting the {} leaf tiles that intersect the --tile-bbox",
+            nr_leaves
+        );
+    }
+
+    tileset.set_refinement(cli.refine.unwrap());
+    if let Some(lod_levels) = lod_levels {
+        let nr_tiles = tileset.add_lod_content(lod_levels, &tile_name_prefix);
+        info!(
+            "Added content to {} parent tiles up to {} levels above the leaves",
+            nr_tiles, lod_levels
+        );
+        if nr_tiles == 0 {
+            warn!("None of the parent tiles has a larger geometric error than its children, set the --geometric-error-factor to add content to them");
+        }
+    }
+    tileset.set_asset(cli.tileset_version.clone(), cli.tileset_generator.clone());
+    tileset.set_extras(
+        cli.tileset_extra
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+    );
+    tileset.set_attributions(match cli.attribution {
+        Some(ref attributions) => attributions.clone(),
+        None => world.attribution.iter().cloned().collect(),
+    });
+
+    // // Select how many levels of tiles from the hierarchy do we want to export with
+    // // content.
+    // let qtree_export_levels = Some(0); //override cli.qtree_export_levels
+    // tileset.add_content(qtree_export_levels);
+
+    let (tiles, _) = match cli.cesium3dtiles_implicit {
+        true => {
+            let mut tileset_implicit = tileset.clone();
+            // FIXME: here we have a Vec<(Tile, TileId)> in 'tiles' instead of Vec<&Tile>, because of the
+            //  mess with the implicit/explicit tile id-s.
+            info!("Converting to implicit tiling");
+            // Tileset.make_implicit() outputs the tiles that have content. If only the leaves have
+            //  content, then only the leaves are outputted.
+            let tiles_subtrees = tileset_implicit.make_implicit(
+                &world.grid,
+                &quadtree,
+                cli.grid_export,
+                &tile_name_prefix,
+            );
+            tiles_subtrees
+        }
+        false => {
+            // let just_tiles = tileset.flatten(qtree_export_levels);
+            let just_tiles = tileset.collect_content_tiles();
+            // FIXME: here we need Vec<(Tile, TileId)> instead of Vec<&Tile>, for the same reason
+            //  as above
+            let tiles: Vec<(Tile, TileId)> = just_tiles
+                .into_iter()
+                .map(|tile_ref| (tile_ref.clone(), tile_ref.id.clone()))
+                .collect();
+            (tiles, vec![])
+        }
+    };
+
+    let tiles = match cli.only_tile {
+        Some(ref only_tile) => {
+            let tile = tiles
+                .into_iter()
+                .find(|(_, tileid)| &tileid.to_string() == only_tile)
+                .filter(|(tile, _)| !index.tile_node(&tile.id).0.is_empty())
+                .ok_or_else(|| {
+                    format!(
+                        "the --only-tile {} is not a non-empty tile of the tileset",
+                        only_tile
+                    )
+                })?;
+            info!("Converting only the tile {}", only_tile);
+            vec![tile]
+        }
+        None => tiles,
+    };
+    Ok((tileset, tiles))
+}
+
+/// The conversion phase of a [run]: convert the `tiles` in parallel, and write the run
+/// manifest. Returns the report of the run and the tiles that failed to convert.
+fn convert_tiles(
+    cli: &cli::Cli,
+    settings: &RunSettings,
+    index: &FeatureIndex,
+    tileset: &mut formats::cesium3dtiles::Tileset,
+    tiles: ContentTiles,
+    run_start: Instant,
+) -> Result<(RunReport, Vec<Tile>), Box<dyn std::error::Error>> {
+    let RunSettings {
+        max_retries,
+        retry_policy,
+        converter_input,
+        converter,
+        ref native_cotype_options,
+        ref converter_rules,
+        ref memory_throttle,
+        ref tile_name_prefix,
+        ref thread_pool,
+        ref format,
+        ref subprocess_config,
+        ref geof_args,
+        ref metadata_class,
+        ref subprocess_env,
+        ref proj_data,
+        ref output,
+        ref path_output_tiles,
+        ref path_output_tiles_cityjson,
+        ref path_features_input_dir,
+        ..
+    } = *settings;
+    let world = &index.world;
+    let tmp_dir = index
+        .tmp_dir
+        .as_ref()
+        .ok_or("the directory of the intermediate files was not created")?;
+    let crs_from = format!("EPSG:{}", world.crs.to_epsg()?);
+    let native_writer = match converter {
+        Converter::Native => Some(glb_writer(&crs_from, world, native_cotype_options)?),
+        Converter::Geof => None,
+    };
+
+    // Export by calling a subprocess to merge the .jsonl files and convert them to the
+    // target format
+    let cotypes_str: Vec<String> = match &world.cityobject_types {
+        None => Vec::new(),
+        Some(cotypes) => cotypes.iter().map(|co| co.to_string()).collect(),
+    };
+    let cotypes_arg = cotypes_str.join(",");
+
+    let attribute_spec: String = match &cli.object_attribute {
+        None => "".to_string(),
+        Some(attributes) => attributes.join(","),
+    };
+
+    fs::create_dir_all(path_output_tiles)?;
+    info!("Created output directory {:#?}", path_output_tiles);
+    info!("Exporting and optimizing {} tiles", tiles.len());
+    // Set when the remaining tiles should not be converted anymore. The workers check
+    // it before starting the conversion of a tile.
+    let cancel = AtomicBool::new(false);
+    let nr_skipped = AtomicUsize::new(0);
+    let tile_records: Mutex<Vec<TileRecord>> = Mutex::new(Vec::with_capacity(tiles.len()));
+    let tile_errors: Mutex<Vec<TileError>> = Mutex::new(Vec::new());
+    // The checksum is computed in the conversion threads, right after the tile is
+    // converted
+    let checksum = |path: &Path| -> Option<String> {
+        if !cli.emit_checksums {
+            return None;
+        }
+        sha256_file(path)
+            .map_err(|e| warn!("Could not compute the sha256 of {:?}: {}", path, e))
+            .ok()
+    };
+    // The progress bar would be mixed up with the debug messages
+    let progress =
+        if cli.no_progress || !std::io::stdout().is_terminal() || log_enabled!(Level::Debug) {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(tiles.len() as u64).with_style(ProgressStyle::with_template(
+                "{elapsed_precise} [{wide_bar}] {pos}/{len} tiles, ETA {eta}",
+            )?)
+        };
+    // A tile is prepared for the conversion by building its subprocess command. A tile
+    // that is cancelled or already converted (--resume) is finished right away.
+    let prepare_tile = |(tile, tileid): (Tile, TileId)| -> Result<TileJob, TileConversion> {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(Err(Box::new(tile)));
+        }
+        let start = Instant::now();
+        // We use the spatial index node bbox here instead of the Tileset.Tile
+        // bounding volume, because the Tile is in EPSG:4979 and we need the input
+        // data CRS
+        let (feature_ids, b, nr_items) = index.tile_node(&tile.id);
+        let file_name = tileid.file_stem(tile_name_prefix);
+        let output_file = path_output_tiles
+            .join(&file_name)
+            .with_extension(&subprocess_config.output_extension);
+        if cli.keep_intermediate_cityjson {
+            let path_cityjson = path_output_tiles_cityjson
+                .join(&file_name)
+                .with_extension("city.json");
+            let exists = fs::metadata(&path_cityjson).is_ok_and(|m| m.len() > 0);
+            if !(cli.resume && exists) {
+                let result = world.merge_features(&feature_ids).and_then(|cm| {
+                    fs::create_dir_all(path_cityjson.parent().unwrap())?;
+                    serde_json::to_writer(File::create(&path_cityjson)?, &cm)?;
+                    Ok(())
+                });
+                if let Err(e) = result {
+                    warn!(
+                        "{} could not write the CityJSON {:?}: {}",
+                        &tileid, &path_cityjson, e
+                    );
+                }
+            }
+        }
+        if cli.resume {
+            if let Ok(output_metadata) = fs::metadata(&output_file) {
+                if output_metadata.len() > 0 {
+                    debug!("{} output {:?} exists, skipping", &tileid, &output_file);
+                    nr_skipped.fetch_add(1, Ordering::Relaxed);
+                    let sha256 = checksum(&output_file);
+                    tile_records.lock().unwrap().push(TileRecord {
+                        tile_id: tileid.to_string(),
+                        output_file,
+                        bbox: b,
+                        nr_items,
+                        success: true,
+                        nr_bytes: Some(output_metadata.len()),
+                        duration_secs: start.elapsed().as_secs_f64(),
+                        timed_out: false,
+                        sha256,
+                    });
+                    return Err(Ok(subprocess_config.content_extensions.clone()));
+                }
+            }
+        }
+        let feature_input = world.feature_input(&feature_ids);
+
+        // We need to string-format all the arguments with an = separator, because that's what
+        // geof can accept.
+        let mut cmd = Cmd::new(&subprocess_config.exe)
+            .arg(&subprocess_config.script)
+            .arg(format!(
+                "--output_format={}",
+                &format.to_string().to_lowercase()
+            ))
+            .arg(format!("--output_file={}", &output_file.to_str().unwrap()))
+            .arg(format!(
+                "--path_metadata={}",
+                &world.path_metadata.to_str().unwrap()
+            ))
+            .arg(format!("--min_x={}", b[0]))
+            .arg(format!("--min_y={}", b[1]))
+            .arg(format!("--min_z={}", b[2]))
+            .arg(format!("--max_x={}", b[3]))
+            .arg(format!("--max_y={}", b[4]))
+            .arg(format!("--max_z={}", b[5]))
+            .arg(format!("--cotypes={}", &cotypes_arg))
+            .arg(format!("--metadata_class={}", &metadata_class))
+            .arg(format!("--attribute_spec={}", &attribute_spec))
+            .arg(format!("--geometric_error={}", &tile.geometric_error));
+        // The list of feature paths is passed in a text file or on stdin, instead of
+        // passing super long paths-string to the subprocess, because with very long
+        // arguments we can get an 'Argument list too long' error.
+        let stdin_data: Option<String> = match converter_input {
+            ConverterInput::File => {
+                let path_features_input_file = path_features_input_dir
+                    .join(&file_name)
+                    .with_extension("input");
+                if let Err(e) = tmp_dir.write(&path_features_input_file, &feature_input) {
+                    warn!(
+                        "{} could not write the feature paths to {:?}: {}",
+                        &tileid, &path_features_input_file, e
+                    );
+                    return Err(Err(Box::new(tile)));
+                }
+                cmd = cmd.arg(format!(
+                    "--path_features_input_file={}",
+                    &path_features_input_file.to_str().unwrap()
+                ));
+                None
+            }
+            ConverterInput::Stdin => {
+                // The TextReader of the flowchart reads the list from the stdin
+                cmd = cmd.arg("--path_features_input_file=/dev/stdin");
+                Some(feature_input)
+            }
+        };
+        if *format == Formats::_3DTiles {
+            // geof specific args
+            // colors
+            if !cli.color_building.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBuilding={}",
+                    cli.color_building.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_building_part.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBuildingPart={}",
+                    cli.color_building_part.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_building_installation.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBuildingInstallation={}",
+                    cli.color_building_installation.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_tin_relief.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorTINRelief={}",
+                    cli.color_tin_relief.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_road.is_none() {
+                cmd = cmd.arg(format!("--colorRoad={}", cli.color_road.as_ref().unwrap()));
+            }
+            if !cli.color_railway.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorRailway={}",
+                    cli.color_railway.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_transport_square.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorTransportSquare={}",
+                    cli.color_transport_square.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_water_body.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorWaterBody={}",
+                    cli.color_water_body.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_plant_cover.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorPlantCover={}",
+                    cli.color_plant_cover.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_solitary_vegetation_object.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorSolitaryVegetationObject={}",
+                    cli.color_solitary_vegetation_object.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_land_use.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorLandUse={}",
+                    cli.color_land_use.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_city_furniture.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorCityFurniture={}",
+                    cli.color_city_furniture.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_bridge.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBridge={}",
+                    cli.color_bridge.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_bridge_part.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBridgePart={}",
+                    cli.color_bridge_part.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_bridge_installation.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBridgeInstallation={}",
+                    cli.color_bridge_installation.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_bridge_construction_element.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorBridgeConstructionElement={}",
+                    cli.color_bridge_construction_element.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_tunnel.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorTunnel={}",
+                    cli.color_tunnel.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_tunnel_part.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorTunnelPart={}",
+                    cli.color_tunnel_part.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_tunnel_installation.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorTunnelInstallation={}",
+                    cli.color_tunnel_installation.as_ref().unwrap()
+                ));
+            }
+            if !cli.color_generic_city_object.is_none() {
+                cmd = cmd.arg(format!(
+                    "--colorGenericCityObject={}",
+                    cli.color_generic_city_object.as_ref().unwrap()
+                ));
+            }
+
+            // lod filter
+            if !cli.lod_building.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodBuilding={}",
+                    cli.lod_building.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_building_part.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodBuildingPart={}",
+                    cli.lod_building_part.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_building_installation.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodBuildingInstallation={}",
+                    cli.lod_building_installation.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_tin_relief.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodTINRelief={}",
+                    cli.lod_tin_relief.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_road.is_none() {
+                cmd = cmd.arg(format!("--lodRoad={}", cli.lod_road.as_ref().unwrap()));
+            }
+            if !cli.lod_railway.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodRailway={}",
+                    cli.lod_railway.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_transport_square.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodTransportSquare={}",
+                    cli.lod_transport_square.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_water_body.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodWaterBody={}",
+                    cli.lod_water_body.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_plant_cover.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodPlantCover={}",
+                    cli.lod_plant_cover.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_solitary_vegetation_object.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodSolitaryVegetationObject={}",
+                    cli.lod_solitary_vegetation_object.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_land_use.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodLandUse={}",
+                    cli.lod_land_use.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_city_furniture.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodCityFurniture={}",
+                    cli.lod_city_furniture.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_bridge.is_none() {
+                cmd = cmd.arg(format!("--lodBridge={}", cli.lod_bridge.as_ref().unwrap()));
+            }
+            if !cli.lod_bridge_part.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodBridgePart={}",
+                    cli.lod_bridge_part.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_bridge_installation.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodBridgeInstallation={}",
+                    cli.lod_bridge_installation.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_bridge_construction_element.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodBridgeConstructionElement={}",
+                    cli.lod_bridge_construction_element.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_tunnel.is_none() {
+                cmd = cmd.arg(format!("--lodTunnel={}", cli.lod_tunnel.as_ref().unwrap()));
+            }
+            if !cli.lod_tunnel_part.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodTunnelPart={}",
+                    cli.lod_tunnel_part.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_tunnel_installation.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodTunnelInstallation={}",
+                    cli.lod_tunnel_installation.as_ref().unwrap()
+                ));
+            }
+            if !cli.lod_generic_city_object.is_none() {
+                cmd = cmd.arg(format!(
+                    "--lodGenericCityObject={}",
+                    cli.lod_generic_city_object.as_ref().unwrap()
+                ));
+            }
+
+            let simplify_ratio = cli.simplify_ratio.clone().unwrap_or_default();
+            // The content of the parent tiles (--lod-levels) is simplified further with
+            // each level above the leaves
+            let height = tile.height();
+            let lod_ratio = |ratio: Option<f64>| -> Option<f64> {
+                if height == 0 {
+                    ratio
+                } else {
+                    let lod_simplify_ratio = cli.lod_simplify_ratio.unwrap();
+                    Some(ratio.unwrap_or(1.0) * lod_simplify_ratio.powi(height as i32))
+                }
+            };
+            if let Some(ref cotypes) = world.cityobject_types {
+                // The features of a building tile are not clipped, because each
+                // building is assigned to a single tile
+                if !cli.clip_buildings && world.has_building(&feature_ids) {
+                    let ratio = lod_ratio(simplify_ratio.ratio(cotypes)).unwrap_or(1.0);
+                    cmd = cmd
+                        .arg(format!("--simplify_ratio={}", ratio))
+                        .arg("--skip_clip=true");
+                } else if let Some(ratio) =
+                    lod_ratio(simplify_ratio.ratio(cotypes).or(cli.reduce_vertices))
+                {
+                    cmd = cmd.arg(format!("--simplify_ratio={}", ratio));
+                }
+            } else if let Some(ratio) = lod_ratio(simplify_ratio.global) {
+                cmd = cmd.arg(format!("--simplify_ratio={}", ratio));
+            }
+            if cli.draco {
+                cmd = cmd.args(["--meshopt_compress=false", "--draco_compress=true"]);
+            }
+            // The features are already welded, the threshold is passed for the
+            // flowcharts that depend on it
+            if let Some(threshold) = cli.drop_duplicate_vertices_threshold {
+                cmd = cmd.arg(format!("--drop_duplicate_vertices_threshold={}", threshold));
+            }
+            if log_enabled!(Level::Debug) {
+                cmd = cmd.arg("--verbose");
+            }
+        }
+
+        cmd = cmd.args(geof_args.iter());
+
+        if let Some(ref rules) = converter_rules {
+            // Added last, so that the rules override the default arguments
+            cmd = cmd.args(rules.args(&tileid.to_string(), tileid.level, &b));
+        }
+
+        if let Some(pd) = &proj_data {
+            cmd = cmd.env("PROJ_DATA", pd);
+        }
+        for (key, value) in subprocess_env.iter() {
+            cmd = cmd.env(key, value);
+        }
+
+        // The native converter does not run the command, it is only recorded
+        let cmd_string = match converter {
+            Converter::Native => "--converter=native".to_string(),
+            Converter::Geof => cmd.to_cmdline_lossy(),
+        };
+        debug!("{} conversion subprocess command: {}", &tileid, &cmd_string);
+        Ok(TileJob {
+            tile,
+            tileid,
+            output_file,
+            bbox: b,
+            nr_items,
+            start,
+            cmd,
+            cmd_string,
+            stdin_data,
+            feature_ids,
+        })
+    };
+    // Run a conversion subprocess with the retries, and return the exit status, the
+    // stdout (merged with the stderr) and whether it timed out, of the last attempt.
+    let run_subprocess = |cmd: &Cmd,
+                          stdin_data: Option<&str>,
+                          label: &str,
+                          cmd_string: &str|
+     -> std::io::Result<(std::process::ExitStatus, String, bool)> {
+        let mut attempt: u32 = 0;
+        loop {
+            if let Some(ref throttle) = memory_throttle {
+                throttle.wait();
+            }
+            let running = cmd.spawn_merged(stdin_data)?;
+            let pid = Some(running.pid());
+            if let Some(ref throttle) = memory_throttle {
+                throttle.started(pid);
+            }
+            let output = running.wait(subprocess_config.timeout);
+            if let Some(ref throttle) = memory_throttle {
+                throttle.finished(pid);
+            }
+            let output = output?;
+            if output.timed_out {
+                warn!(
+                    "{} timed out, conversion subprocess command:\n{}",
+                    label, cmd_string
+                );
+            }
+
+            if !output.status.success()
+                && attempt < max_retries
+                && retry_policy.should_retry(&output.status, output.timed_out)
+            {
+                let backoff = RETRY_BACKOFF * 2u32.pow(attempt.min(RETRY_BACKOFF_MAX_DOUBLINGS));
+                attempt += 1;
+                warn!(
+                    "{} conversion subprocess failed with {:?}, retrying in {:?} ({}/{})",
+                    label, output.status, backoff, attempt, max_retries
+                );
+                std::thread::sleep(backoff);
+                continue;
+            }
+            return Ok((output.status, output.stdout, output.timed_out));
+        }
+    };
+    // Record the result of a tile in the run manifest, and return the glTF extensions
+    // that are present in its content, or the tile itself if the conversion failed.
+    let finish_tile =
+        |job: TileJob, success: bool, timed_out: bool, output: &str| -> TileConversion {
+            let nr_bytes = success
+                .then(|| fs::metadata(&job.output_file).ok())
+                .flatten()
+                .map(|output_metadata| output_metadata.len());
+            if let (Some(nr_bytes), Some(max_tile_bytes)) = (nr_bytes, cli.max_tile_bytes) {
+                if nr_bytes > max_tile_bytes {
+                    warn!(
+                        "{} output {:?} is {} bytes, which is larger than the --max-tile-bytes {}",
+                        &job.tileid, &job.output_file, nr_bytes, max_tile_bytes
+                    );
+                }
+            }
+            let sha256 = nr_bytes.and_then(|_| checksum(&job.output_file));
+            tile_records.lock().unwrap().push(TileRecord {
+                tile_id: job.tileid.to_string(),
+                output_file: job.output_file,
+                bbox: job.bbox,
+                nr_items: job.nr_items,
+                success,
+                nr_bytes,
+                duration_secs: job.start.elapsed().as_secs_f64(),
+                timed_out,
+                sha256,
+            });
+            if success {
+                return Ok(subprocess_config.content_extensions.clone());
+            }
+            tile_errors.lock().unwrap().push(TileError {
+                tile_id: job.tileid.to_string(),
+                command: job.cmd_string.clone(),
+                output: output.to_string(),
+                timed_out,
+            });
+            if cli.fail_fast && !cancel.swap(true, Ordering::Relaxed) {
+                error!(
+                    "{} conversion failed, cancelling the remaining tiles (--fail-fast)",
+                    &job.tileid
+                );
+            }
+            Err(Box::new(job.tile))
+        };
+    let convert_tile_job = |job: TileJob| -> TileConversion {
+        if let Some(ref writer) = native_writer {
+            let mut feature_paths: Vec<&Path> = job
+                .feature_ids
+                .iter()
+                .map(|fid| world.features[*fid].path_jsonl.as_path())
+                .collect();
+            feature_paths.sort_unstable();
+            feature_paths.dedup();
+            return match writer.write(&feature_paths, job.tile.content_origin(), &job.output_file) {
+                Ok(nr_triangles) => {
+                    debug!("{} wrote {} triangles", &job.tileid, nr_triangles);
+                    finish_tile(job, true, false, "")
+                }
+                Err(e) => {
+                    warn!("{} could not write the glTF: {}", &job.tileid, e);
+                    finish_tile(job, false, false, &e.to_string())
+                }
+            };
+        }
+        let mut timed_out = false;
+        let (success, output) = match run_subprocess(
+            &job.cmd,
+            job.stdin_data.as_deref(),
+            &job.tileid.to_string(),
+            &job.cmd_string,
+        ) {
+            Ok((exit_status, stdout, subprocess_timed_out)) => {
+                timed_out = subprocess_timed_out;
+                // The stderr is merged into the stdout
+                if timed_out {
+                    warn!(
+                        "{} conversion subprocess timed out after {:?}",
+                        &job.tileid,
+                        subprocess_config.timeout.unwrap_or_default()
+                    );
+                } else if !exit_status.success() {
+                    warn!(
+                        "{} conversion subprocess failed\ncommand: {}\nwith stdout and stderr:\n{}",
+                        &job.tileid, &job.cmd_string, &stdout
+                    );
+                } else if !stdout.is_empty() && stdout != "\n" {
+                    debug!("{} conversion subproces stdout {}", &job.tileid, &stdout);
+                }
+                if !timed_out && !job.output_file.exists() {
+                    warn!(
+                        "{} output {:?} was not written by the subprocess, conversion subprocess command:\n{}",
+                        &job.tileid, &job.output_file, &job.cmd_string
+                    );
+                }
+                (
+                    !timed_out && exit_status.success() && job.output_file.exists(),
+                    stdout,
+                )
+            }
+            Err(popen_error) => {
+                warn!("{}", popen_error);
+                (false, popen_error.to_string())
+            }
+        };
+        finish_tile(job, success, timed_out, &output)
+    };
+    // The tile ID is added to the JSON log records of the conversion
+    let convert_tile = |job: TileJob| -> TileConversion {
+        let tileid = job.tileid.to_string();
+        logging::with_tile(&tileid, || convert_tile_job(job))
+    };
+    // Each tile returns the glTF extensions that are present in its content, or itself
+    // if the conversion failed.
+    let convert_tiles = || -> Vec<TileConversion> {
+        tiles
+            .into_par_iter()
+            .map(|tile| match prepare_tile(tile) {
+                Ok(job) => convert_tile(job),
+                Err(done) => done,
+            })
+            .inspect(|_| progress.inc(1))
+            .collect()
+    };
+    let tiles_converted = match thread_pool {
+        Some(ref pool) => pool.install(convert_tiles),
+        None => convert_tiles(),
+    };
+    progress.finish_and_clear();
+    // The report is written before returning the error of --fail-fast, so that it has
+    // the failed tiles
+    let cancelled = cancel.load(Ordering::Relaxed);
+    let mut tiles_failed: Vec<Tile> = Vec::new();
+    let mut content_extensions: Vec<ExtensionName> = Vec::new();
+    let mut nr_ok: usize = 0;
+    for res in tiles_converted {
+        match res {
+            Ok(extensions) => {
+                nr_ok += 1;
+                content_extensions.extend(extensions)
+            }
+            Err(tile) => tiles_failed.push(*tile),
+        }
+    }
+    if let Some(ref only_tile) = cli.only_tile {
+        if !tiles_failed.is_empty() {
+            return Err(format!("failed to convert the tile {}", only_tile).into());
+        }
+        // The other tiles were converted in a previous run, so we declare what their
+        // conversion produces, same as for --3dtiles-tileset-only.
+        content_extensions = subprocess_config.content_extensions.clone();
+    }
+    tileset.set_content_extensions(&content_extensions);
+    let tile_records = tile_records.into_inner().unwrap();
+    let tiles_oversize: Vec<String> = match cli.max_tile_bytes {
+        Some(max_tile_bytes) => tile_records
+            .iter()
+            .filter(|r| r.nr_bytes.is_some_and(|nr_bytes| nr_bytes > max_tile_bytes))
+            .map(|r| r.tile_id.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    if !tiles_oversize.is_empty() {
+        warn!(
+            "{} tiles are larger than the --max-tile-bytes, lower the --qtree-capacity to get smaller tiles",
+            tiles_oversize.len()
+        );
+    }
+    // The tiles that were cancelled by --fail-fast are not converted, but they did not
+    // fail either
+    let nr_tiles_failed = match cancelled {
+        true => tile_records.iter().filter(|r| !r.success).count(),
+        false => tiles_failed.len(),
+    };
+    let report = RunReport {
+        nr_tiles: tile_records.len(),
+        nr_tiles_empty: tile_records.iter().filter(|r| r.nr_items == 0).count(),
+        nr_tiles_failed,
+        nr_tiles_timed_out: tile_records.iter().filter(|r| r.timed_out).count(),
+        nr_features_invalid: world.invalid_features.len(),
+        tiles_oversize,
+        duration_secs: run_start.elapsed().as_secs_f64(),
+        tiles: tile_records,
+    };
+    // The manifest of the previous run is kept, if this run converted only one tile
+    if cli.only_tile.is_none() {
+        let path_manifest = output.join("tyler_run.json");
+        info!("Writing the run manifest to {:?}", &path_manifest);
+        serde_json::to_writer(&File::create(&path_manifest)?, &report)?;
+        // The errors file is only present if some tiles failed, so the file of a
+        // previous run is removed
+        let path_errors = output.join("tyler_errors.json");
+        let tile_errors = tile_errors.into_inner().unwrap();
+        if tile_errors.is_empty() {
+            if path_errors.is_file() {
+                fs::remove_file(&path_errors)?;
+            }
+        } else {
+            warn!(
+                "Writing the {} failed tiles to {:?}",
+                tile_errors.len(),
+                &path_errors
+            );
+            serde_json::to_writer_pretty(&File::create(&path_errors)?, &tile_errors)?;
+        }
+    }
+    if report.nr_tiles_timed_out > 0 {
+        warn!(
+            "{} of the {} failed tiles timed out after the --timeout",
+            report.nr_tiles_timed_out, report.nr_tiles_failed
+        );
+    }
+    if cancelled {
+        return Err(format!(
+            "stopped the tile conversion after the first failure, because --fail-fast is set, {} tiles were not converted",
+            tiles_failed.len() - report.nr_tiles_failed
+        )
+        .into());
+    }
+    if !tiles_failed.is_empty() && !cli.keep_going {
+        let first_failed: Vec<String> = tiles_failed
+            .iter()
+            .take(5)
+            .map(|tile| tile.id.to_string())
+            .collect();
+        return Err(format!(
+            "{} tiles failed to convert, including {}. Use --keep-going to write the tileset without the failed tiles.",
+            tiles_failed.len(),
+            first_failed.join(", ")
+        )
+        .into());
+    }
+    if cli.resume {
+        let nr_skipped = nr_skipped.load(Ordering::Relaxed);
+        info!(
+            "Skipped {} tiles that were already converted, converted {} tiles",
+            nr_skipped,
+            nr_ok - nr_skipped
+        );
+    }
+    info!("Done");
+    Ok((report, tiles_failed))
+}
+
+/// The last phase of a [run]: write the tileset and the other outputs.
+fn write_tileset(
+    cli: &cli::Cli,
+    settings: &RunSettings,
+    index: &FeatureIndex,
+    mut tileset: formats::cesium3dtiles::Tileset,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let RunSettings {
+        bounding_volume,
+        ref output,
+        ..
+    } = *settings;
+    let FeatureIndex {
+        ref world,
+        ref quadtree,
+        ..
+    } = *index;
+    let tileset_path = output.join("tileset.json");
+    if cli.prefetch_hints {
+        info!("Computing tile prefetch priorities");
+        tileset.set_prefetch_priorities(&quadtree, &world.grid);
+    }
+
+    if bounding_volume == formats::cesium3dtiles::BoundingVolumeType::Box {
+        info!("Converting the bounding volumes to boxes");
+        tileset.use_box_bounding_volumes()?;
+    }
+
+    // The content is checked before the --content-uri-prefix is prepended, because the
+    // prefixed URIs point to where the tiles are served from
+    let missing_content = match cli.validate_output {
+        true => Some(tileset.missing_content(&output)),
+        false => None,
+    };
+
+    if let Some(ref content_uri_prefix) = cli.content_uri_prefix {
+        if cli.archive.is_some() && !content_uri_prefix.is_empty() {
+            warn!("The content URIs with the --content-uri-prefix do not resolve inside the --archive");
+        }
+        tileset.set_content_uri_prefix(content_uri_prefix);
+    }
+
+    info!("Writing 3D Tiles tileset");
+    match cli.inline_levels {
+        Some(inline_levels) => tileset.to_file_split(&tileset_path, inline_levels)?,
+        None => tileset.to_file(&tileset_path)?,
+    }
+
+    if let Some(missing_content) = missing_content {
+        for (tileid, uri) in missing_content.iter() {
+            error!(
+                "The content {} of the tile {} is missing or empty",
+                uri, tileid
+            );
+        }
+        if !missing_content.is_empty() {
+            return Err(format!(
+                "{} tiles of the tileset reference missing or empty content files",
+                missing_content.len()
+            )
+            .into());
+        }
+        info!("Validated the content files of the tileset");
+    }
+
+    if cli.output_crs_info {
+        info!("Writing CRS information to {:?}", &output);
+        formats::crs_info::write_crs_info(&output, &world, cli.cesium3dtiles_local_transforms)?;
+    }
+
+    if let Some(ref qa_camera_manifest_path) = cli.qa_camera_manifest {
+        info!(
+            "Writing QA camera manifest to {:?}",
+            qa_camera_manifest_path
+        );
+        formats::qa::write_camera_manifest(qa_camera_manifest_path, &quadtree, &world)?;
+    }
+
+    if cli.archive == Some(archive::ArchiveFormat::ThreeTz) {
+        let path_output = output.canonicalize()?;
+        let mut archive_name = path_output.file_name().unwrap_or_default().to_os_string();
+        archive_name.push(".3tz");
+        let path_archive = path_output.with_file_name(archive_name);
+        let files = archive::tileset_files(&path_output)?;
+        info!(
+            "Writing {} files into the 3TZ archive {:?}",
+            files.len(),
+            &path_archive
+        );
+        archive::write_3tz(&path_output, &files, &path_archive)?;
+        if cli.archive_remove_files {
+            info!("Removing the archived files from {:?}", &path_output);
+            archive::remove_tileset_files(&path_output, &files)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::ExitStatus;
+
+    #[test]
+    fn test_retry_policy() {
+        // The raw wait status of an exit with code 1, and of the termination by SIGSEGV
+        let exited = ExitStatus::from_raw(1 << 8);
+        let crashed = ExitStatus::from_raw(11);
+        let succeeded = ExitStatus::from_raw(0);
+        assert!(RetryPolicy::Always.should_retry(&exited, false));
+        assert!(RetryPolicy::Always.should_retry(&crashed, false));
+        assert!(RetryPolicy::Always.should_retry(&exited, true));
+        assert!(!RetryPolicy::Always.should_retry(&succeeded, false));
+        assert!(RetryPolicy::OnCrash.should_retry(&crashed, false));
+        assert!(!RetryPolicy::OnCrash.should_retry(&exited, false));
+        assert!(!RetryPolicy::OnCrash.should_retry(&succeeded, false));
+        // A subprocess that was killed after the timeout did not crash
+        assert!(!RetryPolicy::OnCrash.should_retry(&crashed, true));
+    }
+
+    #[test]
+    fn test_run_errors() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join("tyler_test_run_errors");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("features")).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/resources/data/3dbag_feature_x71.city.jsonl"
+            ),
+            dir.join("features").join("x71.city.jsonl"),
+        )
+        .unwrap();
+        let path_features = dir.join("features");
+        let path_output = dir.join("output");
+        let run_with = |args: &[&str]| -> String {
+            let mut all_args = vec![
+                "tyler",
+                "-m",
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/resources/data/3dbag_x00.city.json"
+                ),
+                "-f",
+                path_features.to_str().unwrap(),
+                "-o",
+                path_output.to_str().unwrap(),
+            ];
+            all_args.extend(args);
+            run(TilerConfig::parse_from(all_args))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        // The user errors are returned, instead of panicking
+        let path_exe = dir.join("features");
+        assert!(run_with(&["--exe-geof", path_exe.to_str().unwrap()]).contains("exe_geof"));
+        assert!(run_with(&["--converter", "native"]).contains("--3dtiles-metadata-class"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tmp_dir_existing() {
+        let dir = std::env::temp_dir().join("tyler_test_tmp_dir_existing");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("other")).unwrap();
+        fs::write(dir.join("other").join("file.txt"), "keep").unwrap();
+        let tmp_dir = TmpDir::create(dir.clone(), false, false).unwrap();
+        tmp_dir.track_new(&dir.join("features"));
+        fs::create_dir_all(dir.join("features")).unwrap();
+        fs::write(dir.join("features").join("1.city.jsonl"), "{}").unwrap();
+        tmp_dir
+            .write(&dir.join("0").join("0").join("0-0-0.input"), "a.city.jsonl")
+            .unwrap();
+        tmp_dir
+            .write(&dir.join("other").join("1-0-0.input"), "b.city.jsonl")
+            .unwrap();
+        drop(tmp_dir);
+        // Only the files that tyler created are removed from an existing directory
+        assert!(dir.join("other").join("file.txt").exists());
+        assert!(!dir.join("other").join("1-0-0.input").exists());
+        assert!(!dir.join("features").exists());
+        assert!(!dir.join("0").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tmp_dir_owned() {
+        let dir = std::env::temp_dir().join("tyler_test_tmp_dir_owned");
+        let _ = fs::remove_dir_all(&dir);
+        let tmp_dir = TmpDir::create(dir.clone(), false, false).unwrap();
+        fs::write(dir.join("tyler_index.json"), "{}").unwrap();
+        drop(tmp_dir);
+        // A --tmp-dir that did not exist before is removed completely
+        assert!(!dir.exists());
+    }
+}
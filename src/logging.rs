@@ -0,0 +1,95 @@
+//! Formatting the log records.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cell::RefCell;
+use std::io::Write;
+
+use serde_json::json;
+
+/// The format of the log records.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines of the env_logger.
+    #[default]
+    Text,
+    /// One JSON object per line, with the `timestamp`, `level`, `target`, `message` and
+    /// the `tile` that is converted when the record is logged.
+    Json,
+}
+
+thread_local! {
+    /// The ID of the tile that is converted in the current thread.
+    static TILE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Initialize the logger. The log level is set with the `RUST_LOG` environment variable.
+pub fn init(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let timestamp = buf.timestamp_millis().to_string();
+            let tile = TILE.with(|tile| tile.borrow().clone());
+            writeln!(buf, "{}", json_record(&timestamp, record, tile.as_deref()))
+        });
+    }
+    builder.init();
+}
+
+/// Add the tile ID to the JSON log records that are logged by the current thread while
+/// `f` runs.
+pub fn with_tile<T>(tile: &str, f: impl FnOnce() -> T) -> T {
+    let previous = TILE.with(|t| t.replace(Some(tile.to_string())));
+    let result = f();
+    TILE.with(|t| t.replace(previous));
+    result
+}
+
+fn json_record(timestamp: &str, record: &log::Record, tile: Option<&str>) -> String {
+    let mut record_json = json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    if let Some(tile) = tile {
+        record_json["tile"] = json!(tile);
+    }
+    record_json.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_record() {
+        let record = log::Record::builder()
+            .level(log::Level::Warn)
+            .target("tyler")
+            .args(format_args!("conversion \"failed\"\nwith output"))
+            .build();
+        let record_json: serde_json::Value = serde_json::from_str(&json_record(
+            "2023-01-01T00:00:00.000Z",
+            &record,
+            Some("10/4/7"),
+        ))
+        .unwrap();
+        assert_eq!(record_json["level"], "WARN");
+        assert_eq!(record_json["message"], "conversion \"failed\"\nwith output");
+        assert_eq!(record_json["tile"], "10/4/7");
+        let record_json: serde_json::Value =
+            serde_json::from_str(&json_record("", &record, None)).unwrap();
+        assert!(record_json.get("tile").is_none());
+    }
+}
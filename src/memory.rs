@@ -0,0 +1,121 @@
+//! Limiting the memory use of the tile conversion.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fs::read_to_string;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::Duration;
+
+use log::{info, warn};
+
+/// How long a worker waits before it checks the memory use again.
+const THROTTLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A soft limit on the memory use of tyler and its conversion subprocesses.
+///
+/// The workers call [MemoryThrottle::wait] before starting a conversion subprocess, which
+/// blocks while the resident set size (RSS) of tyler and the running subprocesses is
+/// above the limit. Thus, the number of concurrent conversions is reduced until the
+/// memory use drops. At least one conversion is always allowed to run, so that the
+/// conversion can progress.
+///
+/// The RSS is read from `/proc`, so the limit only has an effect on Linux.
+#[derive(Debug)]
+pub struct MemoryThrottle {
+    limit_bytes: u64,
+    running: Mutex<Vec<u32>>,
+    throttling: AtomicBool,
+}
+
+impl MemoryThrottle {
+    pub fn new(limit_bytes: u64) -> Self {
+        if rss_bytes("self").is_none() {
+            warn!("Cannot read the memory use of the process, --max-memory has no effect");
+        }
+        Self {
+            limit_bytes,
+            running: Mutex::new(Vec::new()),
+            throttling: AtomicBool::new(false),
+        }
+    }
+
+    /// The RSS of tyler and the running conversion subprocesses.
+    fn used_bytes(&self, running: &[u32]) -> u64 {
+        let children: u64 = running
+            .iter()
+            .filter_map(|pid| rss_bytes(&pid.to_string()))
+            .sum();
+        rss_bytes("self").unwrap_or(0) + children
+    }
+
+    /// Block until a new conversion subprocess can be started.
+    pub fn wait(&self) {
+        loop {
+            {
+                let running = self.running.lock().unwrap();
+                if running.is_empty() || self.used_bytes(&running) < self.limit_bytes {
+                    if self.throttling.swap(false, Ordering::Relaxed) {
+                        info!("Memory use is below --max-memory, resuming the conversion");
+                    }
+                    return;
+                }
+            }
+            if !self.throttling.swap(true, Ordering::Relaxed) {
+                info!("Memory use reached --max-memory, throttling the conversion");
+            }
+            sleep(THROTTLE_INTERVAL);
+        }
+    }
+
+    /// Register a running conversion subprocess.
+    pub fn started(&self, pid: Option<u32>) {
+        if let Some(pid) = pid {
+            self.running.lock().unwrap().push(pid);
+        }
+    }
+
+    /// Unregister a conversion subprocess when it is finished.
+    pub fn finished(&self, pid: Option<u32>) {
+        if let Some(pid) = pid {
+            self.running.lock().unwrap().retain(|p| *p != pid);
+        }
+    }
+}
+
+/// The RSS of the process `pid` (or "self") in bytes, or `None` if it cannot be read.
+fn rss_bytes(pid: &str) -> Option<u64> {
+    let statm = read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(parse_statm_rss_pages(&statm)? * page_size as u64)
+}
+
+/// The RSS in pages is the second field of `/proc/<pid>/statm`.
+fn parse_statm_rss_pages(statm: &str) -> Option<u64> {
+    statm.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_statm_rss_pages() {
+        assert_eq!(
+            parse_statm_rss_pages("3261 1626 1142 188 0 339 0\n"),
+            Some(1626)
+        );
+        assert_eq!(parse_statm_rss_pages(""), None);
+    }
+}
@@ -14,75 +14,244 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::fs::read_to_string;
+use std::fs::{read_to_string, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, Read, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
 
-use log::{debug, error, info};
-use serde::Deserialize;
+use flate2::read::GzDecoder;
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use serde_json::from_str;
 use walkdir::WalkDir;
 
-use crate::spatial_structs::BboxQc;
+use crate::spatial_structs::{Bbox, BboxQc};
+
+/// How the features are assigned to the grid cells, and thus to the tiles.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum FeatureAssignment {
+    /// Assign each feature to the single cell that contains its centroid, so that a
+    /// feature is in only one tile. A centroid on the boundary of two cells is assigned to
+    /// the cell with the greater row or column, because the cells contain their minimum
+    /// boundary but not their maximum boundary.
+    Centroid,
+    /// Assign a feature to all the cells that intersect its bbox, except buildings, which
+    /// are assigned to the cell that contains the most of their vertices.
+    #[default]
+    AllCells,
+}
 
 /// Represents the "world" that contains some features and needs to be partitioned into
 /// tiles.
 ///
 /// # Members
 ///
-/// `path_features_root` - The path to the root directory containing all features.
+/// `path_features_root` - The path to the root directory containing all features, or to
+/// a text file that lists the feature files (see [World::feature_paths]).
 ///
 /// `path_metadata` - The path to the JSON file that stores the
 /// [CityJSON object](https://www.cityjson.org/specs/1.1.3/#cityjson-object)
 /// (also called CityJSON metadata in *tyler*).
 ///
 /// `cityobject_types` - The World only contains features of these types.
+///
+/// `dedup_features` - Drop the features that are exact duplicates of a feature that was
+/// already indexed (same CityObject IDs and geometry).
+///
+/// `feature_assignment` - How the features are assigned to the grid cells.
+///
+/// `feature_filters` - The World only contains the features that have a CityObject (of the
+/// `cityobject_types`) that matches all the filters.
+///
+/// `features_extensions` - The file extensions of the feature files in the
+/// `path_features_root` directory (eg. `jsonl`), see [World::feature_paths].
+///
+/// `invalid_features` - The features that were excluded by [World::index_with_grid],
+/// because they have no vertices or their coordinates are not finite.
+///
+/// `skip_invalid` - Skip the invalid features instead of failing the indexing.
+///
+/// `verify_metadata` - Fail the indexing if there are features that are far outside of
+/// the `geographical_extent` of the metadata, see [World::features_outside_metadata].
+///
+/// `sample` - Only index a random subset of the features, which is selected with the
+/// `sample_seed`, see [Sample].
+///
+/// # Memory use
+///
+/// The World does not keep the geometry of the features in memory. Each feature file is
+/// parsed once for computing the extent and once for indexing, and only the [Feature]
+/// (centroid, bbox, vertex count and file path) is retained. The conversion subprocess
+/// reads the geometry from the feature files, which are referenced by their path in the
+/// `.input` files of the tiles.
 pub struct World {
+    /// The attribution of the source data from the metadata, see [Metadata::attribution].
+    pub attribution: Option<String>,
     pub cityobject_types: Option<Vec<CityObjectType>>,
+    /// The CRS of the input, which is the `referenceSystem` of the metadata, unless it is
+    /// overridden with `--source-crs`.
     pub crs: Crs,
+    pub dedup_features: bool,
+    /// The 2D extent (`[minx, miny, maxx, maxy]`) that overrides the extent of the
+    /// features. The features outside of it are dropped.
+    pub extent: Option<[f64; 4]>,
+    pub feature_assignment: FeatureAssignment,
+    pub feature_filters: Vec<FeatureFilter>,
     pub features: FeatureSet,
+    pub features_extensions: Vec<String>,
+    /// The `geographicalExtent` of the metadata.
+    pub geographical_extent: Option<Bbox>,
     pub grid: crate::spatial_structs::SquareGrid,
+    pub invalid_features: Vec<PathBuf>,
     pub path_features_root: PathBuf,
     pub path_metadata: PathBuf,
+    pub sample: Option<Sample>,
+    pub sample_seed: u64,
+    pub skip_invalid: bool,
     pub transform: Transform,
+    pub verify_metadata: bool,
+}
+
+/// The index of a [World], see [World::write_index].
+#[derive(Deserialize)]
+struct WorldIndex {
+    attribution: Option<String>,
+    crs: Crs,
+    transform: Transform,
+    geographical_extent: Option<Bbox>,
+    grid: crate::spatial_structs::SquareGrid,
+    features: FeatureSet,
+    invalid_features: Vec<PathBuf>,
 }
 
 impl World {
+    /// If `cellsize` is `None`, the cell size is computed from the extent and the number
+    /// of the features, see [World::auto_cellsize].
+    /// If `extent` is set, the grid is created over this 2D extent instead of the extent
+    /// of the features.
+    /// The `source_crs` overrides the `referenceSystem` of the metadata.
+    /// The files with the `features_extensions` are the features, by default the `.jsonl`
+    /// files.
+    /// Returns an error if there are more feature files than `max_features`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new<P: AsRef<Path>>(
         path_metadata: P,
         path_features_root: P,
-        cellsize: u16,
+        cellsize: Option<[u16; 2]>,
+        features_per_cell: usize,
         cityobject_types: Option<Vec<CityObjectType>>,
         arg_minz: Option<i32>,
         arg_maxz: Option<i32>,
+        extent: Option<[f64; 4]>,
+        dedup_features: bool,
+        max_features: Option<usize>,
+        source_crs: Option<Crs>,
+        features_extensions: Option<Vec<String>>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let path_features_root = path_features_root.as_ref().to_path_buf();
         let path_metadata = path_metadata.as_ref().to_path_buf();
+        let features_extensions =
+            features_extensions.unwrap_or_else(|| vec![DEFAULT_FEATURES_EXTENSION.to_string()]);
+        // Counting the files is cheap compared to computing the extent, which parses
+        // every feature
+        if let Some(max_features) = max_features {
+            let nr_feature_files =
+                Self::feature_paths(&path_features_root, &features_extensions).count();
+            if nr_feature_files > max_features {
+                return Err(format!(
+                    "found {} feature files in {:?}, which is more than the --max-features {}",
+                    nr_feature_files, &path_features_root, max_features
+                )
+                .into());
+            }
+        }
         let cm = CityJSONMetadata::from_file(&path_metadata)?;
-        let crs = cm.metadata.reference_system;
+        let attribution = cm.metadata.attribution();
+        let geographical_extent = cm.metadata.geographical_extent;
+        let crs = match (source_crs, cm.metadata.reference_system) {
+            (Some(source_crs), reference_system) => {
+                if reference_system.is_some() {
+                    info!(
+                        "Using the --source-crs {} instead of the referenceSystem of the metadata",
+                        &source_crs.0
+                    );
+                }
+                source_crs
+            }
+            (None, Some(reference_system)) => reference_system,
+            (None, None) => {
+                return Err(format!(
+                "the metadata {:?} does not have a referenceSystem, set the CRS with --source-crs",
+                &path_metadata
+            )
+                .into())
+            }
+        };
         let transform = cm.transform;
+        // The vertices are integers, so their real-world coordinates are finite if the
+        // transform is finite
+        if !transform
+            .scale
+            .iter()
+            .chain(transform.translate.iter())
+            .all(|v| v.is_finite())
+        {
+            return Err(format!(
+                "the transform of the metadata {:?} is not finite: {:?}",
+                &path_metadata, &transform
+            )
+            .into());
+        }
 
-        // FIXME: if cityobject_types is None, then all cityobject are ignored, instead of included
         // Compute the extent of the features and the number of features.
         // We don't store the computed extent explicitly, because the grid contains that info.
-        let (extent_qc, nr_features, cityobject_types_ignored) =
-            Self::extent_qc(&path_features_root, cityobject_types.as_ref());
+        let (extent_qc, nr_features, cityobject_types_ignored) = Self::extent_qc(
+            &path_features_root,
+            &features_extensions,
+            cityobject_types.as_ref(),
+        );
         info!(
             "Found {} features of type {:?}",
             nr_features, &cityobject_types
         );
         info!("Ignored feature types: {:?}", &cityobject_types_ignored);
         debug!("extent_qc: {:?}", &extent_qc);
-        let extent_rw = extent_qc.to_bbox(&transform, arg_minz, arg_maxz);
+        let mut extent_rw = extent_qc.to_bbox(&transform, arg_minz, arg_maxz);
         info!(
             "Computed extent from features in real-world coordinates: {:?}",
             &extent_rw
         );
+        if let Some([minx, miny, maxx, maxy]) = extent {
+            extent_rw = [minx, miny, extent_rw[2], maxx, maxy, extent_rw[5]];
+            info!("Using the extent {:?} instead", &extent_rw);
+        }
+
+        let cellsize = match cellsize {
+            Some(cellsize) => cellsize,
+            None => {
+                let cellsize = Self::auto_cellsize(&extent_rw, nr_features, features_per_cell);
+                info!(
+                    "Computed the grid cell size {} for {} features per cell",
+                    cellsize, features_per_cell
+                );
+                [cellsize, cellsize]
+            }
+        };
 
         // Allocate the grid, but at this point it is still empty
         let epsg = crs.to_epsg()?;
-        let grid = crate::spatial_structs::SquareGrid::new(&extent_rw, cellsize, epsg, Some(10.0));
+        let grid = crate::spatial_structs::SquareGrid::new_rectangular(
+            &extent_rw,
+            cellsize,
+            epsg,
+            Some(10.0),
+        );
         debug!("{}", grid);
 
         // Allocate the features container, but at this point it is still empty
@@ -90,20 +259,111 @@ impl World {
         features.resize(nr_features + 1, Feature::default());
 
         Ok(Self {
+            attribution,
             features,
             crs,
             transform,
             grid,
             cityobject_types,
+            dedup_features,
+            extent,
+            feature_assignment: FeatureAssignment::default(),
+            feature_filters: Vec::new(),
+            features_extensions,
+            geographical_extent,
+            invalid_features: Vec::new(),
             path_features_root,
             path_metadata,
+            sample: None,
+            sample_seed: 0,
+            skip_invalid: false,
+            verify_metadata: false,
         })
     }
 
+    /// Write the grid and the features of [World::index_with_grid] to a JSON file, so that
+    /// the tiles can be converted in a different run without indexing the features again
+    /// (see [World::from_index]).
+    pub fn write_index(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let index = serde_json::json!({
+            "attribution": &self.attribution,
+            "crs": &self.crs,
+            "transform": &self.transform,
+            "geographical_extent": &self.geographical_extent,
+            "grid": &self.grid,
+            "features": &self.features,
+            "invalid_features": &self.invalid_features,
+        });
+        let writer = std::io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, &index)?;
+        Ok(())
+    }
+
+    /// Create the World from the index that was written with [World::write_index]. The
+    /// options that are only used for indexing the features are left at their defaults.
+    pub fn from_index<P: AsRef<Path>>(
+        path_index: P,
+        path_metadata: P,
+        path_features_root: P,
+        cityobject_types: Option<Vec<CityObjectType>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = std::io::BufReader::new(File::open(path_index.as_ref())?);
+        let index: WorldIndex = serde_json::from_reader(reader)
+            .map_err(|e| format!("could not read the index {:?}: {}", path_index.as_ref(), e))?;
+        Ok(Self {
+            attribution: index.attribution,
+            cityobject_types,
+            crs: index.crs,
+            dedup_features: false,
+            extent: None,
+            feature_assignment: Default::default(),
+            feature_filters: Vec::new(),
+            features_extensions: vec![DEFAULT_FEATURES_EXTENSION.to_string()],
+            features: index.features,
+            geographical_extent: index.geographical_extent,
+            grid: index.grid,
+            invalid_features: index.invalid_features,
+            path_features_root: path_features_root.as_ref().to_path_buf(),
+            path_metadata: path_metadata.as_ref().to_path_buf(),
+            sample: None,
+            sample_seed: 0,
+            skip_invalid: false,
+            transform: index.transform,
+            verify_metadata: false,
+        })
+    }
+
+    /// The z bounds of the grid, which are computed from the features and limited by the
+    /// `--grid-minz`/`--grid-maxz` arguments. They are rounded outwards to whole input units.
+    pub fn z_bounds(&self) -> (i32, i32) {
+        (
+            self.grid.bbox[2].floor() as i32,
+            self.grid.bbox[5].ceil() as i32,
+        )
+    }
+
+    /// The cell size at which the cells of the `extent` have `features_per_cell` features
+    /// on average, if the `nr_features` were distributed evenly over the extent.
+    fn auto_cellsize(
+        extent: &crate::spatial_structs::Bbox,
+        nr_features: usize,
+        features_per_cell: usize,
+    ) -> u16 {
+        let area = (extent[3] - extent[0]) * (extent[4] - extent[1]);
+        if nr_features == 0 || area <= 0.0 {
+            return 1;
+        }
+        (area * features_per_cell as f64 / nr_features as f64)
+            .sqrt()
+            .round()
+            .clamp(1.0, u16::MAX as f64) as u16
+    }
+
     /// Compute the extent (in quantized coordinates), the number of features and the
     /// CityObject types that are present in the data but not selected.
     fn extent_qc<P: AsRef<Path> + std::fmt::Debug>(
         path_features: P,
+        features_extensions: &[String],
         cityobject_types: Option<&Vec<CityObjectType>>,
     ) -> (BboxQc, usize, Vec<CityObjectType>) {
         info!(
@@ -112,9 +372,7 @@ impl World {
         );
         // Do a first loop over the features to calculate their extent and their number.
         // Need a mutable iterator, because .next() consumes the next value and advances the iterator.
-        let mut features_enum_iter = WalkDir::new(&path_features)
-            .into_iter()
-            .filter_map(Self::jsonl_path);
+        let mut features_enum_iter = Self::feature_paths(&path_features, features_extensions);
         // Init the extent with from the first feature of the requested types
         let mut extent_qc = BboxQc([0, 0, 0, 0, 0, 0]);
         let mut found_feature_type = false;
@@ -141,8 +399,9 @@ impl World {
                 }
             } else {
                 panic!(
-                    "Did not find any CityJSONFeature file in {:?}",
-                    &path_features
+                    "Did not find any CityJSONFeature file in {:?} (with the --features-extension {})",
+                    &path_features,
+                    features_extensions.join(",")
                 );
             }
         }
@@ -156,22 +415,7 @@ impl World {
         for feature_path in features_enum_iter {
             if let Ok(cf) = CityJSONFeatureVertices::from_file(&feature_path) {
                 if let Some(bbox_qc) = cf.bbox_of_types(cityobject_types) {
-                    let [x_min, y_min, z_min, x_max, y_max, z_max] = bbox_qc.0;
-                    if x_min < extent_qc.0[0] {
-                        extent_qc.0[0] = x_min
-                    } else if x_max > extent_qc.0[3] {
-                        extent_qc.0[3] = x_max
-                    }
-                    if y_min < extent_qc.0[1] {
-                        extent_qc.0[1] = y_min
-                    } else if y_max > extent_qc.0[4] {
-                        extent_qc.0[4] = y_max
-                    }
-                    if z_min < extent_qc.0[2] {
-                        extent_qc.0[2] = z_min
-                    } else if z_max > extent_qc.0[5] {
-                        extent_qc.0[5] = z_max
-                    }
+                    extent_qc.update_with(&bbox_qc);
                     nr_features += 1;
                 } else {
                     for (_, co) in cf.cityobjects.iter() {
@@ -187,15 +431,75 @@ impl World {
         (extent_qc, nr_features, cotypes_ignored)
     }
 
-    /// Return the file path if the 'DirEntry' is a .jsonl file (eg. .city.jsonl).
-    pub fn jsonl_path(walkdir_res: Result<walkdir::DirEntry, walkdir::Error>) -> Option<PathBuf> {
+    /// The paths of the feature files in `path_features`, which is either a directory or a
+    /// list of files. The directory and all its subdirectories are searched for the files
+    /// with one of the `features_extensions` (eg. `jsonl` for `.city.jsonl`), also gzipped
+    /// (eg. `.city.jsonl.gz`). A list is a text file (`.txt`) with one path per line, and
+    /// the relative paths are resolved against the directory of the list. Empty lines and
+    /// lines starting with `#` are ignored.
+    pub fn feature_paths<P: AsRef<Path>>(
+        path_features: P,
+        features_extensions: &[String],
+    ) -> Box<dyn Iterator<Item = PathBuf>> {
+        let path_features = path_features.as_ref();
+        if !Self::is_feature_list(path_features) {
+            let features_extensions = features_extensions.to_vec();
+            return Box::new(
+                WalkDir::new(path_features)
+                    .into_iter()
+                    .filter_map(move |entry| Self::feature_file_path(entry, &features_extensions)),
+            );
+        }
+        let list = match read_to_string(path_features) {
+            Ok(list) => list,
+            Err(e) => {
+                error!("Failed to read the feature list {:?}: {}", path_features, e);
+                return Box::new(std::iter::empty());
+            }
+        };
+        let list_dir = path_features
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let paths: Vec<PathBuf> = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| list_dir.join(line))
+            .collect();
+        Box::new(paths.into_iter())
+    }
+
+    /// Is `path_features` a list of feature files, instead of a directory?
+    pub fn is_feature_list<P: AsRef<Path>>(path_features: P) -> bool {
+        let path_features = path_features.as_ref();
+        path_features.is_file() && path_features.extension().is_some_and(|ext| ext == "txt")
+    }
+
+    /// Is `path_features` a CityJSONSeq file (`.jsonl`, eg. `.city.jsonl`), instead of a
+    /// directory? The first line of the file can be the CityJSON object with the metadata.
+    pub fn is_cityjsonseq<P: AsRef<Path>>(path_features: P) -> bool {
+        let path_features = path_features.as_ref();
+        path_features.is_file() && path_features.extension().is_some_and(|ext| ext == "jsonl")
+    }
+
+    /// Return the file path if the 'DirEntry' is a file with one of the
+    /// `features_extensions` (eg. .city.jsonl for `jsonl`), or a gzipped file with the
+    /// extension (eg. .city.jsonl.gz).
+    pub fn feature_file_path(
+        walkdir_res: Result<walkdir::DirEntry, walkdir::Error>,
+        features_extensions: &[String],
+    ) -> Option<PathBuf> {
         if let Ok(entry) = walkdir_res {
-            if let Some(ext) = entry.path().extension() {
-                if ext == "jsonl" {
-                    Some(entry.path().to_path_buf())
-                } else {
-                    None
-                }
+            let file_name = entry.file_name().to_str()?;
+            let file_name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+            let is_feature = features_extensions.iter().any(|ext| {
+                file_name
+                    .strip_suffix(ext.trim_start_matches('.'))
+                    .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+            });
+            if is_feature && entry.file_type().is_file() {
+                Some(entry.path().to_path_buf())
             } else {
                 None
             }
@@ -206,110 +510,469 @@ impl World {
     }
 
     // Loop through the features and assign the features to the grid cells.
-    pub fn index_with_grid(&mut self) {
-        let feature_set_paths_iter = WalkDir::new(&self.path_features_root)
-            .into_iter()
-            .filter_map(Self::jsonl_path)
-            .enumerate();
-        // For each feature_path (parallel) -- but we would need to mutate a variable from a parallel loop, creating a data race condition, we'll fix this later
-        //  parse the feature
-        //  for each vertex of the feature
-        //      cellid <- locate vertex in grid
-        //      cell <- get mutable cell reference from cellid
-        //      increment vertex count in cell
-        //      add feature id to cell
+    // Fails if there are invalid features, unless `skip_invalid` is set.
+    //
+    // The features are parsed and located in the grid in parallel, in chunks of
+    // INDEX_CHUNK_SIZE features. Then the results of a chunk are added to the grid in the
+    // order of the feature paths, so that the feature IDs and the cell contents are the
+    // same in each run.
+    pub fn index_with_grid(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let indexing_start = Instant::now();
+        let mut feature_set_paths_iter =
+            Self::feature_paths(&self.path_features_root, &self.features_extensions);
         info!("Counting vertices in grid cells");
         let mut fid: usize = 0;
-        for (_, feature_path) in feature_set_paths_iter {
-            let cf = CityJSONFeatureVertices::from_file(&feature_path);
-            if let Ok(featurevertices) = cf {
-                // We make a (cellid, vertex count) map and assign the feature to the cell that
-                // contains the most of the feature's vertices.
-                // But maybe a HashMap is not the most performant solution here? A Vec of tuples?
-                let mut cell_vtx_cnt: HashMap<crate::spatial_structs::CellId, usize> =
-                    HashMap::new();
-                for (_, co) in featurevertices.cityobjects.iter() {
-                    // If the object_type argument was not passed, that means that we need all
-                    // CityObject types. If it was passed, then we filter with its values.
-                    // Doing this condition-tree would be much simpler if Option.is_some_and()
-                    // was stable feature already.
-                    let mut do_compute = self.cityobject_types.is_none();
-                    if let Some(ref cotypes) = self.cityobject_types {
-                        do_compute = cotypes.contains(&co.cotype);
+        let mut feature_hashes: HashSet<u64> = HashSet::new();
+        let mut nr_duplicates: usize = 0;
+        let mut nr_filtered: usize = 0;
+        let mut nr_outside: usize = 0;
+        let mut nr_not_sampled: usize = 0;
+        let sample_threshold = self.sample.map(|sample| self.sample_threshold(sample));
+        loop {
+            let mut chunk: Vec<PathBuf> = feature_set_paths_iter
+                .by_ref()
+                .take(INDEX_CHUNK_SIZE)
+                .collect();
+            if chunk.is_empty() {
+                break;
+            }
+            if let Some(threshold) = sample_threshold {
+                let nr_paths = chunk.len();
+                chunk.retain(|feature_path| self.sample_key(feature_path) <= threshold);
+                nr_not_sampled += nr_paths - chunk.len();
+            }
+            let indexed: Vec<IndexedFeature> = chunk
+                .par_iter()
+                .map(|feature_path| self.index_feature(feature_path))
+                .collect();
+            for (feature_path, indexed_feature) in chunk.into_iter().zip(indexed) {
+                let (hash, cells) = match indexed_feature {
+                    IndexedFeature::Unparsable => {
+                        error!("Failed to parse the feature {:?}", &feature_path);
+                        continue;
+                    }
+                    IndexedFeature::Filtered => {
+                        nr_filtered += 1;
+                        continue;
+                    }
+                    IndexedFeature::Invalid => {
+                        warn!("The feature {:?} has no vertices", &feature_path);
+                        self.invalid_features.push(feature_path);
+                        continue;
+                    }
+                    IndexedFeature::Valid { hash, cells } => (hash, cells),
+                };
+                if self.dedup_features && !feature_hashes.insert(hash) {
+                    debug!("Dropping duplicate feature {:?}", &feature_path);
+                    nr_duplicates += 1;
+                    continue;
+                }
+                let Some(cells) = cells else {
+                    debug!("Dropping feature outside of the extent {:?}", &feature_path);
+                    nr_outside += 1;
+                    continue;
+                };
+                let FeatureCells {
+                    feature,
+                    cell_vtx_cnt,
+                } = cells;
+                if cell_vtx_cnt.is_empty() {
+                    continue;
+                }
+                // We found at least one CityObject of the required type
+                self.features[fid] = feature;
+                // TODO: what other cityobject types need to have 1-1 cell assignment?
+                if self.feature_assignment == FeatureAssignment::Centroid {
+                    // The cell gets all the vertices of the feature. With --extent,
+                    // the centroid can be outside of the grid, then the feature is
+                    // assigned to the nearest cell on the border of the grid.
+                    let centroid = self.features[fid].centroid(&self.transform);
+                    let located = self.grid.locate_point(&centroid);
+                    let cellid = crate::spatial_structs::CellId {
+                        row: located.row.min(self.grid.length - 1),
+                        column: located.column.min(self.grid.length - 1),
+                    };
+                    let cell = self.grid.cell_mut(&cellid);
+                    cell.nr_vertices += cell_vtx_cnt.values().sum::<usize>();
+                    cell.feature_ids.push(fid);
+                    cell.nr_bytes += self.features[fid].nr_bytes as usize;
+                } else if self.features[fid].has_building {
+                    // In this case we have a 1-1 feature-to-cell assignment, we only retain the vertex
+                    // count in the cell that gets the feature.
+                    // The cell that receives the feature is the one with the highest vertex count
+                    // of the feature.
+                    // The assignment method is chosen per feature, so that cityobject types that
+                    // require different cell-assignment methods can be combined into the same
+                    // tileset. E.g. terrain features need to be duplicated across cells, buildings
+                    // need to unique.
+                    // The ties are broken by the cell ID, so that the assignment does not depend
+                    // on the iteration order of the HashMap.
+                    let (cellid, nr_vertices) = cell_vtx_cnt
+                        .iter()
+                        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                        .unwrap();
+                    let cell = self.grid.cell_mut(cellid);
+                    cell.nr_vertices += nr_vertices;
+                    if !cell.feature_ids.contains(&fid) {
+                        cell.feature_ids.push(fid);
+                        cell.nr_bytes += self.features[fid].nr_bytes as usize;
                     }
-                    if do_compute {
-                        // Just counting vertices here
-                        for vtx_qc in featurevertices.vertices.iter() {
-                            let vtx_rw = [
-                                (vtx_qc[0] as f64 * self.transform.scale[0])
-                                    + self.transform.translate[0],
-                                (vtx_qc[1] as f64 * self.transform.scale[1])
-                                    + self.transform.translate[1],
-                            ];
-                            let cellid = self.grid.locate_point(&vtx_rw);
-                            *cell_vtx_cnt.entry(cellid).or_insert(1) += 1;
+                } else {
+                    for (cellid, nr_vertices) in cell_vtx_cnt.iter() {
+                        let cell = self.grid.cell_mut(cellid);
+                        cell.nr_vertices += nr_vertices;
+                        if !cell.feature_ids.contains(&fid) {
+                            cell.feature_ids.push(fid);
+                            cell.nr_bytes += self.features[fid].nr_bytes as usize;
                         }
                     }
                 }
-                // After counting the object vertices in the cells, we need to
-                // assign the object to the cells that intersect with its bbox,
-                // because of https://github.com/3DGI/tyler/issues/28
-                if let Some(bbox_qc) = featurevertices.bbox_of_types(self.cityobject_types.as_ref())
-                {
-                    let bbox = bbox_qc.to_bbox(&self.transform, None, None);
-                    let intersecting_cellids = self.grid.intersect_bbox(&bbox);
-                    for cellid in intersecting_cellids {
-                        // Just add a new entry with the intersecting cell to the map, but no not
-                        // increase the vertex count, because the vertices have been counted
-                        // already, these might be cells where the object does not actually have a
-                        // vertex.
-                        // REVIEW: actually, let's just increase the vertex count
-                        *cell_vtx_cnt.entry(cellid).or_insert(1) += 1;
+                fid += 1;
+            }
+        }
+        info!(
+            "Indexed {} features in {:.1?}",
+            fid,
+            indexing_start.elapsed()
+        );
+        if self.dedup_features {
+            info!("Dropped {} duplicate features", nr_duplicates);
+        }
+        if !self.feature_filters.is_empty() {
+            info!("Excluded {} features with the feature filters", nr_filtered);
+        }
+        if self.extent.is_some() {
+            info!("Dropped {} features outside of the extent", nr_outside);
+        }
+        if let Some(sample) = self.sample {
+            warn!(
+                "Sampled {} of the {} feature files with --sample {}, the tileset is only a preview",
+                fid,
+                fid + nr_not_sampled,
+                sample
+            );
+        }
+        if !self.invalid_features.is_empty() {
+            if !self.skip_invalid {
+                let paths: Vec<String> = self
+                    .invalid_features
+                    .iter()
+                    .map(|path| format!("{:?}", path))
+                    .collect();
+                return Err(format!(
+                    "Found {} invalid features, which have no vertices. Fix the features or use --skip-invalid to skip them:\n{}",
+                    paths.len(),
+                    paths.join("\n")
+                )
+                .into());
+            }
+            info!("Skipped {} invalid features", self.invalid_features.len());
+        }
+        if self.verify_metadata {
+            self.verify_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Check that the indexed features are within the `geographical_extent` of the
+    /// metadata, see [World::features_outside_metadata].
+    fn verify_metadata(&self) -> Result<(), String> {
+        let Some(geographical_extent) = self.geographical_extent else {
+            warn!(
+                "The metadata {:?} does not have a geographicalExtent, so the features cannot be verified against it",
+                &self.path_metadata
+            );
+            return Ok(());
+        };
+        let outside = self.features_outside_metadata(&geographical_extent);
+        if outside.is_empty() {
+            info!("The features are within the geographicalExtent of the metadata");
+            return Ok(());
+        }
+        let examples: Vec<String> = outside
+            .iter()
+            .take(5)
+            .map(|path| format!("{:?}", path))
+            .collect();
+        Err(format!(
+            "{} features are far outside of the geographicalExtent {:?} of the metadata {:?}, they are probably in a different CRS than {:?}, for example:\n{}",
+            outside.len(),
+            geographical_extent,
+            &self.path_metadata,
+            &self.crs.0,
+            examples.join("\n")
+        ))
+    }
+
+    /// The paths of the indexed features whose 2D bbox does not intersect the
+    /// `geographical_extent`, after it is expanded by its size on each side. Thus only the
+    /// features that are far outside of the extent are returned, and not the ones that
+    /// are slightly outside of an inaccurate extent.
+    pub fn features_outside_metadata(&self, geographical_extent: &Bbox) -> Vec<&PathBuf> {
+        let [minx, miny, _, maxx, maxy, _] = *geographical_extent;
+        let margin = (maxx - minx).max(maxy - miny);
+        self.features
+            .iter()
+            .filter(|feature| !feature.path_jsonl.as_os_str().is_empty())
+            .filter(|feature| {
+                let bbox = feature.bbox_qc.to_bbox(&self.transform, None, None);
+                bbox[3] < minx - margin
+                    || bbox[0] > maxx + margin
+                    || bbox[4] < miny - margin
+                    || bbox[1] > maxy + margin
+            })
+            .map(|feature| &feature.path_jsonl)
+            .collect()
+    }
+
+    /// A pseudo-random key of the feature for the [Sample], which only depends on the
+    /// `sample_seed` and the path of the feature relative to the `path_features_root`, so
+    /// that the same features are sampled in every run.
+    fn sample_key(&self, feature_path: &Path) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.sample_seed.hash(&mut hasher);
+        feature_path
+            .strip_prefix(&self.path_features_root)
+            .unwrap_or(feature_path)
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The largest [World::sample_key] of the features that are sampled. For a
+    /// [Sample::Count], the keys of all the feature files are computed to find the
+    /// count-th smallest key.
+    fn sample_threshold(&self, sample: Sample) -> u64 {
+        match sample {
+            Sample::Fraction(fraction) => (fraction * u64::MAX as f64) as u64,
+            Sample::Count(0) => 0,
+            Sample::Count(count) => {
+                let mut keys: Vec<u64> =
+                    Self::feature_paths(&self.path_features_root, &self.features_extensions)
+                        .map(|feature_path| self.sample_key(&feature_path))
+                        .collect();
+                if count >= keys.len() {
+                    return u64::MAX;
+                }
+                *keys.select_nth_unstable(count - 1).1
+            }
+        }
+    }
+
+    /// Parse a feature and count its vertices in the grid cells, without modifying the
+    /// grid, so that the features can be indexed in parallel.
+    fn index_feature(&self, feature_path: &Path) -> IndexedFeature {
+        let Ok(featurevertices) = CityJSONFeatureVertices::from_file(feature_path) else {
+            return IndexedFeature::Unparsable;
+        };
+        if !featurevertices.matches_filters(&self.feature_filters, self.cityobject_types.as_ref()) {
+            return IndexedFeature::Filtered;
+        }
+        if !featurevertices.is_valid() {
+            return IndexedFeature::Invalid;
+        }
+        let hash = if self.dedup_features {
+            featurevertices.feature_hash()
+        } else {
+            0
+        };
+        if let Some([minx, miny, maxx, maxy]) = self.extent {
+            let is_outside = featurevertices
+                .bbox_of_types(self.cityobject_types.as_ref())
+                .map(|bbox_qc| bbox_qc.to_bbox(&self.transform, None, None))
+                .is_some_and(|bbox| {
+                    bbox[3] < minx || bbox[0] > maxx || bbox[4] < miny || bbox[1] > maxy
+                });
+            if is_outside {
+                return IndexedFeature::Valid { hash, cells: None };
+            }
+        }
+        // We make a (cellid, vertex count) map and assign the feature to the cell that
+        // contains the most of the feature's vertices.
+        // But maybe a HashMap is not the most performant solution here? A Vec of tuples?
+        let mut cell_vtx_cnt: HashMap<crate::spatial_structs::CellId, usize> = HashMap::new();
+        for (_, co) in featurevertices.cityobjects.iter() {
+            // If the object_type argument was not passed, that means that we need all
+            // CityObject types. If it was passed, then we filter with its values.
+            // Doing this condition-tree would be much simpler if Option.is_some_and()
+            // was stable feature already.
+            let mut do_compute = self.cityobject_types.is_none();
+            if let Some(ref cotypes) = self.cityobject_types {
+                do_compute = cotypes.contains(&co.cotype);
+            }
+            if do_compute {
+                // Just counting vertices here
+                for vtx_qc in featurevertices.vertices.iter() {
+                    let vtx_rw = [
+                        (vtx_qc[0] as f64 * self.transform.scale[0]) + self.transform.translate[0],
+                        (vtx_qc[1] as f64 * self.transform.scale[1]) + self.transform.translate[1],
+                    ];
+                    // With --extent, a feature can be partly outside of the grid
+                    if !self.grid.contains_point(&vtx_rw) {
+                        continue;
                     }
+                    let cellid = self.grid.locate_point(&vtx_rw);
+                    *cell_vtx_cnt.entry(cellid).or_insert(1) += 1;
                 }
+            }
+        }
+        // After counting the object vertices in the cells, we need to
+        // assign the object to the cells that intersect with its bbox,
+        // because of https://github.com/3DGI/tyler/issues/28
+        if let Some(bbox_qc) = featurevertices.bbox_of_types(self.cityobject_types.as_ref()) {
+            let bbox = bbox_qc.to_bbox(&self.transform, None, None);
+            let intersecting_cellids = self.grid.intersect_bbox(&bbox);
+            for cellid in intersecting_cellids {
+                // Just add a new entry with the intersecting cell to the map, but no not
+                // increase the vertex count, because the vertices have been counted
+                // already, these might be cells where the object does not actually have a
+                // vertex.
+                // REVIEW: actually, let's just increase the vertex count
+                *cell_vtx_cnt.entry(cellid).or_insert(1) += 1;
+            }
+        }
+        let feature = if cell_vtx_cnt.is_empty() {
+            Feature::default()
+        } else {
+            Feature {
+                has_building: featurevertices.has_building(self.cityobject_types.as_ref()),
+                ..featurevertices.to_feature(feature_path)
+            }
+        };
+        IndexedFeature::Valid {
+            hash,
+            cells: Some(FeatureCells {
+                feature,
+                cell_vtx_cnt,
+            }),
+        }
+    }
 
-                if !cell_vtx_cnt.is_empty() {
-                    // We found at least one CityObject of the required type
-                    self.features[fid] = featurevertices.to_feature(&feature_path);
-                    // TODO: what other cityobject types need to have 1-1 cell assignment?
-                    if let Some(ref cotypes) = self.cityobject_types {
-                        if cotypes.contains(&CityObjectType::Building)
-                            || cotypes.contains(&CityObjectType::BuildingPart)
-                        {
-                            // In this case we have a 1-1 feature-to-cell assignment, we only retain the vertex
-                            // count in the cell that gets the feature.
-                            // The cell that receives the feature is the one with the highest vertex count
-                            // of the feature.
-                            // However, with this method it is not possible to combine cityobject types that
-                            // require different cell-assignment methods into the same tileset.
-                            // E.g. terrain features need to be duplicated across cells, buildings need to
-                            // unique. The tileset for them must be generated separately.
-                            let (cellid, nr_vertices) = cell_vtx_cnt
-                                .iter()
-                                .max_by(|a, b| a.1.cmp(b.1))
-                                .map(|(k, v)| (k, v))
-                                .unwrap();
-                            let cell = self.grid.cell_mut(cellid);
-                            cell.nr_vertices += nr_vertices;
-                            if !cell.feature_ids.contains(&fid) {
-                                cell.feature_ids.push(fid)
-                            }
-                        } else {
-                            for (cellid, nr_vertices) in cell_vtx_cnt.iter() {
-                                let cell = self.grid.cell_mut(cellid);
-                                cell.nr_vertices += nr_vertices;
-                                if !cell.feature_ids.contains(&fid) {
-                                    cell.feature_ids.push(fid)
-                                }
+    /// The absolute paths of the features `feature_ids`, one per line, as they are passed
+    /// to the tile conversion. The paths are deduplicated and sorted, so that the input of a tile does
+    /// not depend on the order of the features in the cells.
+    pub fn feature_input(&self, feature_ids: &[usize]) -> String {
+        let mut paths: Vec<String> = feature_ids
+            .iter()
+            .map(|fid| {
+                let path = &self.features[*fid].path_jsonl;
+                // Absolute, so that the list does not depend on the location of the
+                // .input file or the working directory of the subprocess
+                let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+                path.into_os_string()
+                    .into_string()
+                    .unwrap_or_else(|path| panic!("the path {:?} is not valid unicode", path))
+            })
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths.iter().map(|path| format!("{}\n", path)).collect()
+    }
+
+    /// Does any of the features `feature_ids` have a Building or BuildingPart?
+    pub fn has_building(&self, feature_ids: &[usize]) -> bool {
+        feature_ids
+            .iter()
+            .any(|fid| self.features[*fid].has_building)
+    }
+
+    /// Merge the features into a single CityJSON object with the metadata, like
+    /// `convert_cityjsonfeatures.py` does for the CityJSON output. The vertex indices of
+    /// the geometries are shifted by the number of vertices of the preceding features. The
+    /// appearances of the features are not merged.
+    pub fn merge_features(
+        &self,
+        feature_ids: &[usize],
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let mut cm: serde_json::Value = from_str(&read_to_string(&self.path_metadata)?)?;
+        let mut cityobjects = serde_json::Map::new();
+        let mut vertices: Vec<serde_json::Value> = Vec::new();
+        let mut paths: Vec<&PathBuf> = feature_ids
+            .iter()
+            .map(|fid| &self.features[*fid].path_jsonl)
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        for path in paths {
+            let mut feature: serde_json::Value = from_str(&read_feature(path)?)?;
+            let offset = vertices.len() as u64;
+            if let Some(serde_json::Value::Object(feature_cityobjects)) =
+                feature.get_mut("CityObjects").map(serde_json::Value::take)
+            {
+                for (id, mut co) in feature_cityobjects {
+                    if let Some(geometries) = co.get_mut("geometry").and_then(|g| g.as_array_mut())
+                    {
+                        for geometry in geometries {
+                            if let Some(boundaries) = geometry.get_mut("boundaries") {
+                                offset_indices(boundaries, offset);
                             }
                         }
-                        fid += 1;
                     }
+                    cityobjects.insert(id, co);
                 }
-            } else {
-                error!("Failed to parse the feature {:?}", &feature_path);
+            }
+            if let Some(serde_json::Value::Array(feature_vertices)) =
+                feature.get_mut("vertices").map(serde_json::Value::take)
+            {
+                vertices.extend(feature_vertices);
             }
         }
+        cm["CityObjects"] = serde_json::Value::Object(cityobjects);
+        cm["vertices"] = serde_json::Value::Array(vertices);
+        Ok(cm)
+    }
+
+    /// Decompress the gzipped features into `dir`, and point their `path_jsonl` to the
+    /// decompressed files, because the converter cannot read gzipped features. Returns the
+    /// number of decompressed features.
+    pub fn decompress_features(&mut self, dir: &Path) -> std::io::Result<usize> {
+        let features_gz: Vec<(usize, &mut Feature)> = self
+            .features
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, feature)| is_gzipped_jsonl(&feature.path_jsonl))
+            .collect();
+        if features_gz.is_empty() {
+            return Ok(0);
+        }
+        std::fs::create_dir_all(dir)?;
+        let nr_features = features_gz.len();
+        features_gz
+            .into_par_iter()
+            .try_for_each(|(fid, feature)| -> std::io::Result<()> {
+                let path_decompressed = dir.join(format!("{}.city.jsonl", fid));
+                std::fs::write(&path_decompressed, read_feature(&feature.path_jsonl)?)?;
+                feature.path_jsonl = path_decompressed;
+                Ok(())
+            })?;
+        Ok(nr_features)
+    }
+
+    /// Merge the duplicate vertices of the features, and write the features that have
+    /// duplicate vertices into `dir` and point their `path_jsonl` to them. The vertices
+    /// that are within the `threshold` distance (in the units of the CRS) of each other
+    /// are merged, see [weld_vertices]. With a `threshold` of 0, only the vertices with the
+    /// same coordinates are merged. Returns the number of merged vertices.
+    pub fn weld_features(&mut self, dir: &Path, threshold: f64) -> std::io::Result<usize> {
+        std::fs::create_dir_all(dir)?;
+        let scale = self.transform.scale;
+        self.features
+            .par_iter_mut()
+            .enumerate()
+            .filter(|(_, feature)| !feature.path_jsonl.as_os_str().is_empty())
+            .map(|(fid, feature)| -> std::io::Result<usize> {
+                let mut feature_json: serde_json::Value =
+                    from_str(&read_feature(&feature.path_jsonl)?)?;
+                let nr_merged = weld_vertices(&mut feature_json, scale, threshold);
+                // The features without duplicate vertices are converted from their
+                // original file
+                if nr_merged > 0 {
+                    let path_welded = dir.join(format!("{}.city.jsonl", fid));
+                    std::fs::write(&path_welded, feature_json.to_string())?;
+                    feature.path_jsonl = path_welded;
+                }
+                Ok(nr_merged)
+            })
+            .sum()
     }
 
     // Export the grid of the World into the working directory.
@@ -319,6 +982,79 @@ impl World {
     }
 }
 
+/// The number of features that are parsed in parallel by [World::index_with_grid], before
+/// they are added to the grid.
+const INDEX_CHUNK_SIZE: usize = 10000;
+
+/// The result of [World::index_feature].
+enum IndexedFeature {
+    Unparsable,
+    /// Excluded by the feature filters.
+    Filtered,
+    /// Has no vertices.
+    Invalid,
+    /// The `hash` is only computed if the duplicates are dropped. The `cells` are `None`
+    /// if the feature is outside of the extent.
+    Valid {
+        hash: u64,
+        cells: Option<FeatureCells>,
+    },
+}
+
+/// A feature and the number of its vertices in the grid cells.
+struct FeatureCells {
+    feature: Feature,
+    cell_vtx_cnt: HashMap<crate::spatial_structs::CellId, usize>,
+}
+
+/// The number of features that are written into one subdirectory by [spool_cityjsonseq].
+const SPOOL_FEATURES_PER_DIR: usize = 10000;
+
+/// Write the features of a [CityJSONSeq](https://www.cityjson.org/cityjsonseq/) stream
+/// into separate `.city.jsonl` files in `dir`, so that the features can be indexed by the
+/// [World] and read by the tile conversion.
+///
+/// If the first line of the stream is a CityJSON object, it is written to
+/// `dir/metadata.city.json` and its path is returned.
+/// The features are written into subdirectories of [SPOOL_FEATURES_PER_DIR] features, to
+/// avoid very large directories.
+pub fn spool_cityjsonseq<R: BufRead, P: AsRef<Path>>(
+    reader: R,
+    dir: P,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    #[derive(Deserialize)]
+    struct CityJSONType {
+        #[serde(rename = "type")]
+        cjtype: String,
+    }
+
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let mut path_metadata: Option<PathBuf> = None;
+    let mut nr_features: usize = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if i == 0 && from_str::<CityJSONType>(&line)?.cjtype == "CityJSON" {
+            let path = dir.join("metadata.city.json");
+            File::create(&path)?.write_all(line.as_bytes())?;
+            path_metadata = Some(path);
+            continue;
+        }
+        let subdir = dir.join((nr_features / SPOOL_FEATURES_PER_DIR).to_string());
+        if !subdir.is_dir() {
+            std::fs::create_dir_all(&subdir)?;
+        }
+        let mut feature_file = File::create(subdir.join(format!("{}.city.jsonl", nr_features)))?;
+        writeln!(feature_file, "{}", line)?;
+        nr_features += 1;
+    }
+    info!("Read {} features from the CityJSONSeq stream", nr_features);
+    Ok(path_metadata)
+}
+
 /// A partial [CityJSON object](https://www.cityjson.org/specs/1.1.3/#cityjson-object).
 /// It is partial, because we only store the metadata that is necessary for parsing the
 /// CityJSONFeatures.
@@ -328,7 +1064,7 @@ pub struct CityJSONMetadata {
     pub metadata: Metadata,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Transform {
     pub scale: [f64; 3],
     pub translate: [f64; 3],
@@ -337,12 +1073,51 @@ pub struct Transform {
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
-    pub reference_system: Crs,
+    #[serde(default)]
+    pub reference_system: Option<Crs>,
+    /// The `[minx, miny, minz, maxx, maxy, maxz]` of the dataset.
+    pub geographical_extent: Option<Bbox>,
+    pub title: Option<String>,
+    pub point_of_contact: Option<PointOfContact>,
+}
+
+impl Metadata {
+    /// The attribution of the source data as HTML, which is made of the `title` and the
+    /// `organization` (or `contactName`) of the `pointOfContact`, eg. "3DBAG, 3DGI".
+    pub fn attribution(&self) -> Option<String> {
+        let provider = self
+            .point_of_contact
+            .as_ref()
+            .and_then(|poc| poc.organization.as_ref().or(poc.contact_name.as_ref()));
+        let parts: Vec<String> = [self.title.as_ref(), provider]
+            .into_iter()
+            .flatten()
+            .map(|text| escape_html(text))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+/// The [pointOfContact](https://www.cityjson.org/specs/1.1.3/#pointofcontact) of the
+/// metadata.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PointOfContact {
+    pub contact_name: Option<String>,
+    pub organization: Option<String>,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
 }
 
 /// Coordinate Reference System as defined by the
 /// [referenceSystem](https://www.cityjson.org/specs/1.1.3/#referencesystem-crs) CityJSON object.
-#[derive(Deserialize, Debug)]
+/// Besides the OGC URL, the OGC URN (`urn:ogc:def:crs:EPSG::7415`) and the `EPSG:7415`
+/// notation are also accepted.
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Crs(String);
 
 impl Crs {
@@ -350,31 +1125,49 @@ impl Crs {
     ///
     /// ## Examples
     /// ```
-    /// let crs = CRS("https://www.opengis.net/def/crs/EPSG/0/7415");
+    /// let crs: tyler::parser::Crs = "https://www.opengis.net/def/crs/EPSG/0/7415"
+    ///     .parse()
+    ///     .unwrap();
     /// let epsg_code = crs.to_epsg().unwrap();
     /// assert_eq!(7415_u16, epsg_code);
     /// ```
     pub fn to_epsg(&self) -> Result<u16, Box<dyn std::error::Error>> {
-        let parts: Vec<&str> = self.0.split('/').collect();
-        if let Some(authority) = parts.get(parts.len() - 3) {
-            if *authority != "EPSG" {
-                return Err(Box::try_from(format!(
-                    "the CRS definition should be EPSG: {}",
-                    self.0
-                ))
-                .unwrap());
-            }
-        }
-        return if let Some(c) = parts.last() {
-            let code: u16 = c.parse::<u16>().unwrap();
-            Ok(code)
+        // The authority and the code are the third-to-last and the last part of the URL,
+        // and of the URN, eg. 'urn:ogc:def:crs:EPSG::7415'
+        let separator = if self.0.starts_with("urn:") || !self.0.contains('/') {
+            ':'
         } else {
-            Err(Box::try_from(format!(
+            '/'
+        };
+        let parts: Vec<&str> = self.0.split(separator).collect();
+        let authority = match parts.len() {
+            2 => parts.first(),
+            _ => parts.len().checked_sub(3).and_then(|i| parts.get(i)),
+        };
+        if let Some(authority) = authority {
+            if !authority.eq_ignore_ascii_case("EPSG") {
+                return Err(format!("the CRS definition should be EPSG: {}", self.0).into());
+            }
+        }
+        match parts.last().and_then(|c| c.parse::<u16>().ok()) {
+            Some(code) => Ok(code),
+            None => Err(format!(
                 "the CRS definition should contain the EPSG code as its last element: {}",
                 self.0
-            ))
-            .unwrap())
-        };
+            )
+            .into()),
+        }
+    }
+}
+
+impl FromStr for Crs {
+    type Err = String;
+
+    /// Parse a CRS definition that has an EPSG code.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let crs = Crs(s.to_string());
+        crs.to_epsg().map_err(|e| e.to_string())?;
+        Ok(crs)
     }
 }
 
@@ -402,13 +1195,90 @@ impl CityJSONMetadata {
     }
 }
 
+/// Add the `offset` to the vertex indices in the nested arrays of the `boundaries`.
+fn offset_indices(boundaries: &mut serde_json::Value, offset: u64) {
+    match boundaries {
+        serde_json::Value::Array(values) => {
+            for value in values {
+                offset_indices(value, offset);
+            }
+        }
+        serde_json::Value::Number(i) => {
+            if let Some(i) = i.as_u64() {
+                *boundaries = serde_json::Value::from(i + offset);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The file extension of the feature files, unless it is set with `--features-extension`.
+pub const DEFAULT_FEATURES_EXTENSION: &str = "jsonl";
+
+/// Is the file a gzipped CityJSONFeature file (eg. `.city.jsonl.gz`)?
+pub fn is_gzipped_jsonl(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Read a CityJSONFeature file, which is decompressed if it is gzipped.
+pub fn read_feature(path: &Path) -> std::io::Result<String> {
+    if !is_gzipped_jsonl(path) {
+        return read_to_string(path);
+    }
+    let mut feature_str = String::new();
+    GzDecoder::new(File::open(path)?).read_to_string(&mut feature_str)?;
+    Ok(feature_str)
+}
+
 impl CityJSONFeatureVertices {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
-        let cf_str = read_to_string(path.as_ref())?;
+        let cf_str = read_feature(path.as_ref())?;
         let cf: CityJSONFeatureVertices = from_str(&cf_str)?;
         Ok(cf)
     }
 
+    /// Does the feature have a Building or BuildingPart of the `cityobject_types`? These
+    /// features are assigned to a single cell.
+    fn has_building(&self, cityobject_types: Option<&Vec<CityObjectType>>) -> bool {
+        self.cityobjects.values().any(|co| {
+            matches!(
+                co.cotype,
+                CityObjectType::Building | CityObjectType::BuildingPart
+            ) && cityobject_types.is_none_or(|cotypes| cotypes.contains(&co.cotype))
+        })
+    }
+
+    /// Does any CityObject of the `cityobject_types` match all the `filters`? All
+    /// CityObject types are considered if `cityobject_types` is `None`.
+    fn matches_filters(
+        &self,
+        filters: &[FeatureFilter],
+        cityobject_types: Option<&Vec<CityObjectType>>,
+    ) -> bool {
+        filters.is_empty()
+            || self.cityobjects.values().any(|co| {
+                cityobject_types.is_none_or(|cotypes| cotypes.contains(&co.cotype))
+                    && filters
+                        .iter()
+                        .all(|filter| filter.matches(co.attributes.as_ref()))
+            })
+    }
+
+    /// Hash of the CityObject IDs and the geometry of the feature, for detecting exact
+    /// duplicate features.
+    /// The hash does not depend on the order of the CityObjects in the feature.
+    fn feature_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut ids: Vec<&String> = self.cityobjects.keys().collect();
+        ids.sort();
+        for id in ids {
+            id.hash(&mut hasher);
+            self.cityobjects[id].geometry.hash(&mut hasher);
+        }
+        self.vertices.hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Return the number of vertices of the feature.
     /// We assume that the number of vertices in a feature does not exceed 65535 (thus `u16`).
     fn vertex_count(&self) -> u16 {
@@ -438,6 +1308,11 @@ impl CityJSONFeatureVertices {
         ]
     }
 
+    /// The feature has vertices.
+    fn is_valid(&self) -> bool {
+        !self.vertices.is_empty()
+    }
+
     /// Feature centroid (2D) computed as the average coordinate.
     /// The centroid coordinates are real-world coordinates (thus they are transformed back to
     /// real-world coordinates from the quantized coordinates).
@@ -479,9 +1354,17 @@ impl CityJSONFeatureVertices {
     /// Compute the 3D bounding box of only the provided CityObject types in the feature.
     /// Returns quantized coordinates.
     pub fn bbox_of_types(&self, cityobject_types: Option<&Vec<CityObjectType>>) -> Option<BboxQc> {
-        let [mut x_min, mut y_min, mut z_min] = self.vertices[0];
-        let [mut x_max, mut y_max, mut z_max] = self.vertices[0];
-        let mut found_co_geometry = false;
+        // The bbox is initialized from the first vertex of the selected CityObjects, so that
+        // the vertices of the other CityObjects do not extend it.
+        let mut bbox_qc: Option<BboxQc> = None;
+        let mut add_vertex = |vtx: &usize| {
+            let [x, y, z] = self.vertices[*vtx];
+            let vertex_bbox = BboxQc([x, y, z, x, y, z]);
+            match bbox_qc {
+                Some(ref mut bbox_qc) => bbox_qc.update_with(&vertex_bbox),
+                None => bbox_qc = Some(vertex_bbox),
+            }
+        };
         for (_, co) in self.cityobjects.iter() {
             // If the object_type argument was not passed, that means that we need all
             // CityObject types. If it was passed, then we filter with its values.
@@ -495,66 +1378,25 @@ impl CityJSONFeatureVertices {
                 for geom in co.geometry.iter() {
                     match geom {
                         Geometry::MultiSurface { boundaries, .. } => {
-                            for srf in boundaries {
-                                for ring in srf {
-                                    for vtx in ring {
-                                        let [x, y, z] = &self.vertices[*vtx];
-                                        if *x < x_min {
-                                            x_min = *x
-                                        } else if *x > x_max {
-                                            x_max = *x
-                                        }
-                                        if *y < y_min {
-                                            y_min = *y
-                                        } else if *y > y_max {
-                                            y_max = *y
-                                        }
-                                        if *z < z_min {
-                                            z_min = *z
-                                        } else if *z > z_max {
-                                            z_max = *z
-                                        }
-                                    }
-                                }
-                            }
-                            found_co_geometry = true;
+                            boundaries
+                                .iter()
+                                .flatten()
+                                .flatten()
+                                .for_each(&mut add_vertex);
                         }
                         Geometry::Solid { boundaries, .. } => {
-                            for shell in boundaries {
-                                for srf in shell {
-                                    for ring in srf {
-                                        for vtx in ring {
-                                            let [x, y, z] = &self.vertices[*vtx];
-                                            if *x < x_min {
-                                                x_min = *x
-                                            } else if *x > x_max {
-                                                x_max = *x
-                                            }
-                                            if *y < y_min {
-                                                y_min = *y
-                                            } else if *y > y_max {
-                                                y_max = *y
-                                            }
-                                            if *z < z_min {
-                                                z_min = *z
-                                            } else if *z > z_max {
-                                                z_max = *z
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            found_co_geometry = true;
+                            boundaries
+                                .iter()
+                                .flatten()
+                                .flatten()
+                                .flatten()
+                                .for_each(&mut add_vertex);
                         }
                     }
                 }
             }
         }
-        if found_co_geometry {
-            Some(BboxQc([x_min, y_min, z_min, x_max, y_max, z_max]))
-        } else {
-            None
-        }
+        bbox_qc
     }
 
     /// Compute the 2D quantized centroid and the 3D bounding box in one loop.
@@ -596,6 +1438,9 @@ impl CityJSONFeatureVertices {
         Feature {
             centroid_qc: [ctr_bbox[0], ctr_bbox[1]],
             nr_vertices: self.vertex_count(),
+            nr_bytes: std::fs::metadata(path.as_ref())
+                .map(|m| m.len())
+                .unwrap_or(0),
             path_jsonl: path.as_ref().to_path_buf(),
             bbox_qc: BboxQc([
                 ctr_bbox[2],
@@ -605,17 +1450,24 @@ impl CityJSONFeatureVertices {
                 ctr_bbox[6],
                 ctr_bbox[7],
             ]),
+            has_building: false,
         }
     }
 }
 
 /// Stores the information that is computed from a CityJSONFeature.
-#[derive(Debug, Default, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Feature {
     pub(crate) centroid_qc: [i64; 2],
     pub(crate) nr_vertices: u16,
+    /// The size of the feature file.
+    pub(crate) nr_bytes: u64,
     pub path_jsonl: PathBuf,
     pub bbox_qc: BboxQc,
+    /// Does the feature have a Building or BuildingPart of the `cityobject_types` of the
+    /// World?
+    #[serde(default)]
+    pub(crate) has_building: bool,
 }
 
 impl Feature {
@@ -628,6 +1480,8 @@ impl Feature {
     }
 }
 
+/// The [CityObject types](https://www.cityjson.org/specs/1.1.3/#the-different-city-objects)
+/// of CityJSON 1.1, both the first-level and the second-level types.
 #[derive(Debug, Deserialize, clap::ValueEnum, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 #[clap(rename_all = "PascalCase")]
 pub enum CityObjectType {
@@ -646,26 +1500,146 @@ pub enum CityObjectType {
     BuildingRoom,
     BuildingUnit,
     CityFurniture,
+    CityObjectGroup,
     LandUse,
     OtherConstruction,
     PlantCover,
     SolitaryVegetationObject,
+    // PascalCase would be 'TinRelief'
+    #[value(name = "TINRelief")]
     TINRelief,
     WaterBody,
     Road,
     Railway,
     Waterway,
     TransportSquare,
-    #[serde(rename = "+GenericCityObject")]
+    Tunnel,
+    TunnelPart,
+    TunnelInstallation,
+    TunnelConstructiveElement,
+    TunnelHollowSpace,
+    TunnelFurniture,
+    // GenericCityObject was an extension ('+GenericCityObject') before CityJSON 1.1
+    #[serde(alias = "+GenericCityObject")]
     GenericCityObject,
 }
 
+impl FromStr for CityObjectType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as clap::ValueEnum>::from_str(s, false)
+    }
+}
+
 impl fmt::Display for CityObjectType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+/// Merge the vertices of the CityJSONFeature that are within the `threshold` distance of
+/// each other, and update the vertex indices of the boundaries. The `scale` of the
+/// transform converts the integer coordinates of the vertices to the units of the
+/// `threshold`. Returns the number of merged vertices.
+///
+/// The vertices are visited in order, and a vertex is merged into the nearest vertex that
+/// was kept before it, if that is within the `threshold`. So the kept vertices are more
+/// than the `threshold` apart, and a chain of vertices that are each within the
+/// `threshold` of the next one is not collapsed into a single vertex.
+/// The kept vertices are looked up in a grid with cells of the `threshold` size. Because
+/// a vertex within the `threshold` can be on the other side of a cell boundary, the 27
+/// cells around the cell of the vertex are searched, and the distance decides.
+fn weld_vertices(feature: &mut serde_json::Value, scale: [f64; 3], threshold: f64) -> usize {
+    let Some(vertices) = feature.get("vertices").and_then(|v| v.as_array()) else {
+        return 0;
+    };
+    let coordinates = |vertex: &serde_json::Value| -> [i64; 3] {
+        [0, 1, 2].map(|i| vertex[i].as_i64().unwrap_or_default())
+    };
+    // In integer coordinates, at least 1 so that the cell of a vertex is well defined
+    let cellsize = [0, 1, 2].map(|i| (threshold / scale[i]).max(1.0));
+    let cell = |c: &[i64; 3]| -> [i64; 3] {
+        [0, 1, 2].map(|i| (c[i] as f64 / cellsize[i]).floor() as i64)
+    };
+    let distance_squared = |a: &[i64; 3], b: &[i64; 3]| -> f64 {
+        (0..3)
+            .map(|i| ((a[i] - b[i]) as f64 * scale[i]).powi(2))
+            .sum()
+    };
+    let threshold_squared = threshold * threshold;
+    // The indices in vertices_welded of the kept vertices in each cell
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::with_capacity(vertices.len());
+    let mut coordinates_welded: Vec<[i64; 3]> = Vec::with_capacity(vertices.len());
+    let mut vertices_welded: Vec<serde_json::Value> = Vec::with_capacity(vertices.len());
+    let mut new_indices: Vec<usize> = Vec::with_capacity(vertices.len());
+    for vertex in vertices {
+        let c = coordinates(vertex);
+        let key = cell(&c);
+        let mut nearest: Option<(usize, f64)> = None;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbor = [key[0] + dx, key[1] + dy, key[2] + dz];
+                    for &index in cells.get(&neighbor).into_iter().flatten() {
+                        let d = distance_squared(&c, &coordinates_welded[index]);
+                        if d <= threshold_squared && nearest.is_none_or(|(_, dn)| d < dn) {
+                            nearest = Some((index, d));
+                        }
+                    }
+                }
+            }
+        }
+        let index = match nearest {
+            Some((index, _)) => index,
+            None => {
+                vertices_welded.push(vertex.clone());
+                coordinates_welded.push(c);
+                cells
+                    .entry(key)
+                    .or_default()
+                    .push(vertices_welded.len() - 1);
+                vertices_welded.len() - 1
+            }
+        };
+        new_indices.push(index);
+    }
+    let nr_merged = vertices.len() - vertices_welded.len();
+    if nr_merged == 0 {
+        return 0;
+    }
+    fn reindex(boundaries: &mut serde_json::Value, new_indices: &[usize]) {
+        match boundaries {
+            serde_json::Value::Array(children) => children
+                .iter_mut()
+                .for_each(|child| reindex(child, new_indices)),
+            serde_json::Value::Number(index) => {
+                if let Some(new_index) = index.as_u64().and_then(|i| new_indices.get(i as usize)) {
+                    *boundaries = serde_json::Value::from(*new_index);
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(cityobjects) = feature
+        .get_mut("CityObjects")
+        .and_then(|cos| cos.as_object_mut())
+    {
+        for cityobject in cityobjects.values_mut() {
+            let geometries = cityobject
+                .get_mut("geometry")
+                .and_then(|g| g.as_array_mut());
+            for geometry in geometries.into_iter().flatten() {
+                if let Some(boundaries) = geometry.get_mut("boundaries") {
+                    reindex(boundaries, &new_indices);
+                }
+            }
+        }
+    }
+    feature["vertices"] = serde_json::Value::Array(vertices_welded);
+    nr_merged
+}
+
 // Indexed geometry
 type Vertex = usize;
 type Ring = Vec<Vertex>;
@@ -674,7 +1648,7 @@ type Shell = Vec<Surface>;
 type MultiSurface = Vec<Surface>;
 type Solid = Vec<Shell>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Hash)]
 #[serde(tag = "type")]
 enum Geometry {
     MultiSurface { boundaries: MultiSurface },
@@ -686,6 +1660,125 @@ pub struct CityObject {
     #[serde(rename = "type")]
     pub cotype: CityObjectType,
     geometry: Vec<Geometry>,
+    #[serde(default)]
+    attributes: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// The comparison operator of a [FeatureFilter].
+/// A random subset of the features, parsed from a fraction in `(0, 1]` (eg. `0.1`), or a
+/// number of features (eg. `1000`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sample {
+    Fraction(f64),
+    Count(usize),
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(count) = s.parse::<usize>() {
+            if count > 0 {
+                return Ok(Self::Count(count));
+            }
+        }
+        match s.parse::<f64>() {
+            Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(Self::Fraction(fraction)),
+            _ => Err(format!(
+                "the sample {:?} must be a fraction in (0, 1] or a positive number of features",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fraction(fraction) => write!(f, "{}", fraction),
+            Self::Count(count) => write!(f, "{}", count),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FilterOperator {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+}
+
+/// A condition on a CityObject attribute, parsed from `<key><operator><value>`, eg.
+/// `construction_year>2000`. The operators are `=`, `!=`, `>` and `<`.
+///
+/// `>` and `<` compare numbers, `=` and `!=` compare numbers if both sides are numbers,
+/// otherwise they compare the text of the values. A CityObject without the attribute
+/// does not match the filter, with any operator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureFilter {
+    key: String,
+    operator: FilterOperator,
+    value: String,
+}
+
+impl FromStr for FeatureFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The first operator in the string, so that the value can contain operators. The
+        // '=' of a '!=' is found one position after the '!='.
+        let (i, operator, len) = [
+            ("!=", FilterOperator::Ne),
+            ("=", FilterOperator::Eq),
+            (">", FilterOperator::Gt),
+            ("<", FilterOperator::Lt),
+        ]
+        .into_iter()
+        .filter_map(|(op_str, op)| s.find(op_str).map(|i| (i, op, op_str.len())))
+        .min_by_key(|(i, _, _)| *i)
+        .ok_or_else(|| {
+            format!(
+                "the filter {:?} must have the form <key><operator><value>, with one of the operators =, !=, >, <",
+                s
+            )
+        })?;
+        let key = s[..i].trim();
+        if key.is_empty() {
+            return Err(format!(
+                "the filter {:?} does not have an attribute name",
+                s
+            ));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            operator,
+            value: s[i + len..].trim().to_string(),
+        })
+    }
+}
+
+impl FeatureFilter {
+    fn matches(&self, attributes: Option<&serde_json::Map<String, serde_json::Value>>) -> bool {
+        let value = match attributes.and_then(|a| a.get(&self.key)) {
+            Some(serde_json::Value::String(v)) => v.clone(),
+            Some(serde_json::Value::Null) | None => return false,
+            Some(v) => v.to_string(),
+        };
+        let numbers = value
+            .parse::<f64>()
+            .ok()
+            .zip(self.value.parse::<f64>().ok());
+        match (self.operator, numbers) {
+            (FilterOperator::Eq, Some((a, b))) => a == b,
+            (FilterOperator::Eq, None) => value == self.value,
+            (FilterOperator::Ne, Some((a, b))) => a != b,
+            (FilterOperator::Ne, None) => value != self.value,
+            (FilterOperator::Gt, Some((a, b))) => a > b,
+            (FilterOperator::Lt, Some((a, b))) => a < b,
+            (FilterOperator::Gt | FilterOperator::Lt, None) => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -699,11 +1792,569 @@ mod tests {
             .join("data")
     }
 
+    #[test]
+    fn test_feature_paths_list() {
+        let dir = std::env::temp_dir().join("tyler_test_feature_paths_list");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path_list = dir.join("features.txt");
+        std::fs::write(
+            &path_list,
+            "# municipality 1\na/1.city.jsonl\n\n/data/b/2.city.jsonl\n",
+        )
+        .unwrap();
+        assert!(World::is_feature_list(&path_list));
+        assert!(!World::is_feature_list(&dir));
+        assert!(!World::is_cityjsonseq(&path_list));
+        assert!(World::is_cityjsonseq(
+            test_data_dir().join("3dbag_feature_x71.city.jsonl")
+        ));
+        let paths: Vec<PathBuf> = World::feature_paths(&path_list, &[]).collect();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("a").join("1.city.jsonl"),
+                PathBuf::from("/data/b/2.city.jsonl")
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_feature_paths_extensions() {
+        let dir = std::env::temp_dir().join("tyler_test_feature_paths_extensions");
+        std::fs::create_dir_all(dir.join("sub.cjf")).unwrap();
+        for file_name in [
+            "1.city.jsonl",
+            "2.cjf",
+            "3.cjf.gz",
+            "4.city.json",
+            ".cjf",
+            "5.notcjf",
+        ] {
+            std::fs::write(dir.join(file_name), "").unwrap();
+        }
+        let feature_names = |extensions: &[&str]| -> Vec<String> {
+            let extensions: Vec<String> = extensions.iter().map(|ext| ext.to_string()).collect();
+            let mut names: Vec<String> = World::feature_paths(&dir, &extensions)
+                .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(feature_names(&["jsonl"]), ["1.city.jsonl"]);
+        assert_eq!(
+            feature_names(&["jsonl", ".cjf"]),
+            ["1.city.jsonl", "2.cjf", "3.cjf.gz"]
+        );
+        assert!(is_gzipped_jsonl(&dir.join("3.cjf.gz")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Write a metadata file and a feature that spans several cells of 10m into `dir`, and
+    /// return the World of the feature.
+    fn test_world(dir: &Path) -> World {
+        let dir_features = dir.join("features");
+        std::fs::create_dir_all(&dir_features).unwrap();
+        let path_metadata = dir.join("metadata.city.json");
+        std::fs::write(
+            &path_metadata,
+            r#"{"type": "CityJSON", "version": "1.1",
+                "transform": {"scale": [1.0, 1.0, 1.0], "translate": [0.0, 0.0, 0.0]},
+                "metadata": {"referenceSystem": "https://www.opengis.net/def/crs/EPSG/0/7415"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir_features.join("1.city.jsonl"),
+            r#"{"type": "CityJSONFeature", "id": "1", "CityObjects": {"1": {"type": "PlantCover",
+                "geometry": [{"type": "MultiSurface", "lod": "1", "boundaries": [[[0, 1, 2]]]}]}},
+                "vertices": [[0, 0, 0], [45, 0, 0], [45, 45, 0]]}"#,
+        )
+        .unwrap();
+        World::new(
+            &path_metadata,
+            &dir_features,
+            Some([10, 10]),
+            10,
+            None,
+            None,
+            None,
+            None,
+            false,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_feature_assignment() {
+        let dir = std::env::temp_dir().join("tyler_test_feature_assignment");
+        let nr_cells_with_feature = |feature_assignment: FeatureAssignment| -> usize {
+            let mut world = test_world(&dir);
+            world.feature_assignment = feature_assignment;
+            world.index_with_grid().unwrap();
+            world
+                .grid
+                .into_iter()
+                .filter(|(_, cell)| cell.feature_ids.contains(&0))
+                .count()
+        };
+        assert!(nr_cells_with_feature(FeatureAssignment::AllCells) > 1);
+        assert_eq!(nr_cells_with_feature(FeatureAssignment::Centroid), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_deterministic() {
+        let dir = std::env::temp_dir().join("tyler_test_index_deterministic");
+        test_world(&dir);
+        for i in 2..50 {
+            let (x, y) = ((i * 7) % 45, (i * 13) % 45);
+            std::fs::write(
+                dir.join("features").join(format!("{}.city.jsonl", i)),
+                format!(
+                    r#"{{"type": "CityJSONFeature", "id": "{i}", "CityObjects": {{"{i}": {{"type": "Building",
+                    "geometry": [{{"type": "Solid", "lod": "1", "boundaries": [[[[0, 1, 2]]]]}}]}}}},
+                    "vertices": [[{x}, {y}, 0], [{x}, {}, 0], [{}, {y}, 0]]}}"#,
+                    y + 5,
+                    x + 5
+                ),
+            )
+            .unwrap();
+        }
+        let cells = || -> Vec<(crate::spatial_structs::CellId, Vec<usize>)> {
+            let mut world = test_world(&dir);
+            world.index_with_grid().unwrap();
+            world
+                .grid
+                .into_iter()
+                .map(|(cellid, cell)| (cellid, cell.feature_ids.clone()))
+                .collect()
+        };
+        assert_eq!(cells(), cells());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gzipped_features() {
+        let dir = std::env::temp_dir().join("tyler_test_gzipped_features");
+        test_world(&dir);
+        let feature = std::fs::read_to_string(dir.join("features").join("1.city.jsonl")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(dir.join("features").join("2.city.jsonl.gz")).unwrap(),
+            flate2::Compression::fast(),
+        );
+        encoder
+            .write_all(feature.replace("\"1\"", "\"2\"").as_bytes())
+            .unwrap();
+        encoder.finish().unwrap();
+        let mut world = test_world(&dir);
+        world.index_with_grid().unwrap();
+        let paths: Vec<&PathBuf> = world
+            .features
+            .iter()
+            .map(|f| &f.path_jsonl)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect();
+        assert_eq!(paths.len(), 2);
+        let dir_decompressed = dir.join("decompressed");
+        assert_eq!(world.decompress_features(&dir_decompressed).unwrap(), 1);
+        for feature in world.features.iter() {
+            assert!(!is_gzipped_jsonl(&feature.path_jsonl));
+        }
+        let path_decompressed = world
+            .features
+            .iter()
+            .find(|f| f.path_jsonl.starts_with(&dir_decompressed))
+            .map(|f| f.path_jsonl.clone())
+            .unwrap();
+        assert!(CityJSONFeatureVertices::from_file(&path_decompressed).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_weld_features() {
+        let dir = std::env::temp_dir().join("tyler_test_weld_features");
+        test_world(&dir);
+        std::fs::write(
+            dir.join("features").join("2.city.jsonl"),
+            r#"{"type": "CityJSONFeature", "id": "2", "CityObjects": {"2": {"type": "PlantCover",
+                "geometry": [{"type": "MultiSurface", "lod": "1", "boundaries": [[[0, 1, 2]], [[3, 4, 5]]]}]}},
+                "vertices": [[0, 0, 0], [10, 0, 0], [10, 10, 0], [10, 10, 0], [10, 0, 0], [12, 1, 0]]}"#,
+        )
+        .unwrap();
+        let weld = |threshold: f64| -> (usize, Vec<serde_json::Value>) {
+            let mut world = test_world(&dir);
+            world.index_with_grid().unwrap();
+            let nr_merged = world.weld_features(&dir.join("welded"), threshold).unwrap();
+            let features = world
+                .features
+                .iter()
+                .filter(|f| !f.path_jsonl.as_os_str().is_empty())
+                .map(|f| from_str(&read_to_string(&f.path_jsonl).unwrap()).unwrap())
+                .collect();
+            (nr_merged, features)
+        };
+        // Only the coincident vertices
+        let (nr_merged, features) = weld(0.0);
+        assert_eq!(nr_merged, 2);
+        let feature = features.iter().find(|f| f["id"] == "2").unwrap();
+        assert_eq!(feature["vertices"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            feature["CityObjects"]["2"]["geometry"][0]["boundaries"],
+            serde_json::json!([[[0, 1, 2]], [[2, 1, 3]]])
+        );
+        // [12, 1, 0] is within 5m of [10, 0, 0]
+        let (nr_merged, features) = weld(5.0);
+        assert_eq!(nr_merged, 3);
+        let feature = features.iter().find(|f| f["id"] == "2").unwrap();
+        assert_eq!(
+            feature["CityObjects"]["2"]["geometry"][0]["boundaries"],
+            serde_json::json!([[[0, 1, 2]], [[2, 1, 1]]])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_weld_vertices_cell_boundary() {
+        let boundaries = |feature: &serde_json::Value| {
+            feature["CityObjects"]["1"]["geometry"][0]["boundaries"].clone()
+        };
+        // [4, 0, 0] and [6, 0, 0] are in different 5m cells, but within 5m, and [4, 0, 0]
+        // and [0, 4, 4] are in the same cell, but more than 5m apart
+        let mut feature = serde_json::json!({"type": "CityJSONFeature", "id": "1",
+            "CityObjects": {"1": {"type": "PlantCover", "geometry": [{"type": "MultiSurface",
+                "lod": "1", "boundaries": [[[0, 1, 2]]]}]}},
+            "vertices": [[4, 0, 0], [6, 0, 0], [0, 4, 4]]});
+        assert_eq!(weld_vertices(&mut feature, [1.0, 1.0, 1.0], 5.0), 1);
+        assert_eq!(boundaries(&feature), serde_json::json!([[[0, 0, 1]]]));
+        // A vertex is merged into the nearest kept vertex, [6, 0, 0] into [9, 0, 0]
+        let mut feature = serde_json::json!({"type": "CityJSONFeature", "id": "1",
+            "CityObjects": {"1": {"type": "PlantCover", "geometry": [{"type": "MultiSurface",
+                "lod": "1", "boundaries": [[[0, 1, 2]]]}]}},
+            "vertices": [[2, 0, 0], [9, 0, 0], [6, 0, 0]]});
+        assert_eq!(weld_vertices(&mut feature, [1.0, 1.0, 1.0], 5.0), 1);
+        assert_eq!(boundaries(&feature), serde_json::json!([[[0, 1, 1]]]));
+        // The threshold is in the units of the CRS, with a scale of 1mm 5000 and 5004 are
+        // within 5mm
+        let mut feature = serde_json::json!({"type": "CityJSONFeature", "id": "1",
+            "CityObjects": {"1": {"type": "PlantCover", "geometry": [{"type": "MultiSurface",
+                "lod": "1", "boundaries": [[[0, 1, 2]]]}]}},
+            "vertices": [[5000, 0, 0], [5004, 0, 0], [5006, 0, 0]]});
+        assert_eq!(weld_vertices(&mut feature, [0.001, 0.001, 0.001], 0.005), 1);
+        assert_eq!(boundaries(&feature), serde_json::json!([[[0, 0, 1]]]));
+    }
+
+    #[test]
+    fn test_verify_metadata() {
+        let dir = std::env::temp_dir().join("tyler_test_verify_metadata");
+        test_world(&dir);
+        let feature = std::fs::read_to_string(dir.join("features").join("1.city.jsonl")).unwrap();
+        // The same feature, but far from the first one, as if it was in another CRS
+        std::fs::write(
+            dir.join("features").join("2.city.jsonl"),
+            feature.replace("\"1\"", "\"2\"").replace(
+                "[[0, 0, 0], [45, 0, 0], [45, 45, 0]]",
+                "[[900, 900, 0], [945, 900, 0], [945, 945, 0]]",
+            ),
+        )
+        .unwrap();
+        // Without a geographicalExtent, the features are not verified
+        let mut world = test_world(&dir);
+        world.verify_metadata = true;
+        assert!(world.index_with_grid().is_ok());
+        let mut world = test_world(&dir);
+        world.verify_metadata = true;
+        world.geographical_extent = Some([0.0, 0.0, 0.0, 50.0, 50.0, 10.0]);
+        assert!(world.index_with_grid().is_err());
+        assert_eq!(
+            world.features_outside_metadata(&world.geographical_extent.unwrap()),
+            vec![&dir.join("features").join("2.city.jsonl")]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let dir = std::env::temp_dir().join("tyler_test_index_roundtrip");
+        let mut world = test_world(&dir);
+        world.index_with_grid().unwrap();
+        let path_index = dir.join("tyler_index.json");
+        world.write_index(&path_index).unwrap();
+        let world_index = World::from_index(
+            &path_index,
+            &world.path_metadata,
+            &world.path_features_root,
+            None,
+        )
+        .unwrap();
+        assert_eq!(world_index.features, world.features);
+        assert_eq!(world_index.grid.bbox, world.grid.bbox);
+        assert_eq!(world_index.grid.data, world.grid.data);
+        assert_eq!(world_index.crs.to_epsg().unwrap(), 7415);
+        assert_eq!(world_index.z_bounds(), world.z_bounds());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_features() {
+        let dir = std::env::temp_dir().join("tyler_test_merge_features");
+        test_world(&dir);
+        let feature = std::fs::read_to_string(dir.join("features").join("1.city.jsonl")).unwrap();
+        std::fs::write(
+            dir.join("features").join("2.city.jsonl"),
+            feature.replace("\"1\"", "\"2\""),
+        )
+        .unwrap();
+        let mut world = test_world(&dir);
+        world.index_with_grid().unwrap();
+        let feature_ids: Vec<usize> = (&world.grid)
+            .into_iter()
+            .flat_map(|(_, cell)| cell.feature_ids.iter().copied())
+            .collect();
+        let cm = world.merge_features(&feature_ids).unwrap();
+        assert_eq!(cm["type"], "CityJSON");
+        assert_eq!(cm["transform"]["scale"][0], 1.0);
+        assert_eq!(cm["vertices"].as_array().unwrap().len(), 6);
+        assert_eq!(
+            cm["CityObjects"]["1"]["geometry"][0]["boundaries"],
+            serde_json::json!([[[0, 1, 2]]])
+        );
+        assert_eq!(
+            cm["CityObjects"]["2"]["geometry"][0]["boundaries"],
+            serde_json::json!([[[3, 4, 5]]])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_has_building() {
+        let dir = std::env::temp_dir().join("tyler_test_has_building");
+        test_world(&dir);
+        let feature = std::fs::read_to_string(dir.join("features").join("1.city.jsonl")).unwrap();
+        std::fs::write(
+            dir.join("features").join("2.city.jsonl"),
+            feature
+                .replace("\"1\"", "\"2\"")
+                .replace("PlantCover", "Building"),
+        )
+        .unwrap();
+        let mut world = test_world(&dir);
+        world.index_with_grid().unwrap();
+        let fid_building = world
+            .features
+            .iter()
+            .position(|feature| feature.path_jsonl.ends_with("2.city.jsonl"))
+            .unwrap();
+        let fid_plantcover = world
+            .features
+            .iter()
+            .position(|feature| feature.path_jsonl.ends_with("1.city.jsonl"))
+            .unwrap();
+        assert!(world.has_building(&[fid_building]));
+        assert!(!world.has_building(&[fid_plantcover]));
+        assert!(world.has_building(&[fid_plantcover, fid_building]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sample() {
+        let dir = std::env::temp_dir().join("tyler_test_sample");
+        test_world(&dir);
+        let feature = std::fs::read_to_string(dir.join("features").join("1.city.jsonl")).unwrap();
+        for i in 2..=10 {
+            std::fs::write(
+                dir.join("features").join(format!("{}.city.jsonl", i)),
+                feature.replace("\"1\"", &format!("\"{}\"", i)),
+            )
+            .unwrap();
+        }
+        let sampled = |sample: &str, seed: u64| -> Vec<PathBuf> {
+            let mut world = test_world(&dir);
+            world.sample = Some(Sample::from_str(sample).unwrap());
+            world.sample_seed = seed;
+            world.index_with_grid().unwrap();
+            let mut paths: Vec<PathBuf> = world
+                .features
+                .iter()
+                .map(|f| f.path_jsonl.clone())
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(sampled("3", 0).len(), 3);
+        assert_eq!(sampled("3", 0), sampled("3", 0));
+        assert_ne!(sampled("3", 0), sampled("3", 1));
+        assert_eq!(sampled("20", 0).len(), 10);
+        assert_eq!(sampled("1.0", 0).len(), 10);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Sample::from_str("0.5"), Ok(Sample::Fraction(0.5)));
+        assert_eq!(Sample::from_str("1"), Ok(Sample::Count(1)));
+        assert!(Sample::from_str("0").is_err());
+        assert!(Sample::from_str("1.5").is_err());
+    }
+
+    #[test]
+    fn test_max_features() {
+        let dir = std::env::temp_dir().join("tyler_test_max_features");
+        test_world(&dir);
+        let world = |max_features: usize| {
+            World::new(
+                &dir.join("metadata.city.json"),
+                &dir.join("features"),
+                Some([10, 10]),
+                10,
+                None,
+                None,
+                None,
+                None,
+                false,
+                Some(max_features),
+                None,
+                None,
+            )
+        };
+        assert!(world(1).is_ok());
+        let e = world(0).err().unwrap().to_string();
+        assert!(e.starts_with("found 1 feature files"), "{}", e);
+        assert!(e.ends_with("--max-features 0"), "{}", e);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_feature_input() {
+        let dir = std::env::temp_dir().join("tyler_test_feature_input");
+        test_world(&dir);
+        std::fs::write(
+            dir.join("features").join("0.city.jsonl"),
+            r#"{"type": "CityJSONFeature", "id": "0", "CityObjects": {"0": {"type": "PlantCover",
+                "geometry": [{"type": "MultiSurface", "lod": "1", "boundaries": [[[0, 1, 2]]]}]}},
+                "vertices": [[0, 0, 0], [30, 0, 0], [30, 30, 0]]}"#,
+        )
+        .unwrap();
+        let mut world = test_world(&dir);
+        world.index_with_grid().unwrap();
+        // The features span several cells, so the cells list them several times
+        let feature_ids: Vec<usize> = world
+            .grid
+            .into_iter()
+            .flat_map(|(_, cell)| cell.feature_ids.iter().copied())
+            .collect();
+        let mut feature_ids_reversed = feature_ids.clone();
+        feature_ids_reversed.reverse();
+        let feature_input = world.feature_input(&feature_ids);
+        assert_eq!(feature_input, world.feature_input(&feature_ids_reversed));
+        // Sorting does not change the features of the tile, only their order
+        let paths: Vec<&str> = feature_input.lines().collect();
+        let mut paths_expected: Vec<String> = world.features[..2]
+            .iter()
+            .map(|f| f.path_jsonl.to_str().unwrap().to_string())
+            .collect();
+        paths_expected.sort();
+        assert_eq!(paths, paths_expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_features() {
+        let dir = std::env::temp_dir().join("tyler_test_invalid_features");
+        let mut world = test_world(&dir);
+        let path_empty = dir.join("features").join("2.city.jsonl");
+        std::fs::write(
+            &path_empty,
+            r#"{"type": "CityJSONFeature", "id": "2", "CityObjects": {"2": {"type": "PlantCover",
+                "geometry": []}}, "vertices": []}"#,
+        )
+        .unwrap();
+        let err = world.index_with_grid().unwrap_err();
+        assert!(err.to_string().contains("2.city.jsonl"));
+        let mut world = test_world(&dir);
+        world.skip_invalid = true;
+        world.index_with_grid().unwrap();
+        assert_eq!(world.invalid_features, vec![path_empty]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_feature_filter() -> serde_json::Result<()> {
+        let cf: CityJSONFeatureVertices = from_str(
+            r#"{"CityObjects": {
+                "b1": {"type": "Building", "geometry": [],
+                       "attributes": {"construction_year": 1995, "status": "existing"}},
+                "b1-0": {"type": "BuildingPart", "geometry": []}
+            }, "vertices": []}"#,
+        )?;
+        let filters =
+            |f: &[&str]| -> Vec<FeatureFilter> { f.iter().map(|s| s.parse().unwrap()).collect() };
+        assert!(cf.matches_filters(&[], None));
+        assert!(cf.matches_filters(&filters(&["construction_year>1990"]), None));
+        assert!(!cf.matches_filters(&filters(&["construction_year<1990"]), None));
+        assert!(cf.matches_filters(
+            &filters(&["status=existing", "construction_year=1995"]),
+            None
+        ));
+        assert!(!cf.matches_filters(&filters(&["status!=existing"]), None));
+        assert!(!cf.matches_filters(&filters(&["height>10"]), None));
+        assert!(!cf.matches_filters(
+            &filters(&["construction_year>1990"]),
+            Some(&vec![CityObjectType::BuildingPart])
+        ));
+        assert_eq!(
+            FeatureFilter::from_str("status != demolished").unwrap(),
+            FeatureFilter {
+                key: "status".to_string(),
+                operator: FilterOperator::Ne,
+                value: "demolished".to_string()
+            }
+        );
+        assert!(FeatureFilter::from_str("construction_year").is_err());
+        assert!(FeatureFilter::from_str("=1990").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cityobjecttype() {
+        for cotype in <CityObjectType as clap::ValueEnum>::value_variants() {
+            assert_eq!(
+                CityObjectType::from_str(&cotype.to_string()).as_ref(),
+                Ok(cotype)
+            );
+        }
+        assert_eq!(
+            from_str::<CityObjectType>(r#""+GenericCityObject""#).unwrap(),
+            CityObjectType::GenericCityObject
+        );
+        assert_eq!(
+            from_str::<CityObjectType>(r#""TunnelPart""#).unwrap(),
+            CityObjectType::TunnelPart
+        );
+    }
+
+    #[test]
+    fn test_auto_cellsize() {
+        let extent = [0.0, 0.0, 0.0, 1000.0, 1000.0, 10.0];
+        assert_eq!(World::auto_cellsize(&extent, 1000, 10), 100);
+        assert_eq!(World::auto_cellsize(&extent, 0, 10), 1);
+        assert_eq!(World::auto_cellsize(&extent, 1, 1_000_000), u16::MAX);
+    }
+
     #[test]
     fn test_crs_to_epsg() {
         let crs = Crs("https://www.opengis.net/def/crs/EPSG/0/7415".to_string());
         let epsg_code = crs.to_epsg().unwrap();
         assert_eq!(7415_u16, epsg_code);
+        for crs in ["urn:ogc:def:crs:EPSG::28992", "EPSG:28992", "epsg:28992"] {
+            assert_eq!(Crs::from_str(crs).unwrap().to_epsg().unwrap(), 28992);
+        }
+        for crs in [
+            "https://www.opengis.net/def/crs/OGC/0/CRS84",
+            "urn:ogc:def:crs:OGC::CRS84",
+            "EPSG:x",
+        ] {
+            assert!(Crs::from_str(crs).is_err(), "{}", crs);
+        }
     }
 
     #[test]
@@ -723,11 +2374,30 @@ mod tests {
             "vertices": []
         }"#;
         let cm: CityJSONMetadata = from_str(cityjson_str)?;
+        assert_eq!(cm.metadata.attribution().as_deref(), Some("MyTitle"));
         println!("{:#?}", cm.metadata.reference_system);
         println!("{:#?}, {:#?}", cm.transform.scale, cm.transform.translate);
         Ok(())
     }
 
+    #[test]
+    fn test_metadata_attribution() -> serde_json::Result<()> {
+        let metadata: Metadata = from_str(
+            r#"{
+                "referenceSystem": "https://www.opengis.net/def/crs/EPSG/0/7415",
+                "pointOfContact": {"contactName": "J. Doe", "organization": "Kadaster & <Co>"}
+            }"#,
+        )?;
+        assert_eq!(
+            metadata.attribution().as_deref(),
+            Some("Kadaster &amp; &lt;Co&gt;")
+        );
+        let metadata: Metadata =
+            from_str(r#"{"referenceSystem": "https://www.opengis.net/def/crs/EPSG/0/7415"}"#)?;
+        assert!(metadata.attribution().is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_cityjsonfeaturevertices() -> serde_json::Result<()> {
         let cityjsonfeature_str = r#"{"type":"CityJSONFeature","CityObjects":{"b70a1e56f-debe-11e7-8ec4-89be260623ee":{"type":"Road","geometry":[{"type":"MultiSurface","lod":"1","boundaries":[[[0,1,2]],[[1,3,4]],[[1,0,3]],[[2,5,0]],[[2,6,5]],[[7,8,6]],[[9,10,11]],[[10,12,13]],[[14,15,16]],[[17,14,16]],[[18,19,20]],[[21,22,23]],[[24,25,26]],[[20,27,25]],[[20,19,27]],[[28,29,30]],[[9,23,10]],[[31,32,28]],[[31,33,32]],[[34,31,28]],[[35,34,28]],[[35,28,30]],[[36,22,37]],[[30,29,18]],[[36,38,39]],[[18,29,19]],[[40,26,41]],[[42,40,41]],[[24,20,25]],[[17,43,42]],[[40,42,43]],[[26,40,24]],[[43,17,16]],[[15,14,39]],[[39,38,15]],[[37,38,36]],[[21,37,22]],[[9,21,23]],[[11,10,44]],[[44,10,13]],[[13,12,7]],[[7,12,8]],[[2,7,6]],[[45,46,4]],[[46,1,4]],[[47,46,45]],[[48,47,45]]]}],"attributes":{"3df_id":"G0200.42b3d391aef50268e0530a0a28492340"}}},"vertices":[[23241731,-6740287,16980],[23243271,-6737886,17050],[23241947,-6737751,17030],[23243688,-6740239,16990],[23244961,-6739729,16990],[23241021,-6740116,16970],[23240334,-6739867,16960],[23240760,-6737152,17020],[23239680,-6739542,16950],[23207572,-6713437,17050],[23206398,-6715354,17010],[23211403,-6716175,17030],[23239066,-6739146,16950],[23224416,-6725473,17000],[23154567,-6713216,17160],[23200871,-6711570,17040],[23153430,-6710683,17210],[23152498,-6713168,17190],[23148683,-6700000,17400],[23145589,-6704251,17390],[23148683,-6706399,17330],[23205998,-6712657,17050],[23204080,-6714161,17010],[23205285,-6714668,17010],[23149399,-6707907,17300],[23146208,-6708310,17330],[23147259,-6710093,17300],[23145640,-6706320,17370],[23146890,-6619484,17810],[23145656,-6700000,17440],[23149034,-6662558,17710],[23140404,-6619323,17890],[23139266,-6623569,17820],[23139266,-6619957,17790],[23149466,-6614336,17770],[23149281,-6634334,17790],[23202811,-6713844,17010],[23204339,-6712080,17050],[23202621,-6711716,17040],[23201509,-6713726,17010],[23150482,-6709178,17270],[23148723,-6711555,17260],[23150508,-6712602,17220],[23151857,-6710125,17240],[23219449,-6721924,17010],[23246174,-6738901,16990],[23244554,-6737539,17080],[23245629,-6736755,17120],[23246913,-6738228,17040]],"id":"b70a1e56f-debe-11e7-8ec4-89be260623ee"}"#;
@@ -738,6 +2408,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_feature_hash() -> serde_json::Result<()> {
+        let a: CityJSONFeatureVertices = from_str(
+            r#"{"CityObjects":{"a":{"type":"Building","geometry":[{"type":"MultiSurface","boundaries":[[[0,1,2]]]}]},"b":{"type":"BuildingPart","geometry":[]}},"vertices":[[0,0,0],[1,0,0],[0,1,0]]}"#,
+        )?;
+        let a_reordered: CityJSONFeatureVertices = from_str(
+            r#"{"CityObjects":{"b":{"type":"BuildingPart","geometry":[]},"a":{"type":"Building","geometry":[{"type":"MultiSurface","boundaries":[[[0,1,2]]]}]}},"vertices":[[0,0,0],[1,0,0],[0,1,0]]}"#,
+        )?;
+        let a_moved: CityJSONFeatureVertices = from_str(
+            r#"{"CityObjects":{"a":{"type":"Building","geometry":[{"type":"MultiSurface","boundaries":[[[0,1,2]]]}]},"b":{"type":"BuildingPart","geometry":[]}},"vertices":[[0,0,0],[1,0,0],[0,2,0]]}"#,
+        )?;
+        assert_eq!(a.feature_hash(), a_reordered.feature_hash());
+        assert_ne!(a.feature_hash(), a_moved.feature_hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spool_cityjsonseq() -> Result<(), Box<dyn std::error::Error>> {
+        let stream = r#"{"type":"CityJSON","version":"1.1","transform":{"scale":[1.0,1.0,1.0],"translate":[0.0,0.0,0.0]},"metadata":{"referenceSystem":"https://www.opengis.net/def/crs/EPSG/0/7415"},"CityObjects":{},"vertices":[]}
+{"type":"CityJSONFeature","id":"a","CityObjects":{"a":{"type":"Building","geometry":[]}},"vertices":[[0,0,0]]}
+{"type":"CityJSONFeature","id":"b","CityObjects":{"b":{"type":"Building","geometry":[]}},"vertices":[[1,1,1]]}
+"#;
+        let dir = std::env::temp_dir().join("tyler_test_spool_cityjsonseq");
+        let path_metadata = spool_cityjsonseq(stream.as_bytes(), &dir)?;
+        assert_eq!(path_metadata, Some(dir.join("metadata.city.json")));
+        CityJSONMetadata::from_file(path_metadata.unwrap())?;
+        let features: Vec<PathBuf> =
+            World::feature_paths(&dir, &[DEFAULT_FEATURES_EXTENSION.to_string()]).collect();
+        assert_eq!(features.len(), 2);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
     #[test]
     fn test_centroid() -> serde_json::Result<()> {
         let pb: PathBuf = test_data_dir().join("3dbag_feature_x71.city.jsonl");
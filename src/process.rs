@@ -0,0 +1,264 @@
+//! Running external programs, such as the conversion subprocess.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Output, Stdio};
+use std::thread::{sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often a process with a timeout is checked whether it has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A command line that can be run several times, eg. for retrying a failed conversion.
+///
+/// Unlike [std::process::Command], it can be cloned and displayed.
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+}
+
+/// The result of a process whose stderr is merged into its stdout.
+#[derive(Debug)]
+pub struct MergedOutput {
+    pub status: ExitStatus,
+    /// The stdout and stderr, empty if the process timed out.
+    pub stdout: String,
+    /// The process was killed, because it did not finish within the timeout.
+    pub timed_out: bool,
+}
+
+/// A running process that was started with [Cmd::spawn_merged].
+pub struct Running {
+    child: Child,
+    stdout_reader: JoinHandle<io::Result<Vec<u8>>>,
+    stdin_writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Cmd {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Self {
+            program: program.as_ref().to_os_string(),
+            args: Vec::new(),
+            envs: Vec::new(),
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|a| a.as_ref().to_os_string()));
+        self
+    }
+
+    /// Set an environment variable of the process, in addition to the inherited ones.
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, val: V) -> Self {
+        self.envs
+            .push((key.as_ref().to_os_string(), val.as_ref().to_os_string()));
+        self
+    }
+
+    /// The command line quoted in the Unix style, without the environment variables.
+    pub fn to_cmdline_lossy(&self) -> String {
+        let mut out = display_escape(&self.program.to_string_lossy()).into_owned();
+        for arg in self.args.iter() {
+            out.push(' ');
+            out.push_str(&display_escape(&arg.to_string_lossy()));
+        }
+        out
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).envs(self.envs.iter().cloned());
+        command
+    }
+
+    /// Start the process with its stderr merged into its stdout. If `stdin_data` is set,
+    /// it is written to the stdin of the process, otherwise the stdin is inherited.
+    pub fn spawn_merged(&self, stdin_data: Option<&str>) -> io::Result<Running> {
+        let (reader, writer) = io::pipe()?;
+        let mut command = self.command();
+        command.stdout(writer.try_clone()?).stderr(writer);
+        if stdin_data.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        let mut child = command.spawn()?;
+        // The command holds the write end of the pipe, which must be closed so that the
+        // reader gets an EOF when the process exits.
+        drop(command);
+        let stdin_writer = write_stdin(child.stdin.take(), stdin_data);
+        let stdout_reader = std::thread::spawn(move || {
+            let mut reader = reader;
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            Ok(buf)
+        });
+        Ok(Running {
+            child,
+            stdout_reader,
+            stdin_writer,
+        })
+    }
+
+    /// Run the process to completion with its stderr merged into its stdout.
+    pub fn output_merged(&self) -> io::Result<MergedOutput> {
+        self.spawn_merged(None)?.wait(None)
+    }
+
+    /// Run the process to completion with separate stdout and stderr, writing
+    /// `stdin_data` to its stdin.
+    pub fn output(&self, stdin_data: &str) -> io::Result<Output> {
+        let mut child = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin_writer = write_stdin(child.stdin.take(), Some(stdin_data));
+        let output = child.wait_with_output()?;
+        join_stdin_writer(stdin_writer)?;
+        Ok(output)
+    }
+}
+
+impl Running {
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Wait until the process finishes, or kill it if it does not finish within the
+    /// `timeout`.
+    pub fn wait(mut self, timeout: Option<Duration>) -> io::Result<MergedOutput> {
+        let mut timed_out = false;
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            while self.child.try_wait()?.is_none() {
+                if Instant::now() >= deadline {
+                    self.child.kill()?;
+                    timed_out = true;
+                    break;
+                }
+                sleep(POLL_INTERVAL);
+            }
+        }
+        let status = self.child.wait()?;
+        if timed_out {
+            // The pipes can be held open by the children of the killed process, so the
+            // reader and writer threads are not joined.
+            return Ok(MergedOutput {
+                status,
+                stdout: String::new(),
+                timed_out,
+            });
+        }
+        join_stdin_writer(self.stdin_writer)?;
+        let stdout = self
+            .stdout_reader
+            .join()
+            .map_err(|_| io::Error::other("the stdout reader thread panicked"))??;
+        Ok(MergedOutput {
+            status,
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            timed_out,
+        })
+    }
+}
+
+/// Write the `stdin_data` in a separate thread, so that a process that fills its stdout
+/// before it reads all its stdin does not block.
+fn write_stdin(
+    stdin: Option<ChildStdin>,
+    stdin_data: Option<&str>,
+) -> Option<JoinHandle<io::Result<()>>> {
+    let (mut stdin, data) = (stdin?, stdin_data?.as_bytes().to_vec());
+    Some(std::thread::spawn(move || {
+        // The stdin is closed when it is dropped at the end of the thread.
+        match stdin.write_all(&data) {
+            // The process does not need to read all of its input.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            res => res,
+        }
+    }))
+}
+
+fn join_stdin_writer(stdin_writer: Option<JoinHandle<io::Result<()>>>) -> io::Result<()> {
+    match stdin_writer {
+        Some(handle) => handle
+            .join()
+            .map_err(|_| io::Error::other("the stdin writer thread panicked"))?,
+        None => Ok(()),
+    }
+}
+
+/// Quote the string for a Unix shell, if it contains other than safe characters.
+fn display_escape(s: &str) -> Cow<'_, str> {
+    fn nice_char(c: char) -> bool {
+        matches!(c, '-' | '_' | '.' | ',' | '/' | '=') || c.is_ascii_alphanumeric()
+    }
+    if !s.is_empty() && s.chars().all(nice_char) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("'{}'", s.replace('\'', r#"'\''"#)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_cmdline_lossy() {
+        let cmd = Cmd::new("geof")
+            .arg("--output_file=/tmp/a b.glb")
+            .arg("--min_x=1.5")
+            .env("PROJ_DATA", "/usr/share/proj");
+        assert_eq!(
+            cmd.to_cmdline_lossy(),
+            "geof '--output_file=/tmp/a b.glb' --min_x=1.5"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_merged_output() {
+        let out = Cmd::new("sh")
+            .arg("-c")
+            .arg("cat; echo err >&2; exit 3")
+            .spawn_merged(Some("in\n"))
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        assert_eq!(out.status.code(), Some(3));
+        assert_eq!(out.stdout, "in\nerr\n");
+        let out = Cmd::new("sleep")
+            .arg("5")
+            .spawn_merged(None)
+            .unwrap()
+            .wait(Some(Duration::from_millis(100)))
+            .unwrap();
+        assert!(out.timed_out && !out.status.success());
+    }
+}
@@ -20,10 +20,13 @@ use libc::c_int;
 use libc::{c_char, c_double};
 use num_traits::Float;
 use proj_sys::{
-    proj_area_create, proj_area_set_bbox, proj_context_create, proj_context_errno,
-    proj_create_crs_to_crs, proj_destroy, proj_errno_string, proj_normalize_for_visualization,
-    proj_trans, PJconsts, PJ_AREA, PJ_CONTEXT, PJ_COORD, PJ_DIRECTION_PJ_FWD, PJ_XYZT,
+    proj_area_create, proj_area_set_bbox, proj_as_wkt, proj_context_create, proj_context_errno,
+    proj_create, proj_create_crs_to_crs, proj_destroy, proj_errno_string,
+    proj_normalize_for_visualization, proj_trans, PJconsts, PJ_AREA, PJ_CONTEXT, PJ_COORD,
+    PJ_DIRECTION_PJ_FWD, PJ_WKT_TYPE, PJ_XYZT,
 };
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::{fmt::Debug, str};
 
 use proj_sys::{proj_errno, proj_errno_reset};
@@ -65,6 +68,25 @@ fn transform_epsg(
     })
 }
 
+/// Return the WKT representation of a known CRS, eg. "EPSG:7415".
+pub fn crs_to_wkt(crs: &str, wkt_type: PJ_WKT_TYPE) -> Result<String, ProjCreateError> {
+    let ctx = unsafe { proj_context_create() };
+    let crs_c = CString::new(crs).map_err(ProjCreateError::ArgumentNulError)?;
+    let ptr = result_from_create(ctx, unsafe { proj_create(ctx, crs_c.as_ptr()) })
+        .map_err(|e| ProjCreateError::ProjError(e.message(ctx)))?;
+    let wkt_ptr = unsafe { proj_as_wkt(ctx, ptr, wkt_type, std::ptr::null()) };
+    let wkt = if wkt_ptr.is_null() {
+        Err(ProjCreateError::ProjError(format!(
+            "could not export {} to WKT",
+            crs
+        )))
+    } else {
+        unsafe { _string(wkt_ptr) }.map_err(|e| ProjCreateError::ProjError(e.to_string()))
+    };
+    unsafe { proj_destroy(ptr) };
+    wkt
+}
+
 /// Construct a `Result` from the result of a `proj_create*` call.
 fn result_from_create<T>(context: *mut PJ_CONTEXT, ptr: *mut T) -> Result<*mut T, Errno> {
     if ptr.is_null() {
@@ -175,6 +197,62 @@ impl Proj {
     }
 }
 
+thread_local! {
+    /// The transformations that were created by the [Reprojector]-s on the current thread,
+    /// with their source and target CRS.
+    static TRANSFORMATIONS: RefCell<Vec<(String, String, Rc<Proj>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A coordinate transformation between two known CRS-s, that can be shared between threads.
+///
+/// A [Proj] transformation cannot be used by several threads, and creating it is expensive.
+/// Therefore, each thread creates the transformation on its first use, and reuses it for
+/// all the following points, also in the clones of the `Reprojector`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reprojector {
+    from: String,
+    to: String,
+}
+
+impl Reprojector {
+    /// Create the transformation from the CRS `from` to the CRS `to`, eg. "EPSG:7415" and
+    /// "EPSG:4979".
+    pub fn new(from: &str, to: &str) -> Result<Self, ProjCreateError> {
+        let reprojector = Self {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        reprojector.proj()?;
+        Ok(reprojector)
+    }
+
+    /// The transformation of the current thread.
+    fn proj(&self) -> Result<Rc<Proj>, ProjCreateError> {
+        TRANSFORMATIONS.with(|transformations| {
+            if let Some((_, _, proj)) = transformations
+                .borrow()
+                .iter()
+                .find(|(from, to, _)| from == &self.from && to == &self.to)
+            {
+                return Ok(proj.clone());
+            }
+            let proj = Rc::new(Proj::new_known_crs(&self.from, &self.to, None)?);
+            transformations
+                .borrow_mut()
+                .push((self.from.clone(), self.to.clone(), proj.clone()));
+            Ok(proj)
+        })
+    }
+
+    pub fn reproject(&self, point: &[f64; 3]) -> Result<[f64; 3], ProjError> {
+        let proj = self
+            .proj()
+            .map_err(|e| ProjError::Conversion(e.to_string()))?;
+        let (x, y, z) = proj.convert((point[0], point[1], point[2]))?;
+        Ok([x, y, z])
+    }
+}
+
 /// Errors originating in PROJ which can occur during projection and conversion
 #[derive(Error, Debug)]
 pub enum ProjError {
@@ -257,4 +335,23 @@ mod tests {
         // [4] = {f64} 300035.46416343335
         // [5] = {f64} 5003151.7442537257
     }
+
+    #[test]
+    fn test_reprojector() {
+        use rayon::prelude::*;
+        let reprojector = Reprojector::new("EPSG:7415", "EPSG:4979").unwrap();
+        let transformer = Proj::new_known_crs("EPSG:7415", "EPSG:4979", None).unwrap();
+        let points: Vec<[f64; 3]> = (0..100)
+            .map(|i| [85000.0 + i as f64, 446000.0 + i as f64, 10.0])
+            .collect();
+        // Each rayon worker creates its own transformation
+        let reprojected: Vec<[f64; 3]> = points
+            .par_iter()
+            .map(|point| reprojector.clone().reproject(point).unwrap())
+            .collect();
+        for (point, point_reprojected) in points.iter().zip(reprojected.iter()) {
+            let (x, y, z) = transformer.convert((point[0], point[1], point[2])).unwrap();
+            assert_eq!(*point_reprojected, [x, y, z]);
+        }
+    }
 }
@@ -0,0 +1,200 @@
+//! Downloading the inputs from HTTP(S) URLs into a local cache.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use rayon::prelude::*;
+
+use crate::process::Cmd;
+
+/// Is the input path an `http://` or `https://` URL?
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// Downloads the files with [curl](https://curl.se/) into a cache directory.
+///
+/// A file is stored in the cache under its host and path, eg.
+/// `https://example.com/data/metadata.city.json` in `<cache_dir>/example.com/data/metadata.city.json`.
+/// The files that are already in the cache are not downloaded again, so the cache can be
+/// reused across runs.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    exe: PathBuf,
+    cache_dir: PathBuf,
+}
+
+impl Downloader {
+    pub fn new(exe: PathBuf, cache_dir: PathBuf) -> Self {
+        Self { exe, cache_dir }
+    }
+
+    /// The default cache directory, which is `$XDG_CACHE_HOME/tyler` or
+    /// `$HOME/.cache/tyler`, or the `tyler_cache` in the temporary directory if neither
+    /// is set.
+    pub fn default_cache_dir() -> PathBuf {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|cache| cache.join("tyler"))
+            .unwrap_or_else(|| std::env::temp_dir().join("tyler_cache"))
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// The location of the `url` in the cache.
+    fn cache_path(&self, url: &str) -> Result<PathBuf, String> {
+        let without_scheme = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .ok_or_else(|| format!("{} is not an HTTP(S) URL", url))?;
+        let mut path = self.cache_dir.clone();
+        for part in without_scheme.split('/').filter(|part| !part.is_empty()) {
+            // The query and the characters that are not allowed in file names are replaced,
+            // and '..' cannot escape the cache
+            let part: String = part
+                .chars()
+                .map(|c| match c {
+                    '?' | '&' | '=' | ':' | '\\' | '*' | '"' | '<' | '>' | '|' => '_',
+                    c => c,
+                })
+                .collect();
+            path.push(if part == ".." { "_".to_string() } else { part });
+        }
+        Ok(path)
+    }
+
+    /// Download the `url` into the cache, unless it is cached already, and return its path
+    /// in the cache.
+    pub fn fetch(&self, url: &str) -> Result<PathBuf, String> {
+        let path = self.cache_path(url)?;
+        if path.metadata().is_ok_and(|m| m.len() > 0) {
+            debug!("Using the cached {:?} for {}", &path, url);
+            return Ok(path);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("could not create the directory {:?}: {}", dir, e))?;
+        }
+        // Download into a temporary file, so that an interrupted download is not cached
+        let mut path_part = path.clone().into_os_string();
+        path_part.push(".part");
+        debug!("Downloading {} into {:?}", url, &path);
+        let output = Cmd::new(&self.exe)
+            .args([
+                "--fail",
+                "--silent",
+                "--show-error",
+                "--location",
+                "--output",
+            ])
+            .arg(&path_part)
+            .arg(url)
+            .output("")
+            .map_err(|e| format!("could not run {:?}: {}", &self.exe, e))?;
+        if !output.status.success() {
+            let _ = fs::remove_file(&path_part);
+            return Err(format!(
+                "could not download {}: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        fs::rename(&path_part, &path).map_err(|e| format!("could not write {:?}: {}", &path, e))?;
+        Ok(path)
+    }
+
+    /// Download a feature list and the features that it lists, and return the path of a
+    /// local feature list with the paths of the cached features.
+    /// The relative URLs in the list are resolved against the URL of the list.
+    pub fn fetch_feature_list(&self, url: &str) -> Result<PathBuf, String> {
+        let path_list = self.fetch(url)?;
+        let list = fs::read_to_string(&path_list)
+            .map_err(|e| format!("could not read {:?}: {}", &path_list, e))?;
+        let feature_urls: Vec<String> = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| resolve_url(url, line))
+            .collect();
+        let paths: Vec<PathBuf> = feature_urls
+            .par_iter()
+            .map(|feature_url| self.fetch(feature_url))
+            .collect::<Result<_, _>>()?;
+        let path_local_list = path_list.with_extension("local.txt");
+        let local_list: String = paths
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect();
+        fs::write(&path_local_list, local_list)
+            .map_err(|e| format!("could not write {:?}: {}", &path_local_list, e))?;
+        Ok(path_local_list)
+    }
+}
+
+/// Resolve the `reference` from a feature list against the `base` URL of the list.
+fn resolve_url(base: &str, reference: &str) -> String {
+    if is_url(Path::new(reference)) {
+        return reference.to_string();
+    }
+    if reference.starts_with('/') {
+        // The scheme and the host of the base
+        let host_end = base
+            .find("://")
+            .and_then(|i| base[i + 3..].find('/').map(|j| i + 3 + j))
+            .unwrap_or(base.len());
+        return format!("{}{}", &base[..host_end], reference);
+    }
+    let dir_end = base.rfind('/').map(|i| i + 1).unwrap_or(base.len());
+    format!("{}{}", &base[..dir_end], reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "https://example.com/data/features.txt";
+        assert_eq!(
+            resolve_url(base, "a/1.city.jsonl"),
+            "https://example.com/data/a/1.city.jsonl"
+        );
+        assert_eq!(
+            resolve_url(base, "/other/2.city.jsonl"),
+            "https://example.com/other/2.city.jsonl"
+        );
+        assert_eq!(
+            resolve_url(base, "http://mirror.org/3.city.jsonl"),
+            "http://mirror.org/3.city.jsonl"
+        );
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let downloader = Downloader::new(PathBuf::from("curl"), PathBuf::from("/cache"));
+        assert_eq!(
+            downloader
+                .cache_path("https://example.com/data/../f.city.json?v=1")
+                .unwrap(),
+            PathBuf::from("/cache/example.com/data/_/f.city.json_v_1")
+        );
+        assert!(downloader.cache_path("ftp://example.com/f").is_err());
+    }
+}
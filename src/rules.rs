@@ -0,0 +1,113 @@
+//! Per-tile arguments for the conversion subprocess.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::spatial_structs::Bbox;
+
+/// A rule that selects tiles and adds extra arguments to their conversion subprocess.
+///
+/// A rule selects a tile if all of its selectors match. A rule without selectors
+/// selects every tile.
+///
+/// `tile_id_prefix` - The tile ID (`level/x/y`) starts with this string, eg. `"10/4"`.
+///
+/// `level` - The tile is on this level.
+///
+/// `bbox` - The tile intersects this 2D bbox (`[minx, miny, maxx, maxy]`) in the CRS of
+/// the input.
+///
+/// `args` - The arguments that are passed to the subprocess, eg. `"--simplify_ratio=0.1"`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConverterRule {
+    tile_id_prefix: Option<String>,
+    level: Option<u16>,
+    bbox: Option<[f64; 4]>,
+    args: Vec<String>,
+}
+
+impl ConverterRule {
+    fn matches(&self, tile_id: &str, level: u16, tile_bbox: &Bbox) -> bool {
+        if let Some(ref prefix) = self.tile_id_prefix {
+            if !tile_id.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(l) = self.level {
+            if l != level {
+                return false;
+            }
+        }
+        if let Some([minx, miny, maxx, maxy]) = self.bbox {
+            if tile_bbox[3] < minx
+                || tile_bbox[0] > maxx
+                || tile_bbox[4] < miny
+                || tile_bbox[1] > maxy
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The ordered list of [ConverterRule]-s, read from a JSON array.
+/// The rules are evaluated in order and the first matching rule wins.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct ConverterRules(Vec<ConverterRule>);
+
+impl ConverterRules {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let rules_str = read_to_string(path.as_ref())?;
+        let rules: ConverterRules = serde_json::from_str(&rules_str)?;
+        Ok(rules)
+    }
+
+    /// The extra arguments of the first rule that matches the tile, or none if no rule
+    /// matches.
+    pub fn args(&self, tile_id: &str, level: u16, tile_bbox: &Bbox) -> &[String] {
+        self.0
+            .iter()
+            .find(|rule| rule.matches(tile_id, level, tile_bbox))
+            .map(|rule| rule.args.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_match_wins() {
+        let rules: ConverterRules = serde_json::from_str(
+            r#"[
+                {"tile_id_prefix": "10/4/", "args": ["--simplify_ratio=1.0"]},
+                {"level": 10, "args": ["--simplify_ratio=0.1"]},
+                {"bbox": [0.0, 0.0, 10.0, 10.0], "args": ["--skip_clip=true"]}
+            ]"#,
+        )
+        .unwrap();
+        let bbox: Bbox = [5.0, 5.0, 0.0, 15.0, 15.0, 1.0];
+        assert_eq!(rules.args("10/4/7", 10, &bbox), ["--simplify_ratio=1.0"]);
+        assert_eq!(rules.args("10/5/7", 10, &bbox), ["--simplify_ratio=0.1"]);
+        assert_eq!(rules.args("9/2/3", 9, &bbox), ["--skip_clip=true"]);
+        let bbox_outside: Bbox = [20.0, 20.0, 0.0, 30.0, 30.0, 1.0];
+        assert!(rules.args("9/2/3", 9, &bbox_outside).is_empty());
+    }
+}
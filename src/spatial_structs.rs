@@ -13,11 +13,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::parser::FeatureSet;
-use log::{debug, error, warn};
-use std::collections::VecDeque;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 use morton_encoding::{morton_decode, morton_encode};
 
@@ -27,19 +29,113 @@ use morton_encoding::{morton_decode, morton_encode};
 #[derive(Clone, Debug)]
 pub struct QuadTree {
     pub id: QuadTreeNodeId,
-    side_length: u64,
+    /// The number of grid cells along the x and y-axis.
+    nr_cells: [usize; 2],
     pub children: Vec<QuadTree>,
     cells: Vec<CellId>,
     pub nr_items: usize,
+    /// The number of features in the node, for the [QuadTreeCapacity::ObjectsAndVertices].
+    nr_objects: usize,
 }
 
 impl QuadTree {
-    pub fn from_world(world: &crate::parser::World, limit: QuadTreeCapacity) -> Self {
-        Self::from_grid(&world.grid, limit)
+    /// The nodes at `max_depth` are not subdivided further, even if they have more items
+    /// than the capacity.
+    pub fn from_world(
+        world: &crate::parser::World,
+        limit: QuadTreeCapacity,
+        max_depth: Option<u16>,
+        split: QuadTreeSplit,
+    ) -> Self {
+        let quadtree = match split {
+            QuadTreeSplit::Center => Self::from_grid(&world.grid, limit, max_depth),
+            QuadTreeSplit::Median => Self::from_grid_median(&world.grid, limit, max_depth),
+        };
+        let nr_over_capacity = quadtree.stats(limit).nr_over_capacity;
+        if nr_over_capacity > 0 {
+            warn!(
+                "{} quadtree leaves have more items than the capacity {:?}",
+                nr_over_capacity, limit
+            );
+        }
+        quadtree
     }
 
-    fn from_grid(grid: &SquareGrid, limit: QuadTreeCapacity) -> Self {
-        let mut merge_limit: usize = 0;
+    /// Find the capacity at which the quadtree has about `target_leaves` leaves with
+    /// items, within the `tolerance` (a fraction of `target_leaves`). The capacity of the
+    /// `limit` is binary searched in at most `max_iterations` quadtrees, and the type of
+    /// the capacity is kept. For [QuadTreeCapacity::ObjectsAndVertices], only the number
+    /// of vertices is searched. If the target is not reached, the capacity with the number
+    /// of leaves that is closest to the target is returned.
+    pub fn tune_capacity(
+        grid: &SquareGrid,
+        limit: QuadTreeCapacity,
+        max_depth: Option<u16>,
+        split: QuadTreeSplit,
+        target_leaves: usize,
+        tolerance: f64,
+        max_iterations: usize,
+    ) -> QuadTreeCapacity {
+        let nr_leaves = |limit: QuadTreeCapacity| -> usize {
+            let quadtree = match split {
+                QuadTreeSplit::Center => Self::from_grid(grid, limit, max_depth),
+                QuadTreeSplit::Median => Self::from_grid_median(grid, limit, max_depth),
+            };
+            quadtree
+                .collect_leaves()
+                .iter()
+                .filter(|leaf| leaf.nr_items > 0)
+                .count()
+        };
+        let max_deviation = (target_leaves as f64 * tolerance).round() as usize;
+        // The number of leaves decreases when the capacity increases, and with the total
+        // number of items as capacity, the root is the only leaf
+        let total_items: usize = grid
+            .into_iter()
+            .map(|(cellid, _)| Self::cell_items(grid, &cellid, limit))
+            .sum();
+        let (mut low, mut high) = (1_usize, total_items.max(1));
+        let mut best: Option<(QuadTreeCapacity, usize)> = None;
+        for iteration in 1..=max_iterations {
+            if low > high {
+                break;
+            }
+            let capacity = low + (high - low) / 2;
+            let trial = limit.with_capacity(capacity);
+            let leaves = nr_leaves(trial);
+            info!(
+                "Capacity trial {}: {:?} gives {} leaves (target {})",
+                iteration, trial, leaves, target_leaves
+            );
+            if best.is_none_or(|(_, best_leaves)| {
+                leaves.abs_diff(target_leaves) < best_leaves.abs_diff(target_leaves)
+            }) {
+                best = Some((trial, leaves));
+            }
+            if leaves.abs_diff(target_leaves) <= max_deviation {
+                break;
+            }
+            if leaves > target_leaves {
+                low = capacity + 1;
+            } else {
+                high = capacity - 1;
+            }
+        }
+        match best {
+            Some((capacity, leaves)) => {
+                if leaves.abs_diff(target_leaves) > max_deviation {
+                    warn!(
+                        "Could not reach the target of {} leaves, using the closest capacity {:?} with {} leaves",
+                        target_leaves, capacity, leaves
+                    );
+                }
+                capacity
+            }
+            None => limit,
+        }
+    }
+
+    fn from_grid(grid: &SquareGrid, limit: QuadTreeCapacity, max_depth: Option<u16>) -> Self {
         let nr_cells = grid.length.pow(2) as f64;
         let max_level = (nr_cells.ln() / 4.0_f64.ln()).ceil() as u16;
         debug!("Calculated maximum level for quadtree: {}", &max_level);
@@ -62,68 +158,212 @@ impl QuadTree {
                     row: y as usize,
                     column: x as usize,
                 };
-                let items: usize;
-                match limit {
-                    QuadTreeCapacity::Objects(l) => {
-                        // Use the number of features as a limit
-                        items = grid.cell(&cellid).feature_ids.len();
-                        merge_limit = l;
-                    }
-                    QuadTreeCapacity::Vertices(l) => {
-                        // Use the number of vertices as a limit
-                        items = grid.cell(&cellid).nr_vertices;
-                        merge_limit = l;
-                    }
-                }
+                let items = Self::cell_items(grid, &cellid, limit);
                 QuadTree {
                     id: QuadTreeNodeId::new(x as usize, y as usize, max_level),
-                    side_length: grid.cellsize as u64,
+                    nr_cells: [1, 1],
                     children: Vec::new(),
                     cells: vec![cellid],
                     nr_items: items,
+                    nr_objects: grid.cell(&cellid).feature_ids.len(),
                 }
             })
             .collect();
-        Self::merge_tiles(0, tiles_morton, merge_limit)
+        Self::merge_tiles(
+            0,
+            max_level,
+            tiles_morton,
+            limit,
+            max_depth.unwrap_or(u16::MAX),
+        )
+    }
+
+    /// The number of items in a cell, counted with the `limit`.
+    fn cell_items(grid: &SquareGrid, cellid: &CellId, limit: QuadTreeCapacity) -> usize {
+        let cell = grid.cell(cellid);
+        match limit {
+            // Use the number of features as a limit
+            QuadTreeCapacity::Objects(_) => cell.feature_ids.len(),
+            // Use the number of vertices as a limit, the number of features is counted
+            // separately
+            QuadTreeCapacity::Vertices(_) | QuadTreeCapacity::ObjectsAndVertices { .. } => {
+                cell.nr_vertices
+            }
+            // Use the size of the feature files as a limit
+            QuadTreeCapacity::Bytes(_) => cell.nr_bytes,
+        }
     }
 
-    fn merge_tiles(level: u16, tiles: Vec<QuadTree>, limit: usize) -> QuadTree {
+    /// Build the quadtree top-down, by splitting each node at the weighted median of the
+    /// items of its cells along the x and y-axis, instead of at its center. Thus the
+    /// children of a node have a similar number of items, and the nodes are rectangles of
+    /// whole cells, which are not necessarily square. A node that is a single cell is not
+    /// split any further.
+    /// The children without items are not added to the quadtree.
+    fn from_grid_median(
+        grid: &SquareGrid,
+        limit: QuadTreeCapacity,
+        max_depth: Option<u16>,
+    ) -> Self {
+        let items: Vec<Vec<usize>> = (0..grid.length)
+            .map(|column| {
+                (0..grid.length)
+                    .map(|row| Self::cell_items(grid, &CellId { row, column }, limit))
+                    .collect()
+            })
+            .collect();
+        let objects: Vec<Vec<usize>> = (0..grid.length)
+            .map(|column| {
+                (0..grid.length)
+                    .map(|row| grid.cell(&CellId { row, column }).feature_ids.len())
+                    .collect()
+            })
+            .collect();
+        Self::split_median(
+            &items,
+            &objects,
+            limit,
+            max_depth.unwrap_or(u16::MAX),
+            QuadTreeNodeId::new(0, 0, 0),
+            [grid.length, grid.length],
+        )
+    }
+
+    /// See [QuadTree::from_grid_median]. The `items` and the number of `objects` of the
+    /// cells are indexed by [column][row].
+    fn split_median(
+        items: &[Vec<usize>],
+        objects: &[Vec<usize>],
+        limit: QuadTreeCapacity,
+        max_depth: u16,
+        id: QuadTreeNodeId,
+        nr_cells: [usize; 2],
+    ) -> Self {
+        let columns = id.x..id.x + nr_cells[0];
+        let rows = id.y..id.y + nr_cells[1];
+        let items_column: Vec<usize> = columns
+            .clone()
+            .map(|column| items[column][rows.clone()].iter().sum())
+            .collect();
+        let items_row: Vec<usize> = rows
+            .clone()
+            .map(|row| columns.clone().map(|column| items[column][row]).sum())
+            .collect();
+        let nr_items: usize = items_column.iter().sum();
+        let nr_objects: usize = objects[columns.clone()]
+            .iter()
+            .map(|column| column[rows.clone()].iter().sum::<usize>())
+            .sum();
+        if limit.fits(nr_items, nr_objects)
+            || (nr_cells[0] <= 1 && nr_cells[1] <= 1)
+            || id.level >= max_depth
+        {
+            let mut cells: Vec<CellId> = Vec::with_capacity(nr_cells[0] * nr_cells[1]);
+            for row in rows {
+                for column in columns.clone() {
+                    cells.push(CellId { row, column });
+                }
+            }
+            return QuadTree {
+                id,
+                nr_cells,
+                children: vec![],
+                cells,
+                nr_items,
+                nr_objects,
+            };
+        }
+        let split_x = median_split(&items_column);
+        let split_y = median_split(&items_row);
+        let mut children: Vec<QuadTree> = Vec::with_capacity(4);
+        for (dy, child_nr_y) in [(0, split_y), (split_y, nr_cells[1] - split_y)] {
+            for (dx, child_nr_x) in [(0, split_x), (split_x, nr_cells[0] - split_x)] {
+                if child_nr_x == 0 || child_nr_y == 0 {
+                    continue;
+                }
+                let child_items: usize = items[id.x + dx..id.x + dx + child_nr_x]
+                    .iter()
+                    .map(|column| {
+                        column[id.y + dy..id.y + dy + child_nr_y]
+                            .iter()
+                            .sum::<usize>()
+                    })
+                    .sum();
+                if child_items == 0 {
+                    continue;
+                }
+                children.push(Self::split_median(
+                    items,
+                    objects,
+                    limit,
+                    max_depth,
+                    QuadTreeNodeId::new(id.x + dx, id.y + dy, id.level + 1),
+                    [child_nr_x, child_nr_y],
+                ));
+            }
+        }
+        QuadTree {
+            id,
+            nr_cells,
+            children,
+            cells: vec![],
+            nr_items,
+            nr_objects,
+        }
+    }
+
+    /// Merge the `tiles`, which are in Morton order, into a node at `level`.
+    /// The tiles are split into the quadrants of the node by their position, so the ID of a
+    /// node only depends on its position in the grid, and not on the number of tiles or
+    /// items.
+    fn merge_tiles(
+        level: u16,
+        max_level: u16,
+        tiles: Vec<QuadTree>,
+        limit: QuadTreeCapacity,
+        max_depth: u16,
+    ) -> QuadTree {
         let len_tiles = tiles.len();
         if len_tiles > 4 {
-            let q0: usize = len_tiles / 4;
-            let q1: usize = q0 * 2;
-            let q2: usize = q0 * 3;
             let next_level = level + 1;
-            Self::merge_tiles(
-                level,
-                vec![
-                    Self::merge_tiles(next_level, tiles[0..q0].to_vec(), limit),
-                    Self::merge_tiles(next_level, tiles[q0..q1].to_vec(), limit),
-                    Self::merge_tiles(next_level, tiles[q1..q2].to_vec(), limit),
-                    Self::merge_tiles(next_level, tiles[q2..].to_vec(), limit),
-                ],
-                limit,
-            )
+            let mut quadrants: [Vec<QuadTree>; 4] = Default::default();
+            for tile in tiles {
+                let quadrant = tile.id.quadrant(next_level, max_level);
+                quadrants[quadrant].push(tile);
+            }
+            let children: Vec<QuadTree> = quadrants
+                .into_iter()
+                .filter(|quadrant| !quadrant.is_empty())
+                .map(|quadrant| {
+                    Self::merge_tiles(next_level, max_level, quadrant, limit, max_depth)
+                })
+                .collect();
+            Self::merge_tiles(level, max_level, children, limit, max_depth)
         } else {
             let sum_items: usize = tiles.iter().map(|t| t.nr_items).sum();
+            let sum_objects: usize = tiles.iter().map(|t| t.nr_objects).sum();
+            // The tiles below max_depth can have children, so the cells are collected
+            // from their leaves.
             let mut cells: Vec<CellId> = Vec::new();
             for t in tiles.iter() {
-                for c in t.cells.iter() {
-                    cells.push(*c)
+                for leaf in t.collect_leaves() {
+                    cells.extend(leaf.cells.iter().copied());
                 }
             }
-            let id = QuadTreeNodeId::new(tiles[0].id.x, tiles[0].id.y, level);
+            let id = tiles[0].id.ancestor(level, max_level);
+            let side = QuadTreeNodeId::side_cells(level, max_level);
             let id_string = id.to_string();
             // FIXME: this also adds the quadtree if sum_items == 0 so the parent will have 4
             //  children instead of 3. Probably should return Option<Quadtree>.
             //  Currently these empty tiles are removed in Tile.prune().
-            if sum_items <= limit {
+            if limit.fits(sum_items, sum_objects) || level >= max_depth {
                 QuadTree {
                     id,
-                    side_length: tiles[0].side_length * 2,
+                    nr_cells: [side, side],
                     children: vec![],
                     cells,
                     nr_items: sum_items,
+                    nr_objects: sum_objects,
                 }
             } else {
                 if tiles.len() % 4 != 0 {
@@ -135,10 +375,11 @@ impl QuadTree {
                 }
                 QuadTree {
                     id,
-                    side_length: tiles[0].side_length * 2,
+                    nr_cells: [side, side],
                     children: tiles.clone(),
                     cells: vec![],
                     nr_items: sum_items,
+                    nr_objects: sum_objects,
                 }
             }
         }
@@ -160,15 +401,49 @@ impl QuadTree {
         leaves
     }
 
+    /// The distribution of the items in the leaves, where the items are counted with the
+    /// `limit`.
+    pub fn stats(&self, limit: QuadTreeCapacity) -> QuadTreeStats {
+        let leaves = self.collect_leaves();
+        let nr_over_capacity = leaves
+            .iter()
+            .filter(|leaf| !limit.fits(leaf.nr_items, leaf.nr_objects))
+            .count();
+        let mut nr_items: Vec<usize> = leaves.iter().map(|leaf| leaf.nr_items).collect();
+        nr_items.sort_unstable();
+        let mut leaves_per_level: Vec<usize> = Vec::new();
+        for leaf in leaves.iter() {
+            let level = leaf.id.level as usize;
+            if leaves_per_level.len() <= level {
+                leaves_per_level.resize(level + 1, 0);
+            }
+            leaves_per_level[level] += 1;
+        }
+        QuadTreeStats {
+            nr_leaves: leaves.len(),
+            min_items: nr_items[0],
+            median_items: nr_items[nr_items.len() / 2],
+            max_items: nr_items[nr_items.len() - 1],
+            leaves_per_level,
+            nr_over_capacity,
+            nr_vertices: match limit {
+                QuadTreeCapacity::Vertices(_) | QuadTreeCapacity::ObjectsAndVertices { .. } => {
+                    Some(nr_items.iter().sum())
+                }
+                _ => None,
+            },
+        }
+    }
+
     pub fn bbox(&self, grid: &SquareGrid) -> Bbox {
-        let minx = grid.origin[0] + (self.id.x * grid.cellsize as usize) as f64;
-        let miny = grid.origin[1] + (self.id.y * grid.cellsize as usize) as f64;
+        let minx = grid.origin[0] + (self.id.x * grid.cellsize[0] as usize) as f64;
+        let miny = grid.origin[1] + (self.id.y * grid.cellsize[1] as usize) as f64;
         [
             minx,
             miny,
             grid.bbox[2],
-            minx + self.side_length as f64,
-            miny + self.side_length as f64,
+            minx + (self.nr_cells[0] * grid.cellsize[0] as usize) as f64,
+            miny + (self.nr_cells[1] * grid.cellsize[1] as usize) as f64,
             grid.bbox[5],
         ]
     }
@@ -243,9 +518,113 @@ impl QuadTree {
         }
         Ok(())
     }
+
+    /// Write the quadtree structure to a JSON file, independent of the output format of
+    /// the tiles. Each node has its bbox, level, number of items and children, and the
+    /// leaves also list the paths of the features that are assigned to them.
+    pub fn export_json<P: AsRef<Path>>(
+        &self,
+        path: P,
+        world: &crate::parser::World,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file_out = File::create(path.as_ref())?;
+        serde_json::to_writer(&file_out, &QuadTreeJson::new(self, world))?;
+        Ok(())
+    }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+impl QuadTree {
+    /// The leaves of the quadtree as a GeoJSON FeatureCollection of polygons, in the CRS of
+    /// the grid. Each leaf has the properties `tile_id`, `nr_items` and `depth`.
+    pub fn to_geojson(&self, grid: &SquareGrid) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self
+            .collect_leaves()
+            .into_iter()
+            .map(|leaf| {
+                let [minx, miny, _, maxx, maxy, _] = leaf.bbox(grid);
+                serde_json::json!({
+                    "type": "Feature",
+                    "properties": {
+                        "tile_id": leaf.id.to_string(),
+                        "nr_items": leaf.nr_items,
+                        "depth": leaf.id.level,
+                    },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [minx, miny], [maxx, miny], [maxx, maxy], [minx, maxy], [minx, miny]
+                        ]],
+                    },
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "type": "FeatureCollection",
+            "crs": {
+                "type": "name",
+                "properties": {"name": format!("urn:ogc:def:crs:EPSG::{}", grid.epsg)},
+            },
+            "features": features,
+        })
+    }
+
+    /// Write the leaves of the quadtree to a GeoJSON file, see [QuadTree::to_geojson].
+    pub fn export_geojson<P: AsRef<Path>>(
+        &self,
+        path: P,
+        grid: &SquareGrid,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file_out = File::create(path.as_ref())?;
+        serde_json::to_writer(&file_out, &self.to_geojson(grid))?;
+        Ok(())
+    }
+}
+
+/// The serializable representation of a [QuadTree] node, see [QuadTree::export_json].
+#[derive(Serialize, Debug)]
+struct QuadTreeJson<'a> {
+    id: String,
+    level: u16,
+    bbox: Bbox,
+    nr_items: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<QuadTreeJson<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    features: Option<Vec<&'a PathBuf>>,
+}
+
+impl<'a> QuadTreeJson<'a> {
+    fn new(node: &QuadTree, world: &'a crate::parser::World) -> Self {
+        let features = if node.children.is_empty() {
+            Some(
+                node.cells()
+                    .into_iter()
+                    .flat_map(|cellid| world.grid.cell(cellid).feature_ids.iter())
+                    .map(|fid| &world.features[*fid].path_jsonl)
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        Self {
+            id: node.id.to_string(),
+            level: node.id.level,
+            bbox: node.bbox(&world.grid),
+            nr_items: node.nr_items,
+            children: node
+                .children
+                .iter()
+                .map(|child| QuadTreeJson::new(child, world))
+                .collect(),
+            features,
+        }
+    }
+}
+
+/// The ID of a [QuadTree] node is its `level` and the column (`x`) and row (`y`) of the
+/// grid cell in the lower-left corner of the node. Thus, the ID only depends on the
+/// position of the node in the grid.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct QuadTreeNodeId {
     pub x: usize,
     pub y: usize,
@@ -256,6 +635,25 @@ impl QuadTreeNodeId {
     pub fn new(x: usize, y: usize, level: u16) -> Self {
         Self { x, y, level }
     }
+
+    /// The number of grid cells along one side of a node at `level`, if the leaves at
+    /// `max_level` are single cells.
+    fn side_cells(level: u16, max_level: u16) -> usize {
+        1 << max_level.saturating_sub(level)
+    }
+
+    /// The ID of the node at `level` that contains this node.
+    pub fn ancestor(&self, level: u16, max_level: u16) -> Self {
+        let side = Self::side_cells(level, max_level);
+        Self::new(self.x / side * side, self.y / side * side, level)
+    }
+
+    /// The index of the quadrant at `level` that contains this node, in Morton order
+    /// (lower-left, lower-right, upper-left, upper-right).
+    fn quadrant(&self, level: u16, max_level: u16) -> usize {
+        let side = Self::side_cells(level, max_level);
+        (self.x / side) % 2 + (self.y / side) % 2 * 2
+    }
 }
 
 impl Display for QuadTreeNodeId {
@@ -264,15 +662,416 @@ impl Display for QuadTreeNodeId {
     }
 }
 
+/// Octree
+///
+/// Same as the [QuadTree], but a node is also split along the z-axis, so that stacked
+/// objects (eg. underground and above-ground structures) can be in different leaves.
+/// The octree is built top-down from the grid. A node that has more items than the
+/// capacity is split into 8 children, by halving its cells along the x and y-axis and
+/// its height along the z-axis. A node that is a single cell is not split any further,
+/// because the features are assigned to the grid cells.
+/// The features are assigned to the z-halves by the z-coordinate of the center of their
+/// bbox. The children without features are not added to the octree.
+#[derive(Clone, Debug)]
+pub struct Octree {
+    pub id: OctreeNodeId,
+    /// The number of grid cells along the x and y-axis.
+    nr_cells: [usize; 2],
+    /// The min. and max. z-coordinate of the node.
+    z_range: [f64; 2],
+    pub children: Vec<Octree>,
+    feature_ids: Vec<usize>,
+    pub nr_items: usize,
+}
+
+impl Octree {
+    /// The nodes at `max_depth` are not split further, even if they have more items than
+    /// the capacity.
+    pub fn from_world(
+        world: &crate::parser::World,
+        limit: QuadTreeCapacity,
+        max_depth: Option<u16>,
+    ) -> Self {
+        let octree = Self::from_grid(
+            &world.grid,
+            &world.features,
+            &world.transform,
+            limit,
+            max_depth,
+        );
+        let nr_over_capacity = octree
+            .collect_leaves()
+            .iter()
+            .filter(|leaf| !limit.fits(leaf.nr_items, leaf.feature_ids.len()))
+            .count();
+        if nr_over_capacity > 0 {
+            warn!(
+                "{} octree leaves have more items than the capacity {:?}",
+                nr_over_capacity, limit
+            );
+        }
+        octree
+    }
+
+    fn from_grid(
+        grid: &SquareGrid,
+        features: &FeatureSet,
+        transform: &crate::parser::Transform,
+        limit: QuadTreeCapacity,
+        max_depth: Option<u16>,
+    ) -> Self {
+        let feature_z: Vec<f64> = features
+            .iter()
+            .map(|f| {
+                let bbox = f.bbox_qc.to_bbox(transform, None, None);
+                (bbox[2] + bbox[5]) * 0.5
+            })
+            .collect();
+        let mut root_features: HashSet<usize> = HashSet::new();
+        for (_, cell) in grid {
+            root_features.extend(cell.feature_ids.iter());
+        }
+        Self::split(
+            grid,
+            features,
+            &feature_z,
+            &limit,
+            max_depth.unwrap_or(u16::MAX),
+            OctreeNodeId::new(0, 0, 0, 0),
+            [grid.length, grid.length],
+            [grid.bbox[2], grid.bbox[5]],
+            root_features,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn split(
+        grid: &SquareGrid,
+        features: &FeatureSet,
+        feature_z: &[f64],
+        limit: &QuadTreeCapacity,
+        max_depth: u16,
+        id: OctreeNodeId,
+        nr_cells: [usize; 2],
+        z_range: [f64; 2],
+        node_features: HashSet<usize>,
+    ) -> Self {
+        let mut feature_ids: Vec<usize> = node_features.into_iter().collect();
+        feature_ids.sort();
+        let nr_items: usize = match limit {
+            QuadTreeCapacity::Objects(_) => feature_ids.len(),
+            QuadTreeCapacity::Vertices(_) | QuadTreeCapacity::ObjectsAndVertices { .. } => {
+                feature_ids
+                    .iter()
+                    .map(|fid| features[*fid].nr_vertices as usize)
+                    .sum()
+            }
+            QuadTreeCapacity::Bytes(_) => feature_ids
+                .iter()
+                .map(|fid| features[*fid].nr_bytes as usize)
+                .sum(),
+        };
+        if limit.fits(nr_items, feature_ids.len())
+            || (nr_cells[0] <= 1 && nr_cells[1] <= 1)
+            || id.level >= max_depth
+        {
+            return Octree {
+                id,
+                nr_cells,
+                z_range,
+                children: vec![],
+                feature_ids,
+                nr_items,
+            };
+        }
+        let node_features: HashSet<usize> = feature_ids.iter().copied().collect();
+        let half_x = nr_cells[0].div_ceil(2);
+        let half_y = nr_cells[1].div_ceil(2);
+        let mid_z = (z_range[0] + z_range[1]) * 0.5;
+        let mut children: Vec<Octree> = Vec::with_capacity(8);
+        for dz in 0..2_usize {
+            let child_z_range = if dz == 0 {
+                [z_range[0], mid_z]
+            } else {
+                [mid_z, z_range[1]]
+            };
+            for (dy, child_nr_y) in [(0, half_y), (half_y, nr_cells[1] - half_y)] {
+                for (dx, child_nr_x) in [(0, half_x), (half_x, nr_cells[0] - half_x)] {
+                    if child_nr_x == 0 || child_nr_y == 0 {
+                        continue;
+                    }
+                    let mut child_features: HashSet<usize> = HashSet::new();
+                    for row in id.y + dy..id.y + dy + child_nr_y {
+                        for column in id.x + dx..id.x + dx + child_nr_x {
+                            let cell = grid.cell(&CellId { row, column });
+                            child_features.extend(cell.feature_ids.iter().filter(|fid| {
+                                node_features.contains(fid)
+                                    && (feature_z[**fid] < mid_z) == (dz == 0)
+                            }));
+                        }
+                    }
+                    if child_features.is_empty() {
+                        continue;
+                    }
+                    children.push(Self::split(
+                        grid,
+                        features,
+                        feature_z,
+                        limit,
+                        max_depth,
+                        OctreeNodeId::new(id.x + dx, id.y + dy, id.z * 2 + dz, id.level + 1),
+                        [child_nr_x, child_nr_y],
+                        child_z_range,
+                        child_features,
+                    ));
+                }
+            }
+        }
+        Octree {
+            id,
+            nr_cells,
+            z_range,
+            children,
+            feature_ids,
+            nr_items,
+        }
+    }
+
+    fn collect_leaves_recurse<'collect>(&'collect self, leaves: &mut Vec<&'collect Octree>) {
+        if !self.children.is_empty() {
+            for child in self.children.iter() {
+                child.collect_leaves_recurse(leaves);
+            }
+        } else {
+            leaves.push(self);
+        }
+    }
+
+    pub fn collect_leaves(&self) -> Vec<&Self> {
+        let mut leaves: Vec<&Octree> = Vec::new();
+        self.collect_leaves_recurse(&mut leaves);
+        leaves
+    }
+
+    pub fn bbox(&self, grid: &SquareGrid) -> Bbox {
+        let minx = grid.origin[0] + (self.id.x * grid.cellsize[0] as usize) as f64;
+        let miny = grid.origin[1] + (self.id.y * grid.cellsize[1] as usize) as f64;
+        [
+            minx,
+            miny,
+            self.z_range[0],
+            minx + (self.nr_cells[0] * grid.cellsize[0] as usize) as f64,
+            miny + (self.nr_cells[1] * grid.cellsize[1] as usize) as f64,
+            self.z_range[1],
+        ]
+    }
+
+    /// Breadth-first search for a node.
+    pub fn node(&self, id: &OctreeNodeId) -> Option<&Octree> {
+        let mut q = VecDeque::new();
+        q.push_back(self);
+
+        while let Some(n) = q.pop_front() {
+            if &n.id == id {
+                return Some(n);
+            } else {
+                for child in &n.children {
+                    q.push_back(child);
+                }
+            }
+        }
+        // Did not find the node
+        None
+    }
+
+    /// The features of the node. Unlike with the [QuadTree], these are not all the
+    /// features of the cells of the node, only the ones in the z-range of the node.
+    pub fn feature_ids(&self) -> &[usize] {
+        &self.feature_ids
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OctreeNodeId {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub level: u16,
+}
+
+impl OctreeNodeId {
+    pub fn new(x: usize, y: usize, z: usize, level: u16) -> Self {
+        Self { x, y, z, level }
+    }
+}
+
+impl Display for OctreeNodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}/{}", self.level, self.x, self.y, self.z)
+    }
+}
+
+/// The number of cells of the first part, when the `items` of the columns (or rows) of a
+/// node are split at their weighted median. Both parts have at least one column, and an
+/// empty node is split at its center.
+fn median_split(items: &[usize]) -> usize {
+    let total: usize = items.iter().sum();
+    if items.len() <= 1 {
+        return items.len();
+    }
+    if total == 0 {
+        return items.len().div_ceil(2);
+    }
+    let mut cumulative = 0;
+    let split = items
+        .iter()
+        .position(|n| {
+            cumulative += n;
+            cumulative * 2 >= total
+        })
+        .unwrap_or(0)
+        + 1;
+    split.clamp(1, items.len() - 1)
+}
+
+/// Where a [QuadTree] node is split into its children.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum QuadTreeSplit {
+    /// At the center of the node, so that the nodes are squares of 2^n cells.
+    #[default]
+    Center,
+    /// At the weighted median of the items of the node, see [QuadTree::from_grid_median].
+    Median,
+}
+
+/// The spatial index that is used for creating the tiles.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum SpatialIndex {
+    #[default]
+    Quadtree,
+    Octree,
+}
+
+/// The formats of the grid that is written with `--grid-export`, see [SquareGrid::to_geojson]
+/// and [SquareGrid::to_csv].
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum, Eq, PartialEq)]
+pub enum GridExportFormat {
+    #[default]
+    Geojson,
+    Csv,
+    Both,
+}
+
+impl GridExportFormat {
+    /// Write the grid into `dir` as `grid.geojson` and/or `grid.csv`.
+    pub fn export(&self, grid: &SquareGrid, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if matches!(self, Self::Geojson | Self::Both) {
+            let file_out = File::create(dir.join("grid.geojson"))?;
+            serde_json::to_writer(&file_out, &grid.to_geojson())?;
+        }
+        if matches!(self, Self::Csv | Self::Both) {
+            std::fs::write(dir.join("grid.csv"), grid.to_csv())?;
+        }
+        Ok(())
+    }
+}
+
 /// We have these double enum, QuadTreeCapacity and QuadTreeCapacityType, because of
 /// how the CLI arguments are parsed. In the quadtree, we need QuadTreeCapacity, because
 /// it can hold both the leaf capacity and the capacity type. But clap can only parse
 /// into unit variants (I think), so we take the the capacity and capacity type as
 /// separate arguments.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum QuadTreeCapacity {
     Objects(usize),
     Vertices(usize),
+    /// The estimated size of the features in bytes, see [Cell::nr_bytes].
+    Bytes(usize),
+    /// A leaf can have at most `objects` features and `vertices` vertices, so that a few
+    /// features with many vertices do not make a large leaf, and neither do many small
+    /// features. The items of a node are its vertices.
+    ObjectsAndVertices {
+        objects: usize,
+        vertices: usize,
+    },
+}
+
+/// The distribution of the items in the leaves of a [QuadTree], see [QuadTree::stats].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuadTreeStats {
+    pub nr_leaves: usize,
+    pub min_items: usize,
+    pub median_items: usize,
+    pub max_items: usize,
+    /// The number of leaves on each level, the index is the level.
+    pub leaves_per_level: Vec<usize>,
+    /// The number of leaves that have more items than the capacity.
+    pub nr_over_capacity: usize,
+    /// The total number of vertices in the leaves, if the capacity is in vertices.
+    pub nr_vertices: Option<usize>,
+}
+
+impl Display for QuadTreeStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} leaves with min {}, median {}, max {} items, {} leaves over the capacity",
+            self.nr_leaves,
+            self.min_items,
+            self.median_items,
+            self.max_items,
+            self.nr_over_capacity
+        )?;
+        if let Some(nr_vertices) = self.nr_vertices {
+            write!(f, ", {} vertices in total", nr_vertices)?;
+        }
+        let levels: Vec<String> = self
+            .leaves_per_level
+            .iter()
+            .enumerate()
+            .filter(|(_, nr)| **nr > 0)
+            .map(|(level, nr)| format!("{}: {}", level, nr))
+            .collect();
+        write!(f, ", leaves per level: {}", levels.join(", "))
+    }
+}
+
+impl QuadTreeCapacity {
+    /// The maximum number of items in a leaf.
+    pub fn capacity(&self) -> usize {
+        match self {
+            QuadTreeCapacity::Objects(l)
+            | QuadTreeCapacity::Vertices(l)
+            | QuadTreeCapacity::Bytes(l) => *l,
+            QuadTreeCapacity::ObjectsAndVertices { vertices, .. } => *vertices,
+        }
+    }
+
+    /// The same type of capacity, with a different maximum number of items. For
+    /// [QuadTreeCapacity::ObjectsAndVertices], this is the number of vertices.
+    pub fn with_capacity(&self, capacity: usize) -> Self {
+        match self {
+            QuadTreeCapacity::Objects(_) => QuadTreeCapacity::Objects(capacity),
+            QuadTreeCapacity::Vertices(_) => QuadTreeCapacity::Vertices(capacity),
+            QuadTreeCapacity::Bytes(_) => QuadTreeCapacity::Bytes(capacity),
+            QuadTreeCapacity::ObjectsAndVertices { objects, .. } => {
+                QuadTreeCapacity::ObjectsAndVertices {
+                    objects: *objects,
+                    vertices: capacity,
+                }
+            }
+        }
+    }
+
+    /// Can a node with `nr_items` items and `nr_objects` features be a leaf?
+    pub fn fits(&self, nr_items: usize, nr_objects: usize) -> bool {
+        match self {
+            QuadTreeCapacity::ObjectsAndVertices { objects, vertices } => {
+                nr_items <= *vertices && nr_objects <= *objects
+            }
+            _ => nr_items <= self.capacity(),
+        }
+    }
 }
 
 /// The type of items to count for the quadtree leaf capacity.
@@ -281,6 +1080,9 @@ pub enum QuadTreeCriteria {
     Objects,
     #[default]
     Vertices,
+    Bytes,
+    /// The number of vertices, and the number of features with --qtree-capacity-objects.
+    ObjectsAndVertices,
 }
 
 /// 64-bit mask
@@ -362,17 +1164,20 @@ pub fn deinterleave(mortoncode: &u64) -> [u64; 2] {
 /// ## Examples
 ///
 /// ```
-/// let grid = SquareGrid::new(&[0.0, 0.0, 0.0, 4.0, 4.0, 4.0], 1);
-/// let grid_idx = grid.locate_point(&[2.5, 1.5]);
-/// assert_eq!(grid_idx, [3_u64, 2_u64]);
+/// use tyler::spatial_structs::{CellId, SquareGrid};
+///
+/// let grid = SquareGrid::new(&[0.0, 0.0, 0.0, 4.0, 4.0, 4.0], 1, 7415, None);
+/// let cellid = grid.locate_point(&[2.5, 1.5]);
+/// assert_eq!(cellid, CellId { row: 1, column: 2 });
 /// ```
 ///
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SquareGrid {
     origin: [f64; 3],
     pub bbox: Bbox,
     pub length: usize,
-    cellsize: u16,
+    /// The size of a cell along the x and y axes.
+    pub cellsize: [u16; 2],
     pub data: Vec<Vec<Cell>>,
     pub epsg: u16,
 }
@@ -381,18 +1186,31 @@ impl Display for SquareGrid {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "SquareGrid (origin: {:?}, bbox: {:?}, length: {}, cellsize: {}, data: not-displayed, epsg: {})",
+            "SquareGrid (origin: {:?}, bbox: {:?}, length: {}, cellsize: {:?}, data: not-displayed, epsg: {})",
             self.origin, self.bbox, self.length, self.cellsize, self.epsg
         )
     }
 }
 
 impl SquareGrid {
-    /// Creates a grid with cells of `cellsize`, that covers the `extent`.
-    /// The grid and the cells are square.
+    /// Creates a grid with square cells of `cellsize`, that covers the `extent`.
+    /// The grid is square.
     /// The grid origin is the `extent` origin.
     /// The grid is returned as an origin coordinate and the number of cells.
     pub fn new(extent: &Bbox, cellsize: u16, epsg: u16, buffer: Option<f64>) -> Self {
+        Self::new_rectangular(extent, [cellsize, cellsize], epsg, buffer)
+    }
+
+    /// Same as [SquareGrid::new], but the cells are `cellsize[0]` wide along the x axis and
+    /// `cellsize[1]` along the y axis. The grid has the same number of cells along both
+    /// axes, so that the quadtree can be built on it, thus the grid is square in cells,
+    /// but not in real-world units.
+    pub fn new_rectangular(
+        extent: &Bbox,
+        cellsize: [u16; 2],
+        epsg: u16,
+        buffer: Option<f64>,
+    ) -> Self {
         // Add some buffer to the extent, to make sure all points will be within the grid.
         let buffer: f64 = buffer.unwrap_or(0.0);
         // Add the buffer to the computed extent
@@ -406,25 +1224,25 @@ impl SquareGrid {
         ];
         let dx = extent_with_buffer[3] - extent_with_buffer[0];
         let dy = extent_with_buffer[4] - extent_with_buffer[1];
-        // The grid dimension is the longest edge of the rectangle, so we get a square
-        let mut d = if dx > dy { dx } else { dy };
+        // The grid dimension is the edge of the rectangle with the most cells, so we get
+        // a grid that is square in cells
+        let d = (dx / cellsize[0] as f64).max(dy / cellsize[1] as f64);
         // We need a grid that is has 2^n cells in one dimension, so that we can build
         // a 4^n cells quadtree.
-        let d_cells = 2_usize.pow((d / cellsize as f64).log2().ceil() as u32);
+        let d_cells = 2_usize.pow(d.log2().ceil() as u32);
         debug!("Computed grid cells dimension: {}", &d_cells);
         let origin = [
             extent_with_buffer[0],
             extent_with_buffer[1],
             extent_with_buffer[2],
         ];
-        // Compute new dimension from the calculated length
-        d = d_cells as f64 * cellsize as f64;
+        // Compute new dimensions from the calculated length
         let bbox = [
             origin[0],
             origin[1],
             origin[2],
-            origin[0] + d,
-            origin[1] + d,
+            origin[0] + d_cells as f64 * cellsize[0] as f64,
+            origin[1] + d_cells as f64 * cellsize[1] as f64,
             extent_with_buffer[5],
         ];
         // A row-vector (x-axis) to store the column-vectors (y-axis).
@@ -438,6 +1256,7 @@ impl SquareGrid {
                 Cell {
                     feature_ids: Vec::new(),
                     nr_vertices: 0,
+                    nr_bytes: 0,
                 },
             );
             column
@@ -456,14 +1275,22 @@ impl SquareGrid {
     pub fn locate_point(&self, point: &[f64; 2]) -> CellId {
         let dx = point[0] - self.origin[0];
         let dy = point[1] - self.origin[1];
-        let col_i = (dx / self.cellsize as f64).floor() as usize;
-        let row_i = (dy / self.cellsize as f64).floor() as usize;
+        let col_i = (dx / self.cellsize[0] as f64).floor() as usize;
+        let row_i = (dy / self.cellsize[1] as f64).floor() as usize;
         CellId {
             row: row_i,
             column: col_i,
         }
     }
 
+    /// Is the point within the grid?
+    pub fn contains_point(&self, point: &[f64; 2]) -> bool {
+        point[0] >= self.bbox[0]
+            && point[0] < self.bbox[3]
+            && point[1] >= self.bbox[1]
+            && point[1] < self.bbox[4]
+    }
+
     /// Insert a point into the grid, by assigning it to the Cell where it is located.
     pub fn insert(&mut self, point: &[f64; 2], feature_id: usize) -> CellId {
         let cell_id = self.locate_point(point);
@@ -473,13 +1300,16 @@ impl SquareGrid {
     }
 
     /// Return the Cells that intersect the Bounding Box.
+    /// The part of the Bounding Box that is outside of the grid is ignored.
     pub fn intersect_bbox(&self, bbox: &Bbox) -> Vec<CellId> {
         let mut cellids: Vec<CellId> = Vec::new();
         let [minx, miny, _, maxx, maxy, _] = *bbox;
         let min_cellid = self.locate_point(&[minx, miny]);
         let max_cellid = self.locate_point(&[maxx, maxy]);
-        for column in min_cellid.column..=max_cellid.column {
-            for row in min_cellid.row..=max_cellid.row {
+        let max_column = max_cellid.column.min(self.length - 1);
+        let max_row = max_cellid.row.min(self.length - 1);
+        for column in min_cellid.column..=max_column {
+            for row in min_cellid.row..=max_row {
                 cellids.push(CellId { row, column });
             }
         }
@@ -538,24 +1368,78 @@ impl SquareGrid {
         Ok(())
     }
 
+    /// The cells of the grid as a GeoJSON FeatureCollection of polygons, in the CRS of the
+    /// grid. Each cell has the properties `cell_id`, `nr_features` and `nr_vertices`.
+    pub fn to_geojson(&self) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self
+            .into_iter()
+            .map(|(cellid, cell)| {
+                let [minx, miny, _, maxx, maxy, _] = self.cell_bbox(&cellid);
+                serde_json::json!({
+                    "type": "Feature",
+                    "properties": {
+                        "cell_id": cellid.to_string(),
+                        "nr_features": cell.feature_ids.len(),
+                        "nr_vertices": cell.nr_vertices,
+                    },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [minx, miny], [maxx, miny], [maxx, maxy], [minx, maxy], [minx, miny]
+                        ]],
+                    },
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "type": "FeatureCollection",
+            "crs": {
+                "type": "name",
+                "properties": {"name": format!("urn:ogc:def:crs:EPSG::{}", self.epsg)},
+            },
+            "features": features,
+        })
+    }
+
+    /// The cells of the grid as CSV, with a header and one row per cell with the cell ID,
+    /// the centroid, the number of features and the 2D bbox of the cell.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("cell_id,centroid_x,centroid_y,nr_features,minx,miny,maxx,maxy\n");
+        for (cellid, cell) in self {
+            let [minx, miny, _, maxx, maxy, _] = self.cell_bbox(&cellid);
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                cellid,
+                (minx + maxx) / 2.0,
+                (miny + maxy) / 2.0,
+                cell.feature_ids.len(),
+                minx,
+                miny,
+                maxx,
+                maxy
+            ));
+        }
+        csv
+    }
+
     pub fn cell_to_wkt(&self, cellid: &CellId) -> String {
-        let minx = self.origin[0] + (cellid.column * self.cellsize as usize) as f64;
-        let miny = self.origin[1] + (cellid.row * self.cellsize as usize) as f64;
+        let [minx, miny, _, maxx, maxy, _] = self.cell_bbox(cellid);
         format!(
             "POLYGON(({minx} {miny}, {maxx} {miny}, {maxx} {maxy}, {minx} {maxy}, {minx} {miny}))",
             minx = minx,
             miny = miny,
-            maxx = minx + self.cellsize as f64,
-            maxy = miny + self.cellsize as f64
+            maxx = maxx,
+            maxy = maxy
         )
     }
 
     pub fn cell_bbox(&self, cellid: &CellId) -> Bbox {
-        let minx = self.origin[0] + (cellid.column * self.cellsize as usize) as f64;
-        let miny = self.origin[1] + (cellid.row * self.cellsize as usize) as f64;
+        let minx = self.origin[0] + (cellid.column * self.cellsize[0] as usize) as f64;
+        let miny = self.origin[1] + (cellid.row * self.cellsize[1] as usize) as f64;
         let minz = self.bbox[2];
-        let maxx = minx + self.cellsize as f64;
-        let maxy = miny + self.cellsize as f64;
+        let maxx = minx + self.cellsize[0] as f64;
+        let maxy = miny + self.cellsize[1] as f64;
         let maxz = self.bbox[5];
         [minx, miny, minz, maxx, maxy, maxz]
     }
@@ -616,10 +1500,13 @@ impl<'squaregrid> Iterator for SquareGridIterator<'squaregrid> {
     }
 }
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Cell {
     pub feature_ids: Vec<usize>,
     pub nr_vertices: usize,
+    /// The estimated serialized size of the features of the cell, which is the sum of the
+    /// size of their files.
+    pub nr_bytes: usize,
 }
 
 /// Grid cell identifier.
@@ -658,7 +1545,7 @@ pub type Bbox = [f64; 6];
 /// 3D bounding box with quantized coordinates.
 ///
 /// [min x, min y, min z, max x, max y, max z]
-#[derive(Debug, Default, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct BboxQc(pub [i64; 6]); // This `pub [i64; 6]` makes the BboxQc constructor public
 
 impl BboxQc {
@@ -710,20 +1597,13 @@ impl BboxQc {
 
     // Update with another bounding box, if the other is larger.
     pub fn update_with(&mut self, bbox_qc: &Self) {
-        if bbox_qc.0[0] < self.0[0] {
-            self.0[0] = bbox_qc.0[0]
-        } else if bbox_qc.0[3] > self.0[3] {
-            self.0[3] = bbox_qc.0[3]
-        }
-        if bbox_qc.0[1] < self.0[1] {
-            self.0[1] = bbox_qc.0[1]
-        } else if bbox_qc.0[4] > self.0[4] {
-            self.0[4] = bbox_qc.0[4]
-        }
-        if bbox_qc.0[2] < self.0[2] {
-            self.0[2] = bbox_qc.0[2]
-        } else if bbox_qc.0[5] > self.0[5] {
-            self.0[5] = bbox_qc.0[5]
+        for i in 0..3 {
+            if bbox_qc.0[i] < self.0[i] {
+                self.0[i] = bbox_qc.0[i]
+            }
+            if bbox_qc.0[i + 3] > self.0[i + 3] {
+                self.0[i + 3] = bbox_qc.0[i + 3]
+            }
         }
     }
 }
@@ -733,6 +1613,13 @@ mod tests {
     use super::*;
     use morton_encoding::morton_encode;
 
+    #[test]
+    fn test_bboxqc_update_with() {
+        let mut bbox_qc = BboxQc([0, 0, 0, 10, 10, 10]);
+        bbox_qc.update_with(&BboxQc([-5, 2, -1, 20, 8, 30]));
+        assert_eq!(bbox_qc.0, [-5, 0, -1, 20, 10, 30]);
+    }
+
     #[test]
     fn test_intersect_bbox() {
         let extent = [195548.0, 538909.0, 0.0, 264268.0, 590410.0, 0.0];
@@ -794,6 +1681,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rectangular_grid() {
+        // A strip that is 8 cells wide and 2 cells tall
+        let grid =
+            SquareGrid::new_rectangular(&[0.0, 0.0, 0.0, 16.0, 2.0, 1.0], [2, 1], 0, Some(0.0));
+        assert_eq!(grid.length, 8);
+        assert_eq!(grid.bbox, [0.0, 0.0, 0.0, 16.0, 8.0, 1.0]);
+        let cellid = grid.locate_point(&[5.5, 1.5]);
+        assert_eq!(cellid, CellId { row: 1, column: 2 });
+        assert_eq!(grid.cell_bbox(&cellid), [4.0, 1.0, 0.0, 6.0, 2.0, 1.0]);
+    }
+
     #[test]
     fn test_morton_encode_rd() {
         let coords = vec![
@@ -947,6 +1846,22 @@ mod tests {
         assert_eq!(cells_morton, expected)
     }
 
+    #[test]
+    fn test_grid_export_formats() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 2.0, 2.0, 1.0], 1, 7415, Some(0.0));
+        grid.insert(&[1.5, 0.5], 0);
+        let csv = grid.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), grid.length * grid.length + 1);
+        assert!(rows.contains(&"1-0,1.5,0.5,1,1,0,2,1"));
+        let geojson = grid.to_geojson();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), grid.length * grid.length);
+        assert!(features
+            .iter()
+            .any(|f| f["properties"]["cell_id"] == "1-0" && f["properties"]["nr_features"] == 1));
+    }
+
     #[test]
     fn test_quadtree_construction() {
         let mut feature_set: FeatureSet = Vec::new();
@@ -957,15 +1872,17 @@ mod tests {
                     feature_set.push(crate::parser::Feature {
                         centroid_qc: [0, 0],
                         nr_vertices: 0,
+                        nr_bytes: 0,
                         path_jsonl: Default::default(),
                         bbox_qc: BboxQc([0, 0, 0, 0, 0, 0]),
+                        has_building: false,
                     });
                     let xc: f64 = format!("{}.{}", &x, &f).parse().unwrap();
                     grid.insert(&[xc, y as f64], f as usize);
                 }
             }
         }
-        let _ = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(20));
+        let _ = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(20), None);
     }
 
     #[test]
@@ -978,21 +1895,154 @@ mod tests {
                     feature_set.push(crate::parser::Feature {
                         centroid_qc: [0, 0],
                         nr_vertices: 0,
+                        nr_bytes: 0,
                         path_jsonl: Default::default(),
                         bbox_qc: BboxQc([0, 0, 0, 0, 0, 0]),
+                        has_building: false,
                     });
                     let xc: f64 = format!("{}.{}", &x, &f).parse().unwrap();
                     grid.insert(&[xc, y as f64], f as usize);
                 }
             }
         }
-        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(20));
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(20), None);
         let leaves: Vec<&QuadTree> = QuadTree::collect_leaves(&qtree);
         for tile in leaves {
             println!("{}", tile.id);
         }
     }
 
+    #[test]
+    fn test_quadtree_max_depth() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 16.0, 16.0, 1.0], 1, 0, None);
+        for x in 0..16 {
+            for y in 0..16 {
+                grid.insert(&[x as f64 + 0.5, y as f64 + 0.5], x * 16 + y);
+            }
+        }
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(1), None);
+        assert_eq!(qtree.collect_leaves().len(), 256);
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(1), Some(1));
+        let leaves = qtree.collect_leaves();
+        assert_eq!(leaves.len(), 4);
+        assert!(leaves
+            .iter()
+            .all(|leaf| leaf.id.level == 1 && leaf.cells.len() == 64 && leaf.nr_items == 64));
+    }
+
+    #[test]
+    fn test_quadtree_stats() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 16.0, 16.0, 1.0], 1, 0, None);
+        for x in 0..16 {
+            for y in 0..16 {
+                grid.insert(&[x as f64 + 0.5, y as f64 + 0.5], x * 16 + y);
+            }
+        }
+        let limit = QuadTreeCapacity::Objects(1);
+        let stats = QuadTree::from_grid(&grid, limit, Some(1)).stats(limit);
+        assert_eq!(stats.nr_leaves, 4);
+        assert_eq!(
+            (stats.min_items, stats.median_items, stats.max_items),
+            (64, 64, 64)
+        );
+        assert_eq!(stats.leaves_per_level, vec![0, 4]);
+        assert_eq!(stats.nr_over_capacity, 4);
+        assert_eq!(stats.nr_vertices, None);
+    }
+
+    #[test]
+    fn test_quadtree_node_id() {
+        let id = QuadTreeNodeId::new(5, 6, 3);
+        assert_eq!(id.ancestor(2, 3), QuadTreeNodeId::new(4, 6, 2));
+        assert_eq!(id.ancestor(1, 3), QuadTreeNodeId::new(4, 4, 1));
+        assert_eq!(id.ancestor(0, 3), QuadTreeNodeId::new(0, 0, 0));
+        assert_eq!(id.quadrant(3, 3), 1);
+        assert_eq!(id.quadrant(2, 3), 2);
+        // The IDs of the nodes do not depend on the number of features in the other nodes
+        let ids = |nr_features: usize| -> Vec<String> {
+            let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 8.0, 8.0, 1.0], 1, 0, None);
+            for f in 0..nr_features {
+                grid.insert(&[(f % 8) as f64 + 0.5, 0.5], f);
+            }
+            grid.insert(&[6.5, 6.5], nr_features);
+            let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(1), None);
+            let mut ids: Vec<String> = qtree
+                .collect_leaves()
+                .iter()
+                .filter(|leaf| leaf.nr_items > 0)
+                .map(|leaf| leaf.id.to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert!(ids(1).contains(&String::from("1/4/4")));
+        assert!(ids(12).contains(&String::from("1/4/4")));
+        for ids in [ids(1), ids(12)] {
+            for id in ids {
+                let [level, x, y]: [usize; 3] = id
+                    .split('/')
+                    .map(|v| v.parse().unwrap())
+                    .collect::<Vec<usize>>()
+                    .try_into()
+                    .unwrap();
+                let side = 1 << (3 - level);
+                assert_eq!((x % side, y % side), (0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_median_split() {
+        assert_eq!(median_split(&[10, 0, 0, 0]), 1);
+        assert_eq!(median_split(&[0, 0, 5, 5]), 3);
+        assert_eq!(median_split(&[0, 0, 0, 10]), 3);
+        assert_eq!(median_split(&[0, 0, 0]), 2);
+        assert_eq!(median_split(&[7]), 1);
+    }
+
+    #[test]
+    fn test_quadtree_median() {
+        // The features are clustered in the lower-left 2x2 cells of an 8x8 grid
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 8.0, 8.0, 1.0], 1, 0, None);
+        for f in 0..16 {
+            grid.insert(&[0.5 + (f % 2) as f64, 0.5 + (f / 8) as f64], f);
+        }
+        grid.insert(&[7.5, 7.5], 16);
+        let limit = QuadTreeCapacity::Objects(5);
+        let center = QuadTree::from_grid(&grid, limit, None);
+        let median = QuadTree::from_grid_median(&grid, limit, None);
+        assert_eq!(median.nr_items, 17);
+        let leaves_non_empty = |qtree: &QuadTree| {
+            qtree
+                .collect_leaves()
+                .iter()
+                .filter(|leaf| leaf.nr_items > 0)
+                .count()
+        };
+        assert!(leaves_non_empty(&median) <= leaves_non_empty(&center));
+        // The leaves cover all the cells with items exactly once, and the bbox of a leaf
+        // contains its cells
+        let mut cells: Vec<CellId> = Vec::new();
+        for leaf in median.collect_leaves() {
+            let bbox = leaf.bbox(&grid);
+            for cell in leaf.cells.iter() {
+                assert!(bbox[0] <= cell.column as f64 && (cell.column as f64) < bbox[3]);
+                assert!(bbox[1] <= cell.row as f64 && (cell.row as f64) < bbox[4]);
+            }
+            cells.extend(leaf.cells.iter().copied());
+        }
+        let nr_cells = cells.len();
+        cells.sort_by_key(|cell| (cell.row, cell.column));
+        cells.dedup();
+        assert_eq!(cells.len(), nr_cells);
+        let nr_items: usize = cells
+            .iter()
+            .map(|cell| grid.cell(cell).feature_ids.len())
+            .sum();
+        assert_eq!(nr_items, 17);
+        assert!(median.node(&QuadTreeNodeId::new(0, 0, 0)).is_some());
+    }
+
     #[test]
     fn test_quadtree_node() {
         let mut feature_set: FeatureSet = Vec::new();
@@ -1003,15 +2053,17 @@ mod tests {
                     feature_set.push(crate::parser::Feature {
                         centroid_qc: [0, 0],
                         nr_vertices: 0,
+                        nr_bytes: 0,
                         path_jsonl: Default::default(),
                         bbox_qc: BboxQc([0, 0, 0, 0, 0, 0]),
+                        has_building: false,
                     });
                     let xc: f64 = format!("{}.{}", &x, &f).parse().unwrap();
                     grid.insert(&[xc, y as f64], f as usize);
                 }
             }
         }
-        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(20));
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(20), None);
         let leaves: Vec<&QuadTree> = QuadTree::collect_leaves(&qtree);
         let n = qtree.node(&QuadTreeNodeId::new(0, 0, 2));
         if let Some(_) = n {
@@ -1020,4 +2072,176 @@ mod tests {
             println!("did not find node");
         }
     }
+
+    #[test]
+    fn test_octree_split_z() {
+        let transform = crate::parser::Transform {
+            scale: [1.0, 1.0, 1.0],
+            translate: [0.0, 0.0, 0.0],
+        };
+        let mut feature_set: FeatureSet = Vec::new();
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 4.0, 4.0, 10.0], 1, 0, None);
+        // 10 features stacked in a single cell, half of them near the bottom and half near
+        // the top of the grid.
+        for f in 0..10_i64 {
+            let z = if f < 5 { 1 } else { 9 };
+            feature_set.push(crate::parser::Feature {
+                centroid_qc: [0, 0],
+                nr_vertices: 0,
+                nr_bytes: 0,
+                path_jsonl: Default::default(),
+                bbox_qc: BboxQc([0, 0, z, 1, 1, z]),
+                has_building: false,
+            });
+            grid.insert(&[0.5, 0.5], f as usize);
+        }
+        let octree = Octree::from_grid(
+            &grid,
+            &feature_set,
+            &transform,
+            QuadTreeCapacity::Objects(5),
+            None,
+        );
+        let leaves = octree.collect_leaves();
+        assert!(leaves.iter().all(|leaf| leaf.nr_items <= 5));
+        assert_eq!(leaves.iter().map(|leaf| leaf.nr_items).sum::<usize>(), 10);
+        let z_ids: HashSet<usize> = leaves.iter().map(|leaf| leaf.id.z).collect();
+        assert!(z_ids.len() > 1);
+        for leaf in leaves {
+            let bbox = leaf.bbox(&grid);
+            assert!(bbox[5] > bbox[2]);
+            assert_eq!(octree.node(&leaf.id).unwrap().nr_items, leaf.nr_items);
+        }
+    }
+
+    #[test]
+    fn test_quadtree_bytes() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 4.0, 4.0, 1.0], 1, 0, None);
+        for x in 0..4_u64 {
+            for y in 0..4u64 {
+                let cellid = grid.insert(&[x as f64 + 0.5, y as f64 + 0.5], 0);
+                grid.cell_mut(&cellid).nr_bytes = 100;
+            }
+        }
+        // Each leaf can hold the size of 4 cells
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Bytes(400), None);
+        let leaves = qtree.collect_leaves();
+        assert_eq!(leaves.len(), 4);
+        assert!(leaves.iter().all(|leaf| leaf.nr_items == 400));
+    }
+
+    #[test]
+    fn test_quadtree_objects_and_vertices() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 4.0, 4.0, 1.0], 1, 0, None);
+        for x in 0..4_u64 {
+            for y in 0..4u64 {
+                let cellid = grid.insert(&[x as f64 + 0.5, y as f64 + 0.5], 0);
+                grid.cell_mut(&cellid).nr_vertices = 10;
+            }
+        }
+        // A single feature with many vertices in the lower-left cell
+        let cellid = grid.insert(&[0.5, 0.5], 1);
+        grid.cell_mut(&cellid).nr_vertices = 1000;
+        // By the number of objects, the root is a leaf
+        let limit = QuadTreeCapacity::ObjectsAndVertices {
+            objects: 17,
+            vertices: 100,
+        };
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(17), None);
+        assert!(qtree.children.is_empty());
+        // but its vertices are over the capacity, so it is split down to the single cell
+        let qtree = QuadTree::from_grid(&grid, limit, None);
+        let leaves = qtree.collect_leaves();
+        assert_eq!(leaves.len(), 7);
+        assert_eq!(qtree.stats(limit).nr_over_capacity, 1);
+        // The vertices fit, but there are too many objects
+        let limit = QuadTreeCapacity::ObjectsAndVertices {
+            objects: 4,
+            vertices: 10000,
+        };
+        let leaves_objects = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(4), None)
+            .collect_leaves()
+            .len();
+        let qtree = QuadTree::from_grid(&grid, limit, None);
+        assert_eq!(qtree.collect_leaves().len(), leaves_objects);
+        let qtree = QuadTree::from_grid_median(&grid, limit, None);
+        assert!(qtree
+            .collect_leaves()
+            .iter()
+            .all(|leaf| leaf.nr_objects <= 4));
+    }
+
+    #[test]
+    fn test_tune_capacity() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 16.0, 16.0, 1.0], 1, 0, None);
+        let mut fid = 0;
+        for x in 0..16_u64 {
+            for y in 0..16_u64 {
+                grid.insert(&[x as f64 + 0.5, y as f64 + 0.5], fid);
+                fid += 1;
+            }
+        }
+        // One feature per cell, so 16 leaves of 4x4 cells
+        let capacity = QuadTree::tune_capacity(
+            &grid,
+            QuadTreeCapacity::Objects(1),
+            None,
+            QuadTreeSplit::Center,
+            16,
+            0.0,
+            20,
+        );
+        let nr_leaves = QuadTree::from_grid(&grid, capacity, None)
+            .collect_leaves()
+            .len();
+        assert_eq!(nr_leaves, 16);
+        // The closest number of leaves to an unreachable target
+        let capacity = QuadTree::tune_capacity(
+            &grid,
+            QuadTreeCapacity::Objects(1),
+            None,
+            QuadTreeSplit::Center,
+            10,
+            0.0,
+            20,
+        );
+        let nr_leaves = QuadTree::from_grid(&grid, capacity, None)
+            .collect_leaves()
+            .len();
+        assert!([4, 16].contains(&nr_leaves));
+        // With a single iteration, the first trial is returned
+        let capacity = QuadTree::tune_capacity(
+            &grid,
+            QuadTreeCapacity::Objects(1),
+            None,
+            QuadTreeSplit::Center,
+            16,
+            0.0,
+            1,
+        );
+        assert!(matches!(capacity, QuadTreeCapacity::Objects(128)));
+    }
+
+    #[test]
+    fn test_quadtree_geojson() {
+        let mut grid = SquareGrid::new(&[0.0, 0.0, 0.0, 4.0, 4.0, 1.0], 1, 7415, None);
+        for x in 0..4_u64 {
+            for y in 0..4u64 {
+                grid.insert(&[x as f64 + 0.5, y as f64 + 0.5], 0);
+            }
+        }
+        let qtree = QuadTree::from_grid(&grid, QuadTreeCapacity::Objects(4), None);
+        let geojson = qtree.to_geojson(&grid);
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), qtree.collect_leaves().len());
+        assert_eq!(
+            geojson["crs"]["properties"]["name"],
+            "urn:ogc:def:crs:EPSG::7415"
+        );
+        let ring = features[0]["geometry"]["coordinates"][0]
+            .as_array()
+            .unwrap();
+        assert_eq!(ring.len(), 5);
+        assert_eq!(ring[0], ring[4]);
+    }
 }
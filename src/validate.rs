@@ -0,0 +1,217 @@
+//! Checking the inputs of a run before the tiling, with the `validate` subcommand.
+// Copyright 2023 Balázs Dukai, Ravi Peters
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+use crate::cli::Cli;
+use crate::parser::{CityJSONMetadata, World};
+
+/// Check the inputs of a run and return the problems that were found. All the checks are
+/// done, so that all problems are reported at once.
+///
+/// The checks are:
+/// - the `--metadata` file can be parsed and its CRS has an EPSG code,
+/// - each feature file of `--features` is readable and not empty,
+/// - the executables that are needed for the run exist and they are executable,
+/// - the geof flowchart exists in the resources directory,
+/// - `--grid-minz` is less than `--grid-maxz`.
+pub fn validate(cli: &Cli, features: &Path) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let features_from_stdin = features == Path::new("-");
+
+    match cli.metadata {
+        Some(ref path_metadata) => match CityJSONMetadata::from_file(path_metadata) {
+            Ok(cm) => match cm.metadata.reference_system {
+                // The --source-crs is already checked when the arguments are parsed
+                _ if cli.source_crs.is_some() => {}
+                Some(ref crs) => {
+                    if let Err(e) = crs.to_epsg() {
+                        problems.push(format!(
+                            "the CRS of the metadata {:?} is not supported: {}",
+                            path_metadata, e
+                        ));
+                    }
+                }
+                None => problems.push(format!(
+                    "the metadata {:?} does not have a referenceSystem, set the CRS with --source-crs",
+                    path_metadata
+                )),
+            },
+            Err(e) => problems.push(format!(
+                "could not parse the metadata {:?}: {}",
+                path_metadata, e
+            )),
+        },
+        None => {
+            if !features_from_stdin && !World::is_cityjsonseq(features) {
+                problems.push("--metadata is required".to_string());
+            }
+        }
+    }
+
+    if features_from_stdin {
+        warn!("The features are read from stdin, so they are not validated");
+    } else {
+        let mut nr_features: usize = 0;
+        let features_extensions = cli.features_extension.clone().unwrap_or_default();
+        for path_feature in World::feature_paths(features, &features_extensions) {
+            nr_features += 1;
+            match File::open(&path_feature).and_then(|f| f.metadata()) {
+                Ok(metadata) => {
+                    if metadata.len() == 0 {
+                        problems.push(format!("the feature file {:?} is empty", &path_feature));
+                    }
+                }
+                Err(e) => problems.push(format!(
+                    "could not read the feature file {:?}: {}",
+                    &path_feature, e
+                )),
+            }
+        }
+        debug!("Validated {} feature files", nr_features);
+        if nr_features == 0 {
+            problems.push(format!("no feature files found in {:?}", &features));
+        }
+    }
+
+    if !cli.cesium3dtiles_tileset_only && !cli.dry_run {
+        check_executable(cli.exe_geof.as_deref(), "geof", "--exe-geof", &mut problems);
+        let path_flowchart = cli.resources_dir().join("geof").join("createGLB.json");
+        if !path_flowchart.is_file() {
+            problems.push(format!(
+                "the geof flowchart {:?} does not exist, set --resources-dir",
+                path_flowchart
+            ));
+        }
+    }
+    if cli.dem.is_some() {
+        check_executable(
+            cli.exe_gdallocationinfo.as_deref(),
+            "gdallocationinfo",
+            "--exe-gdallocationinfo",
+            &mut problems,
+        );
+    }
+
+    if let (Some(minz), Some(maxz)) = (cli.grid_minz, cli.grid_maxz) {
+        if minz >= maxz {
+            problems.push(format!(
+                "--grid-minz ({}) must be less than --grid-maxz ({})",
+                minz, maxz
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Check that the executable `exe` exists and that it is executable. If `exe` is not set,
+/// the `default_name` is looked up in the filesystem PATH.
+fn check_executable(
+    exe: Option<&Path>,
+    default_name: &str,
+    option: &str,
+    problems: &mut Vec<String>,
+) {
+    match exe {
+        Some(path) => {
+            if !is_executable(path) {
+                problems.push(format!("{} {:?} is not an executable file", option, path));
+            }
+        }
+        None => {
+            if find_in_path(default_name).is_none() {
+                problems.push(format!(
+                    "{} is not set and '{}' is not found in the PATH",
+                    option, default_name
+                ));
+            }
+        }
+    }
+}
+
+/// Find an executable in the directories of the PATH environment variable.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path_var = env::var_os("PATH")?;
+    env::split_paths(&path_var)
+        .map(|dir| dir.join(name))
+        .find(|p| is_executable(p))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+
+    #[test]
+    fn test_validate() {
+        let dir = env::temp_dir().join("tyler_test_validate");
+        let dir_features = dir.join("features");
+        fs::create_dir_all(&dir_features).unwrap();
+        let path_data = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("data");
+        fs::copy(
+            path_data.join("3dbag_feature_x71.city.jsonl"),
+            dir_features.join("1.city.jsonl"),
+        )
+        .unwrap();
+        fs::write(dir_features.join("2.city.jsonl"), "").unwrap();
+        let path_not_executable = dir.join("geof");
+        fs::write(&path_not_executable, "").unwrap();
+        let cli = Cli::try_parse_from([
+            "tyler",
+            "--metadata",
+            path_data.join("3dbag_x00.city.json").to_str().unwrap(),
+            "--features",
+            dir_features.to_str().unwrap(),
+            "--output",
+            dir.to_str().unwrap(),
+            "--exe-geof",
+            path_not_executable.to_str().unwrap(),
+            "--resources-dir",
+            dir.to_str().unwrap(),
+            "--grid-minz",
+            "10",
+            "--grid-maxz",
+            "5",
+        ])
+        .unwrap();
+        let problems = validate(&cli, &dir_features);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("2.city.jsonl"));
+        assert!(problems[1].starts_with("--exe-geof"));
+        assert!(problems[2].contains("createGLB.json"));
+        assert!(problems[3].starts_with("--grid-minz"));
+    }
+}